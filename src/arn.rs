@@ -0,0 +1,228 @@
+//! An ARN given instead of the URL (Ex. arn:aws:lambda:us-east-1:123456789012:function:my-fn)
+//!
+//! The ARN is expanded into the URL of the usual action on the resource, such as
+//! Invoke for a Lambda function, with the regional endpoint of its service. The
+//! service and the region of the ARN are used for the signature unless
+//! `--service` and `--region` are given. TARGETS maps the services to their
+//! expansions.
+
+use anyhow::{bail, Context};
+use percent_encoding::utf8_percent_encode;
+
+use crate::{data, lambda, Args};
+
+/// The services whose ARN can be given as the URL.
+const TARGETS: [(&str, Expand); 3] = [("lambda", lambda), ("sns", sns), ("sqs", sqs)];
+
+/// Set the URL and the other options of the request to the resource of ARN.
+type Expand = fn(&Arn, &mut Args) -> anyhow::Result<()>;
+
+/// The parts of arn:PARTITION:SERVICE:REGION:ACCOUNT:RESOURCE.
+#[derive(Debug, PartialEq)]
+struct Arn<'a> {
+    raw: &'a str,
+    partition: &'a str,
+    service: &'a str,
+    region: &'a str,
+    account: &'a str,
+    resource: &'a str,
+}
+
+impl<'a> Arn<'a> {
+    fn parse(raw: &'a str) -> anyhow::Result<Self> {
+        let parts = raw.splitn(6, ':').collect::<Vec<_>>();
+        let [_, partition, service, region, account, resource] = parts[..] else {
+            bail!(
+                "Invalid ARN: {}. Use arn:PARTITION:SERVICE:REGION:ACCOUNT:RESOURCE",
+                raw
+            );
+        };
+        if region.is_empty() {
+            bail!("The ARN has no region: {}", raw);
+        }
+        Ok(Self {
+            raw,
+            partition,
+            service,
+            region,
+            account,
+            resource,
+        })
+    }
+
+    /// The regional endpoint of the service (Ex. https://lambda.us-east-1.amazonaws.com).
+    fn endpoint(&self) -> anyhow::Result<String> {
+        let domain = match self.partition {
+            "aws" | "aws-us-gov" => "amazonaws.com",
+            "aws-cn" => "amazonaws.com.cn",
+            partition => bail!(
+                "Unsupported partition {} in the ARN: {}",
+                partition,
+                self.raw
+            ),
+        };
+        Ok(format!(
+            "https://{}.{}.{}",
+            self.service, self.region, domain
+        ))
+    }
+}
+
+/// Whether URL is an ARN instead of a URL.
+pub fn is_arn(url: &str) -> bool {
+    url.starts_with("arn:")
+}
+
+/// Replace the ARN given as the URL of ARGS with the URL of its resource.
+pub fn expand(args: &mut Args) -> anyhow::Result<()> {
+    let raw = args.url.clone().context("URL is not specified")?;
+    let arn = Arn::parse(&raw)?;
+    let (_, expand) = TARGETS
+        .iter()
+        .find(|(service, _)| *service == arn.service)
+        .with_context(|| {
+            format!(
+                "The ARN of {} is not supported. Use the URL, or the ARN of {}",
+                arn.service,
+                TARGETS.map(|(service, _)| service).join(", ")
+            )
+        })?;
+    expand(&arn, args)?;
+    args.service.get_or_insert_with(|| arn.service.to_string());
+    args.region.get_or_insert_with(|| arn.region.to_string());
+    Ok(())
+}
+
+/// Invoke of the function, or of its version or alias
+/// (function:NAME[:QUALIFIER]).
+fn lambda(arn: &Arn, args: &mut Args) -> anyhow::Result<()> {
+    let Some(function) = arn.resource.strip_prefix("function:") else {
+        bail!(
+            "Only the ARN of a Lambda function is supported: {}",
+            arn.raw
+        );
+    };
+    args.url = Some(lambda::invoke_url(&arn.endpoint()?, function));
+    args.method.get_or_insert_with(|| "POST".to_string());
+    Ok(())
+}
+
+/// Publish to the topic, whose message is given by --data (Ex. -d Message=hello).
+fn sns(arn: &Arn, args: &mut Args) -> anyhow::Result<()> {
+    args.url = Some(format!("{}/", arn.endpoint()?));
+    let topic = utf8_percent_encode(arn.raw, data::URLENCODE);
+    args.data.splice(
+        0..0,
+        [
+            "Action=Publish".to_string(),
+            format!("TopicArn={}", topic),
+            "Version=2010-03-31".to_string(),
+        ],
+    );
+    Ok(())
+}
+
+/// The queue URL, which the actions of the query API are sent to
+/// (Ex. -d Action=SendMessage -d MessageBody=hello).
+fn sqs(arn: &Arn, args: &mut Args) -> anyhow::Result<()> {
+    args.url = Some(format!(
+        "{}/{}/{}",
+        arn.endpoint()?,
+        arn.account,
+        arn.resource
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::expand;
+    use crate::Args;
+
+    fn expanded(options: &[&str]) -> Args {
+        let mut args = Args::parse_from(["awscurl"].iter().chain(options));
+        expand(&mut args).unwrap();
+        args
+    }
+
+    #[test]
+    fn lambda_invoke() {
+        let args = expanded(&[
+            "arn:aws:lambda:us-east-1:123456789012:function:my-fn",
+            "-d",
+            "{}",
+        ]);
+        assert_eq!(
+            args.url.as_deref(),
+            Some("https://lambda.us-east-1.amazonaws.com/2015-03-31/functions/my-fn/invocations")
+        );
+        assert_eq!(args.method.as_deref(), Some("POST"));
+        assert_eq!(args.service.as_deref(), Some("lambda"));
+        assert_eq!(args.region.as_deref(), Some("us-east-1"));
+
+        let args = expanded(&[
+            "arn:aws-cn:lambda:cn-north-1:123456789012:function:my-fn:live",
+            "--region",
+            "cn-northwest-1",
+        ]);
+        assert_eq!(
+            args.url.as_deref(),
+            Some("https://lambda.cn-north-1.amazonaws.com.cn/2015-03-31/functions/my-fn/invocations?Qualifier=live")
+        );
+        assert_eq!(args.region.as_deref(), Some("cn-northwest-1"));
+    }
+
+    #[test]
+    fn sns_publish() {
+        let args = expanded(&[
+            "arn:aws:sns:us-east-1:123456789012:topic",
+            "-d",
+            "Message=hi",
+        ]);
+        assert_eq!(
+            args.url.as_deref(),
+            Some("https://sns.us-east-1.amazonaws.com/")
+        );
+        assert_eq!(
+            args.body().unwrap(),
+            "Action=Publish&TopicArn=arn%3Aaws%3Asns%3Aus-east-1%3A123456789012%3Atopic&Version=2010-03-31&Message=hi"
+        );
+    }
+
+    #[test]
+    fn sqs_queue_url() {
+        let args = expanded(&[
+            "arn:aws:sqs:ap-northeast-1:123456789012:queue",
+            "--service",
+            "sqs",
+        ]);
+        assert_eq!(
+            args.url.as_deref(),
+            Some("https://sqs.ap-northeast-1.amazonaws.com/123456789012/queue")
+        );
+        assert_eq!(args.method, None);
+    }
+
+    #[test]
+    fn unsupported() {
+        let error = |url: &str| {
+            let mut args = Args::parse_from(["awscurl", url]);
+            expand(&mut args).unwrap_err().to_string()
+        };
+        assert_eq!(
+            error("arn:aws:s3:::bucket"),
+            "The ARN has no region: arn:aws:s3:::bucket"
+        );
+        assert_eq!(
+            error("arn:aws:dynamodb:us-east-1:123456789012:table/t"),
+            "The ARN of dynamodb is not supported. Use the URL, or the ARN of lambda, sns, sqs"
+        );
+        assert_eq!(
+            error("arn:aws:lambda:us-east-1:123456789012:layer:l"),
+            "Only the ARN of a Lambda function is supported: arn:aws:lambda:us-east-1:123456789012:layer:l"
+        );
+        assert!(error("arn:aws:lambda").starts_with("Invalid ARN"));
+    }
+}
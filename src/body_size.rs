@@ -0,0 +1,162 @@
+//! The limit of the request body held in memory (`--max-inline-body`)
+//!
+//! The bodies of --data and --upload-file are read into memory to be hashed for
+//! the signature. The body larger than the limit fails before it is hashed,
+//! with the options which send it without buffering, unless --force-buffer is
+//! given. The size of a file is checked before it is read, unless it is
+//! streamed with --unsigned-payload, and stdin fails as soon as it exceeds the
+//! limit while it is read.
+
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use anyhow::bail;
+
+use crate::{upload::Upload, Args, AwsCurlParam};
+
+/// The limit without --max-inline-body.
+pub const DEFAULT_LIMIT: &str = "100M";
+
+/// The ways to send the body which is too large, in the errors.
+const ADVICE: &str = "Stream it with -T FILE --unsigned-payload or -T - --unsigned-payload, upload it to S3 with --multipart-upload, or pass --force-buffer to buffer it anyway";
+
+/// The limit of ARGS, which is None with --force-buffer.
+pub fn limit(args: &Args) -> Option<u64> {
+    (!args.force_buffer).then_some(args.max_inline_body)
+}
+
+/// Fail if the body of LEN bytes is larger than the limit of ARGS.
+fn check(args: &Args, len: u64) -> anyhow::Result<()> {
+    match limit(args) {
+        Some(limit) if len > limit => bail!(
+            "The request body of {} bytes is larger than --max-inline-body {}, and would be held in memory to be signed. {}",
+            len,
+            limit,
+            ADVICE
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Read the body from stdin, which stops as soon as it exceeds LIMIT since its
+/// size is known only after it is read.
+pub fn read_stdin(limit: Option<u64>) -> anyhow::Result<Vec<u8>> {
+    read_within(io::stdin(), limit)
+}
+
+fn read_within(reader: impl Read, limit: Option<u64>) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let max = limit.map_or(u64::MAX, |limit| limit.saturating_add(1));
+    reader.take(max).read_to_end(&mut bytes)?;
+    match limit {
+        Some(limit) if bytes.len() as u64 > limit => bail!(
+            "The request body from stdin is larger than --max-inline-body {}, and would be held in memory to be signed. {}",
+            limit,
+            ADVICE
+        ),
+        _ => Ok(bytes),
+    }
+}
+
+/// Fail before reading the file at PATH of --upload-file if it is too large.
+/// The size of stdin is known only after it is read.
+pub fn check_file(args: &Args, path: &Path) -> anyhow::Result<()> {
+    if path == Path::new("-") {
+        return Ok(());
+    }
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => check(args, metadata.len()),
+        // Reported when the file is read.
+        _ => Ok(()),
+    }
+}
+
+/// Fail if the body of PARAM, which is assembled, is too large.
+pub fn check_body(param: &AwsCurlParam) -> anyhow::Result<()> {
+    let len = match &param.upload {
+        Some(Upload::Bytes(bytes)) => bytes.len(),
+        Some(Upload::File { .. } | Upload::Stream(_)) => return Ok(()),
+        // The values of --data are joined with "&".
+        None => match param.args.data.len() {
+            0 => return Ok(()),
+            n => param.args.data.iter().map(String::len).sum::<usize>() + n - 1,
+        },
+    };
+    check(&param.args, len as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_config::SdkConfig;
+    use clap::Parser;
+
+    use super::{check, check_body, read_within};
+    use crate::{upload::Upload, Args, AwsCurlParam};
+
+    fn args(options: &[&str]) -> Args {
+        Args::parse_from([&["awscurl", "https://example.com"], options].concat())
+    }
+
+    #[test]
+    fn limits() {
+        let default = args(&[]);
+        assert_eq!(default.max_inline_body, 100 << 20);
+        assert!(check(&default, 100 << 20).is_ok());
+        assert!(check(&default, (100 << 20) + 1).is_err());
+
+        let small = args(&["--max-inline-body", "1K"]);
+        assert!(check(&small, 1024).is_ok());
+        assert!(check(&small, 1025)
+            .unwrap_err()
+            .to_string()
+            .starts_with("The request body of 1025 bytes is larger than --max-inline-body 1024"));
+        assert!(check(
+            &args(&["--max-inline-body", "1K", "--force-buffer"]),
+            1 << 30
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn read_within_limit() {
+        let mut reader = &b"0123456789"[..];
+        assert_eq!(read_within(&mut reader, Some(10)).unwrap(), b"0123456789");
+        let mut reader = &b"0123456789"[..];
+        assert!(read_within(&mut reader, Some(4))
+            .unwrap_err()
+            .to_string()
+            .starts_with("The request body from stdin is larger than --max-inline-body 4"));
+        // Stopped after the byte over the limit.
+        assert_eq!(reader, b"56789");
+        assert_eq!(read_within(&b"0123456789"[..], None).unwrap().len(), 10);
+    }
+
+    #[test]
+    fn assembled_bodies() {
+        let param = |options: &[&str], upload: Option<Upload>| {
+            let mut param = AwsCurlParam::new(args(options), SdkConfig::builder().build());
+            param.upload = upload;
+            param
+        };
+        // "a=1&b=2" is 7 bytes.
+        assert!(check_body(&param(
+            &["--max-inline-body", "7", "-d", "a=1", "-d", "b=2"],
+            None
+        ))
+        .is_ok());
+        assert!(check_body(&param(
+            &["--max-inline-body", "6", "-d", "a=1", "-d", "b=2"],
+            None
+        ))
+        .is_err());
+        assert!(check_body(&param(
+            &["--max-inline-body", "4"],
+            Some(Upload::Bytes(vec![0; 5]))
+        ))
+        .is_err());
+        assert!(check_body(&param(&["--max-inline-body", "1"], None)).is_ok());
+    }
+}
@@ -0,0 +1,136 @@
+//! Conditional requests (`--etag-save`, `--etag-compare`, `--cache-dir`, `--time-cond`)
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{bail, Context};
+use chrono::DateTime;
+
+use crate::calc_sha256_hex_digest;
+
+/// Read the ETag saved by `--etag-save`. A missing file means there is no ETag yet.
+pub fn read_etag(path: &Path) -> anyhow::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(raw) => Ok(Some(raw.trim().to_string()).filter(|etag| !etag.is_empty())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Unable to read {}", path.display())),
+    }
+}
+
+/// The time given by `--time-cond`, which is the modification time of the file
+/// if it exists, otherwise a date (Ex. "Fri, 24 May 2013 00:00:00 GMT").
+pub fn parse_time_cond(raw: &str) -> anyhow::Result<SystemTime> {
+    if let Ok(metadata) = fs::metadata(raw) {
+        return Ok(metadata.modified()?);
+    }
+    if let Ok(time) = httpdate::parse_http_date(raw) {
+        return Ok(time);
+    }
+    let date = DateTime::parse_from_rfc2822(raw).or_else(|_| DateTime::parse_from_rfc3339(raw));
+    if let Ok(time) = date {
+        return Ok(time.into());
+    }
+    bail!(
+        "--time-cond is neither an existing file nor a date: {}",
+        raw
+    )
+}
+
+/// Write `contents` to `path` through a temporary file in the same directory.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    io::Write::write_all(&mut file, contents)?;
+    file.persist(path)
+        .with_context(|| format!("Unable to write {}", path.display()))?;
+    Ok(())
+}
+
+/// Response bodies cached with their ETags, keyed by the full URL.
+pub struct ResponseCache {
+    etag_path: PathBuf,
+    body_path: PathBuf,
+}
+
+impl ResponseCache {
+    pub fn new(dir: &Path, url: &str) -> Self {
+        let key = calc_sha256_hex_digest(url);
+        Self {
+            etag_path: dir.join(format!("{}.etag", key)),
+            body_path: dir.join(format!("{}.body", key)),
+        }
+    }
+
+    pub fn etag(&self) -> anyhow::Result<Option<String>> {
+        // The body is written before the ETag, so an ETag always has its body.
+        read_etag(&self.etag_path)
+    }
+
+    pub fn body(&self) -> anyhow::Result<Vec<u8>> {
+        fs::read(&self.body_path)
+            .with_context(|| format!("Unable to read {}", self.body_path.display()))
+    }
+
+    pub fn store(&self, etag: &str, body: &[u8]) -> anyhow::Result<()> {
+        write_atomically(&self.body_path, body)?;
+        write_atomically(&self.etag_path, etag.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{parse_time_cond, read_etag, ResponseCache};
+
+    #[test]
+    fn time_cond_date() {
+        let expected = UNIX_EPOCH + Duration::from_secs(1369353600);
+        for raw in [
+            "Fri, 24 May 2013 00:00:00 GMT",
+            "Fri, 24 May 2013 09:00:00 +0900",
+            "2013-05-24T00:00:00Z",
+        ] {
+            assert_eq!(parse_time_cond(raw).unwrap(), expected, "{}", raw);
+        }
+        assert!(parse_time_cond("yesterday").is_err());
+    }
+
+    #[test]
+    fn time_cond_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mtime = UNIX_EPOCH + Duration::from_secs(1369353600);
+        file.as_file().set_modified(mtime).unwrap();
+        assert_eq!(
+            parse_time_cond(file.path().to_str().unwrap()).unwrap(),
+            mtime
+        );
+    }
+
+    #[test]
+    fn keyed_by_full_url() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::new(dir.path(), "https://example.com/?page=1");
+        cache.store("\"abc\"", b"first page").unwrap();
+
+        let other = ResponseCache::new(dir.path(), "https://example.com/?page=2");
+        assert_eq!(other.etag().unwrap(), None);
+
+        let same = ResponseCache::new(dir.path(), "https://example.com/?page=1");
+        assert_eq!(same.etag().unwrap().as_deref(), Some("\"abc\""));
+        assert_eq!(same.body().unwrap(), b"first page");
+    }
+
+    #[test]
+    fn missing_etag_file() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(read_etag(&dir.path().join("etag")).unwrap(), None);
+    }
+}
@@ -0,0 +1,180 @@
+//! Health check of an endpoint (`--check`)
+//!
+//! The signed request (HEAD unless -X is given) is sent as a probe, whose
+//! response body is dropped without reading, and a line for each probe is
+//! printed to stdout: "OK 200 123ms", "FAIL 503 45ms" or "FAIL timeout". Each
+//! probe times out after 2 seconds unless --max-time is given. With
+//! --check-count, the probes are repeated every --check-interval and followed by
+//! a summary of the success rate.
+
+use std::{
+    io::{self, Write},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    exit, interrupt, poll, rate_limit,
+    retry::{self, Failure},
+    Args, AwsCurlParam,
+};
+
+/// The --max-time of a probe, which is short to tell that the endpoint is down.
+const DEFAULT_TIMEOUT: f64 = 2.0;
+
+/// Set the defaults of --check to ARGS.
+pub fn install(args: &mut Args) {
+    if !args.check {
+        return;
+    }
+    args.method.get_or_insert_with(|| "HEAD".to_string());
+    args.max_time.get_or_insert(DEFAULT_TIMEOUT);
+}
+
+/// The result of a probe.
+#[derive(Debug, PartialEq)]
+enum Probe {
+    Up { status: u16, elapsed: Duration },
+    Down { status: u16, elapsed: Duration },
+    Error(Failure),
+}
+
+impl Probe {
+    fn line(&self) -> String {
+        match self {
+            Probe::Up { status, elapsed } => format!("OK {} {}ms", status, elapsed.as_millis()),
+            Probe::Down { status, elapsed } => {
+                format!("FAIL {} {}ms", status, elapsed.as_millis())
+            }
+            Probe::Error(failure) => format!("FAIL {}", failure),
+        }
+    }
+}
+
+/// Probe the endpoint --check-count times, starting with REQ, and print the
+/// results. The exit code is the one of the last failed probe, or 0 when all of
+/// them are up.
+pub async fn run(
+    param: &AwsCurlParam,
+    client: &reqwest::Client,
+    mut req: reqwest::Request,
+) -> anyhow::Result<ExitCode> {
+    let args = &param.args;
+    let interval = Duration::from_secs_f64(args.check_interval);
+    let mut probes = Vec::new();
+    let mut code = ExitCode::SUCCESS;
+    let mut stdout = io::stdout();
+    for count in 1..=args.check_count {
+        let start = Instant::now();
+        let res = retry::send(param, client, req).await;
+        if let (Ok(res), true) = (&res, args.show_rate_limit) {
+            rate_limit::observe(res.status(), res.headers());
+        }
+        let probe = match res {
+            Ok(res) if res.status().is_success() => Probe::Up {
+                status: res.status().as_u16(),
+                elapsed: start.elapsed(),
+            },
+            Ok(res) => {
+                code = exit::for_status(res.status(), args.exit_code_mode);
+                Probe::Down {
+                    status: res.status().as_u16(),
+                    elapsed: start.elapsed(),
+                }
+            }
+            Err(e) => {
+                let failure = Failure::classify(&e);
+                if failure == Failure::Interrupted {
+                    break;
+                }
+                code = exit::for_error(&e, args.exit_code_mode);
+                Probe::Error(failure)
+            }
+        };
+        writeln!(stdout, "{}", probe.line())?;
+        stdout.flush()?;
+        probes.push(probe);
+        if count == args.check_count {
+            break;
+        }
+        let delay = match args.show_rate_limit {
+            true => interval.max(rate_limit::delay()),
+            false => interval,
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = interrupt::interrupted() => break,
+        }
+        req = poll::resign(param).await?;
+    }
+    if args.check_count > 1 {
+        writeln!(stdout, "{}", summary(&probes))?;
+    }
+    if args.show_rate_limit {
+        rate_limit::print_total();
+    }
+    Ok(code)
+}
+
+/// The success rate of PROBES and the times of the responses, like ping.
+fn summary(probes: &[Probe]) -> String {
+    let elapsed = probes
+        .iter()
+        .filter_map(|probe| match probe {
+            Probe::Up { elapsed, .. } | Probe::Down { elapsed, .. } => Some(elapsed.as_millis()),
+            Probe::Error(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let up = probes
+        .iter()
+        .filter(|probe| matches!(probe, Probe::Up { .. }))
+        .count();
+    let rate = match probes.len() {
+        0 => 0.0,
+        n => up as f64 * 100.0 / n as f64,
+    };
+    let mut summary = format!("{}/{} OK ({:.1}%)", up, probes.len(), rate);
+    if let (Some(min), Some(max)) = (elapsed.iter().min(), elapsed.iter().max()) {
+        let avg = elapsed.iter().sum::<u128>() / elapsed.len() as u128;
+        summary.push_str(&format!(", min/avg/max {}/{}/{}ms", min, avg, max));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{summary, Probe};
+    use crate::retry::Failure;
+
+    fn up(ms: u64) -> Probe {
+        Probe::Up {
+            status: 200,
+            elapsed: Duration::from_millis(ms),
+        }
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(up(123).line(), "OK 200 123ms");
+        let down = Probe::Down {
+            status: 503,
+            elapsed: Duration::from_millis(45),
+        };
+        assert_eq!(down.line(), "FAIL 503 45ms");
+        assert_eq!(Probe::Error(Failure::Timeout).line(), "FAIL timeout");
+    }
+
+    #[test]
+    fn summaries() {
+        let probes = [
+            up(10),
+            up(30),
+            Probe::Error(Failure::ConnectionRefused),
+            up(21),
+        ];
+        assert_eq!(summary(&probes), "3/4 OK (75.0%), min/avg/max 10/20/30ms");
+        assert_eq!(summary(&[Probe::Error(Failure::Dns)]), "0/1 OK (0.0%)");
+    }
+}
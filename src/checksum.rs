@@ -0,0 +1,434 @@
+//! Verifying the response body with its checksums (`--verify-checksum`)
+//!
+//! The body is checked against the x-amz-checksum-* headers and, for S3, the
+//! ETag which is the MD5 of the object uploaded in one part. The checksums of
+//! the multipart uploads and the ETags of the encrypted objects are not the
+//! digests of the body, so they are skipped.
+
+use std::io::{self, Read};
+
+use anyhow::bail;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use clap::ValueEnum;
+use http::{header::ETAG, HeaderMap};
+use sha2::{Digest, Sha256};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum VerifyChecksum {
+    /// Verify the checksums which the response has
+    #[default]
+    Auto,
+    /// Fail if the response has no checksum to verify
+    Required,
+    /// Do not verify the checksums
+    Off,
+}
+
+/// The digest of the body for a checksum header, which is fed in chunks.
+enum Digester {
+    Sha256(Sha256),
+    Sha1(ring::digest::Context),
+    Crc32(Crc32),
+    Md5(Md5),
+}
+
+impl Digester {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Digester::Sha256(sha256) => sha256.update(data),
+            Digester::Sha1(sha1) => sha1.update(data),
+            Digester::Crc32(crc32) => crc32.update(data),
+            Digester::Md5(md5) => md5.update(data),
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        match self {
+            Digester::Sha256(sha256) => sha256.finalize().to_vec(),
+            Digester::Sha1(sha1) => sha1.finish().as_ref().to_vec(),
+            Digester::Crc32(crc32) => crc32.finish().to_be_bytes().to_vec(),
+            Digester::Md5(md5) => md5.finish().to_vec(),
+        }
+    }
+}
+
+type NewDigester = fn() -> Digester;
+
+/// The checksum headers and the digests of the body, which are base64 encoded.
+const CHECKSUM_HEADERS: [(&str, NewDigester); 4] = [
+    ("x-amz-checksum-sha256", || Digester::Sha256(Sha256::new())),
+    ("x-amz-checksum-sha1", || {
+        Digester::Sha1(ring::digest::Context::new(
+            &ring::digest::SHA1_FOR_LEGACY_USE_ONLY,
+        ))
+    }),
+    ("x-amz-checksum-crc32c", || {
+        Digester::Crc32(Crc32::new(0x82F6_3B78))
+    }),
+    ("x-amz-checksum-crc32", || {
+        Digester::Crc32(Crc32::new(0xEDB8_8320))
+    }),
+];
+
+/// The size of the chunks which the body is read in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The result of the verification.
+#[derive(Debug, Default, PartialEq)]
+pub struct Verification {
+    /// The headers which matched the body
+    pub verified: Vec<&'static str>,
+    /// Why the other checksums were not verified
+    pub skipped: Vec<String>,
+}
+
+/// Verify the body read from BODY with the checksums in HEADERS, and the ETag
+/// if ETAG (S3), without holding it in memory. Fails with the expected and the
+/// actual values if one of them differs.
+pub fn verify_read(
+    headers: &HeaderMap,
+    mut body: impl Read,
+    etag: bool,
+) -> anyhow::Result<Verification> {
+    let mut verification = Verification::default();
+    // The header, the expected value and the digest of the body
+    let mut checks = Vec::new();
+    for (name, digester) in CHECKSUM_HEADERS {
+        let Some(expected) = headers.get(name).and_then(|value| value.to_str().ok()) else {
+            continue;
+        };
+        // The checksum of the checksums of the parts (Ex. "i9aeUg==-3")
+        if expected.contains('-') {
+            verification.skipped.push(format!(
+                "{} is the checksum of the parts of a multipart upload",
+                name
+            ));
+            continue;
+        }
+        checks.push((name, expected, digester()));
+    }
+    let value = headers.get(ETAG).and_then(|value| value.to_str().ok());
+    if let Some(expected) = value.filter(|_| etag) {
+        let expected = expected.trim_start_matches("W/").trim_matches('"');
+        let encrypted = headers
+            .get("x-amz-server-side-encryption")
+            .is_some_and(|value| value.as_bytes().starts_with(b"aws:kms"))
+            || headers.contains_key("x-amz-server-side-encryption-customer-algorithm");
+        if expected.contains('-') {
+            verification
+                .skipped
+                .push("ETag of a multipart upload is not the MD5 of the body".to_string());
+        } else if encrypted {
+            verification.skipped.push(
+                "ETag of an object encrypted with KMS or SSE-C is not the MD5 of the body"
+                    .to_string(),
+            );
+        } else if expected.len() == 32 && expected.bytes().all(|b| b.is_ascii_hexdigit()) {
+            checks.push(("etag", expected, Digester::Md5(Md5::new())));
+        }
+    }
+
+    if !checks.is_empty() {
+        let mut chunk = vec![0; CHUNK_SIZE];
+        loop {
+            let len = match body.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for (_, _, digester) in &mut checks {
+                digester.update(&chunk[..len]);
+            }
+        }
+    }
+    for (name, expected, digester) in checks {
+        if name == "etag" {
+            let actual = hex::encode(digester.finish());
+            if !actual.eq_ignore_ascii_case(expected) {
+                bail!(
+                    "Checksum mismatch in ETag: expected {}, actual {}",
+                    expected,
+                    actual
+                );
+            }
+        } else {
+            let actual = BASE64_STANDARD.encode(digester.finish());
+            if actual != expected {
+                bail!(
+                    "Checksum mismatch in {}: expected {}, actual {}",
+                    name,
+                    expected,
+                    actual
+                );
+            }
+        }
+        verification.verified.push(name);
+    }
+    Ok(verification)
+}
+
+/// CRC-32 with the reflected polynomial POLY (CRC-32 and CRC-32C).
+pub fn crc32(poly: u32, data: &[u8]) -> u32 {
+    let mut crc32 = Crc32::new(poly);
+    crc32.update(data);
+    crc32.finish()
+}
+
+/// CRC-32 fed in chunks.
+struct Crc32 {
+    table: Box<[u32; 256]>,
+    crc: u32,
+}
+
+impl Crc32 {
+    fn new(poly: u32) -> Self {
+        let mut table = [0u32; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            *entry = (0..8).fold(i as u32, |crc, _| {
+                if crc & 1 == 1 {
+                    (crc >> 1) ^ poly
+                } else {
+                    crc >> 1
+                }
+            });
+        }
+        Self {
+            table: Box::new(table),
+            crc: !0,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.crc = data.iter().fold(self.crc, |crc, &b| {
+            self.table[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+        });
+    }
+
+    fn finish(self) -> u32 {
+        !self.crc
+    }
+}
+
+/// MD5 (RFC 1321) fed in chunks, which is only used to compare with the ETags
+/// of S3.
+struct Md5 {
+    /// The constants of the rounds
+    k: Box<[u32; 64]>,
+    state: [u32; 4],
+    /// The bytes which do not fill a block yet
+    pending: Vec<u8>,
+    len: u64,
+}
+
+impl Md5 {
+    fn new() -> Self {
+        Self {
+            k: Box::new(std::array::from_fn(|i| {
+                ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32
+            })),
+            state: [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476],
+            pending: Vec::with_capacity(64),
+            len: 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.len = self.len.wrapping_add(data.len() as u64);
+        if !self.pending.is_empty() {
+            let take = (64 - self.pending.len()).min(data.len());
+            self.pending.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.pending.len() < 64 {
+                return;
+            }
+            let block = std::mem::take(&mut self.pending);
+            self.process(&block);
+        }
+        let blocks = data.chunks_exact(64);
+        let rest = blocks.remainder();
+        for block in blocks {
+            self.process(block);
+        }
+        self.pending.extend_from_slice(rest);
+    }
+
+    fn finish(mut self) -> [u8; 16] {
+        // The padding and the length in bits
+        let mut tail = std::mem::take(&mut self.pending);
+        tail.push(0x80);
+        while tail.len() % 64 != 56 {
+            tail.push(0);
+        }
+        tail.extend_from_slice(&self.len.wrapping_mul(8).to_le_bytes());
+        tail.chunks(64).for_each(|block| self.process(block));
+        let mut digest = [0; 16];
+        for (bytes, word) in digest.chunks_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+
+    fn process(&mut self, block: &[u8]) {
+        const SHIFTS: [[u32; 4]; 4] = [
+            [7, 12, 17, 22],
+            [5, 9, 14, 20],
+            [4, 11, 16, 23],
+            [6, 10, 15, 21],
+        ];
+        let m = block
+            .chunks(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect::<Vec<_>>();
+        let [mut a, mut b, mut c, mut d] = self.state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(self.k[i]).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(SHIFTS[i / 16][i % 4]));
+        }
+        for (word, value) in self.state.iter_mut().zip([a, b, c, d]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{HeaderMap, HeaderValue};
+
+    use super::{crc32, verify_read, Md5, Verification};
+
+    fn md5(data: &[u8]) -> [u8; 16] {
+        let mut md5 = Md5::new();
+        md5.update(data);
+        md5.finish()
+    }
+
+    #[test]
+    fn digests() {
+        assert_eq!(hex::encode(md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(
+            hex::encode(md5(b"The quick brown fox jumps over the lazy dog")),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+        // Longer than a block (RFC 1321)
+        assert_eq!(
+            hex::encode(md5("1234567890".repeat(8).as_bytes())),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+        // Fed in chunks which do not fill the blocks
+        let mut chunked = Md5::new();
+        for chunk in "1234567890".repeat(8).as_bytes().chunks(7) {
+            chunked.update(chunk);
+        }
+        assert_eq!(
+            hex::encode(chunked.finish()),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+        assert_eq!(crc32(0xEDB8_8320, b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(0x82F6_3B78, b"123456789"), 0xE306_9283);
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn verify_checksums() {
+        let body: &[u8] = b"hello";
+        let headers_ok = headers(&[
+            ("x-amz-checksum-crc32", "NhCmhg=="),
+            (
+                "x-amz-checksum-sha256",
+                "LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=",
+            ),
+            ("etag", "\"5d41402abc4b2a76b9719d911017c592\""),
+        ]);
+        assert_eq!(
+            verify_read(&headers_ok, body, true).unwrap(),
+            Verification {
+                verified: vec!["x-amz-checksum-sha256", "x-amz-checksum-crc32", "etag"],
+                skipped: vec![],
+            }
+        );
+        // The ETags are verified only for S3.
+        assert_eq!(
+            verify_read(&headers_ok, body, false)
+                .unwrap()
+                .verified
+                .len(),
+            2
+        );
+
+        assert_eq!(
+            verify_read(
+                &headers(&[("x-amz-checksum-crc32", "AAAAAA==")]),
+                body,
+                true
+            )
+            .unwrap_err()
+            .to_string(),
+            "Checksum mismatch in x-amz-checksum-crc32: expected AAAAAA==, actual NhCmhg=="
+        );
+        assert_eq!(
+            verify_read(
+                &headers(&[("etag", "\"00000000000000000000000000000000\"")]),
+                body,
+                true
+            )
+            .unwrap_err()
+            .to_string(),
+            "Checksum mismatch in ETag: expected 00000000000000000000000000000000, actual 5d41402abc4b2a76b9719d911017c592"
+        );
+
+        let skipped = verify_read(
+            &headers(&[
+                ("x-amz-checksum-crc32c", "i9aeUg==-3"),
+                ("etag", "\"9b2cf535f27731c974343645a3985328-3\""),
+            ]),
+            body,
+            true,
+        )
+        .unwrap();
+        assert!(skipped.verified.is_empty());
+        assert_eq!(skipped.skipped.len(), 2);
+    }
+
+    #[test]
+    fn verify_in_chunks() {
+        use base64::{prelude::BASE64_STANDARD, Engine};
+        use sha2::{Digest, Sha256};
+
+        // Longer than a chunk, which is read in several reads.
+        let body = (0..150_000).map(|i| i as u8).collect::<Vec<_>>();
+        let sha256 = BASE64_STANDARD.encode(Sha256::digest(&body));
+        let crc32 = BASE64_STANDARD.encode(crc32(0xEDB8_8320, &body).to_be_bytes());
+        let etag = format!("\"{}\"", hex::encode(md5(&body)));
+        let headers = [
+            ("x-amz-checksum-sha256", sha256.as_str()),
+            ("x-amz-checksum-crc32", crc32.as_str()),
+            ("etag", etag.as_str()),
+        ]
+        .iter()
+        .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+        .collect::<HeaderMap>();
+        assert_eq!(
+            verify_read(&headers, std::io::Cursor::new(&body), true)
+                .unwrap()
+                .verified,
+            ["x-amz-checksum-sha256", "x-amz-checksum-crc32", "etag"]
+        );
+        assert!(verify_read(&headers, &body[1..], true).is_err());
+    }
+}
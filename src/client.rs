@@ -0,0 +1,272 @@
+//! The HTTP client shared by the requests, which is configured by the
+//! connection options (`--interface`, `--dns-servers`, `--tls-max` ...)
+
+use std::{
+    collections::BTreeSet,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+use hyper_util::client::legacy::connect::HttpInfo;
+use reqwest::dns::{Name, Resolve};
+use tokio::net::{TcpSocket, TcpStream};
+
+use crate::{
+    dns::{Family, Resolver},
+    tls, Args,
+};
+
+/// The address family of --ipv4 and --ipv6.
+pub fn family(args: &Args) -> Option<Family> {
+    match (args.ipv4, args.ipv6) {
+        (true, _) => Some(Family::V4),
+        (_, true) => Some(Family::V6),
+        _ => None,
+    }
+}
+
+pub fn build(args: &Args) -> anyhow::Result<reqwest::Client> {
+    let resolver = Resolver::new(&args.dns_servers, family(args))?;
+    // The entries of --resolve are looked up before the resolver.
+    let mut builder = reqwest::Client::builder().dns_resolver(Arc::new(resolver));
+    for (host, addrs) in &args.resolve {
+        builder = builder.resolve_to_addrs(host, addrs);
+    }
+    if let Some(interface) = &args.interface {
+        builder = bind(builder, interface)?;
+    }
+    if args.no_keepalive {
+        // Nothing is kept in the pool, so every request opens a new connection.
+        builder = builder.pool_max_idle_per_host(0);
+    }
+    if let Some(seconds) = args.keepalive_time {
+        builder = builder.tcp_keepalive(Duration::from_secs(seconds));
+    }
+    if let Some(max) = args.max_connections_per_host {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+    if args.websocket {
+        // The connection is upgraded only in HTTP/1.1.
+        builder = builder.http1_only();
+    }
+    builder = tls::configure(builder, args)?;
+    builder.build().context("Unable to build the HTTP client")
+}
+
+/// Connect to HOST:PORT as the client of ARGS does, with the addresses of
+/// --resolve or the resolver of --dns-servers, --ipv4 and --ipv6, and bound to
+/// the address or the interface of --interface.
+pub async fn connect(args: &Args, host: &str, port: u16) -> anyhow::Result<TcpStream> {
+    // The brackets of an IPv6 address are only in the URL.
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = if let Ok(ip) = host.parse::<IpAddr>() {
+        vec![SocketAddr::new(ip, port)]
+    } else if let Some((_, addrs)) = args.resolve.iter().find(|(name, _)| name == host) {
+        addrs.clone()
+    } else {
+        let resolver = Resolver::new(&args.dns_servers, family(args))?;
+        let name = host.parse::<Name>()?;
+        resolver
+            .resolve(name)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?
+            .collect()
+    };
+    let mut last = None;
+    for mut addr in addrs {
+        // The port of --resolve is only for compatibility with curl.
+        addr.set_port(port);
+        match connect_from(args, addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last = Some(e),
+        }
+    }
+    Err(last.unwrap_or_else(|| anyhow::anyhow!("No address of {}", host)))
+        .with_context(|| format!("Unable to connect to {}:{}", host, port))
+}
+
+async fn connect_from(args: &Args, addr: SocketAddr) -> anyhow::Result<TcpStream> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    if let Some(interface) = &args.interface {
+        match interface.parse::<IpAddr>() {
+            Ok(ip) => socket.bind(SocketAddr::new(ip, 0))?,
+            Err(_) => bind_socket_device(&socket, interface)?,
+        }
+    }
+    Ok(socket.connect(addr).await?)
+}
+
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn bind_socket_device(socket: &TcpSocket, interface: &str) -> anyhow::Result<()> {
+    Ok(socket.bind_device(Some(interface.as_bytes()))?)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn bind_socket_device(_socket: &TcpSocket, interface: &str) -> anyhow::Result<()> {
+    bail!(
+        "--interface accepts only an IP address on this platform: {}",
+        interface
+    )
+}
+
+/// The connections which the responses came over, to tell whether one was reused.
+struct Connections {
+    seen: BTreeSet<(SocketAddr, SocketAddr)>,
+    requests: usize,
+    reused: usize,
+}
+
+static CONNECTIONS: Mutex<Connections> = Mutex::new(Connections {
+    seen: BTreeSet::new(),
+    requests: 0,
+    reused: 0,
+});
+
+/// Record the connection of the response and return whether it was reused.
+///
+/// reqwest does not tell it directly, so a connection is identified by its local
+/// and remote addresses. None if they are not known (Ex. --unix-socket).
+pub fn track(res: &reqwest::Response) -> Option<bool> {
+    let info = res.extensions().get::<HttpInfo>()?;
+    let mut connections = CONNECTIONS.lock().unwrap();
+    let reused = !connections
+        .seen
+        .insert((info.local_addr(), info.remote_addr()));
+    connections.requests += 1;
+    connections.reused += usize::from(reused);
+    Some(reused)
+}
+
+/// The number of the tracked requests and the ones which reused a connection.
+pub fn reuse_count() -> (usize, usize) {
+    let connections = CONNECTIONS.lock().unwrap();
+    (connections.requests, connections.reused)
+}
+
+/// Bind the outgoing connections to the local address or the network interface.
+fn bind(
+    builder: reqwest::ClientBuilder,
+    interface: &str,
+) -> anyhow::Result<reqwest::ClientBuilder> {
+    if let Ok(addr) = interface.parse::<IpAddr>() {
+        // Fail early with a clear message instead of on connecting.
+        UdpSocket::bind((addr, 0))
+            .with_context(|| format!("The address {} is not assigned to this host", addr))?;
+        return Ok(builder.local_address(addr));
+    }
+    bind_device(builder, interface)
+}
+
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn bind_device(
+    builder: reqwest::ClientBuilder,
+    interface: &str,
+) -> anyhow::Result<reqwest::ClientBuilder> {
+    if !std::path::Path::new("/sys/class/net")
+        .join(interface)
+        .exists()
+    {
+        bail!("No such network interface: {}", interface);
+    }
+    Ok(builder.interface(interface))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn bind_device(
+    _builder: reqwest::ClientBuilder,
+    interface: &str,
+) -> anyhow::Result<reqwest::ClientBuilder> {
+    bail!(
+        "--interface accepts only an IP address on this platform: {}",
+        interface
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{bind, build, connect, track};
+    use crate::Args;
+
+    /// Start a server which keeps the connections alive, and return its URL.
+    async fn keepalive_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = vec![0; 4096];
+                    while stream.read(&mut buf).await.unwrap_or(0) > 0 {
+                        let response = "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n";
+                        if stream.write_all(response.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    async fn reused(options: &[&str]) -> Vec<Option<bool>> {
+        let url = keepalive_server().await;
+        let args = Args::parse_from(["awscurl", url.as_str()].iter().chain(options));
+        let client = build(&args).unwrap();
+        let mut ret = vec![];
+        for _ in 0..2 {
+            let res = client.get(&url).send().await.unwrap();
+            ret.push(track(&res));
+            res.bytes().await.unwrap();
+        }
+        ret
+    }
+
+    #[tokio::test]
+    async fn reuse_connection() {
+        assert_eq!(reused(&[]).await, [Some(false), Some(true)]);
+        assert_eq!(
+            reused(&["--max-connections-per-host", "1"]).await,
+            [Some(false), Some(true)]
+        );
+        assert_eq!(
+            reused(&["--no-keepalive"]).await,
+            [Some(false), Some(false)]
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_with_resolve() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // The port of --resolve is replaced with the one of the URL.
+        let args = Args::parse_from([
+            "awscurl",
+            "https://example.test/",
+            "--resolve",
+            "example.test:443:127.0.0.1",
+        ]);
+        let stream = connect(&args, "example.test", port).await.unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), listener.local_addr().unwrap());
+    }
+
+    #[test]
+    fn unknown_interface() {
+        let err = bind(reqwest::Client::builder(), "192.0.2.1").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The address 192.0.2.1 is not assigned to this host"
+        );
+        assert!(bind(reqwest::Client::builder(), "no-such-if0").is_err());
+    }
+}
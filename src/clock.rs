@@ -0,0 +1,205 @@
+//! The check of the local clock against the Date of the responses
+//! (`--no-clock-check` to disable)
+//!
+//! A clock off by minutes makes AWS reject the signatures with errors which do
+//! not tell the cause. No request is added for the check: the Date header of an
+//! earlier response in the invocation is compared before the next request is
+//! sent, and the one of a response rejected for the signature or the time is
+//! compared when it is received. The warning is printed once.
+
+use std::sync::Mutex;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use http::{header::DATE, HeaderMap, StatusCode};
+
+use crate::{logging, Args, MAX_CLOCK_SKEW};
+
+/// The skew warned before sending, which is obviously wrong.
+const OBVIOUS_SKEW: TimeDelta = TimeDelta::minutes(5);
+/// The skew warned for a rejected request, beyond the latency and the
+/// precision of seconds of the Date header.
+const SUSPICIOUS_SKEW: TimeDelta = TimeDelta::seconds(30);
+
+/// The error codes and messages of the requests rejected for the signature or
+/// the time.
+const TIME_ERRORS: [&str; 5] = [
+    "RequestTimeTooSkewed",
+    "RequestExpired",
+    "SignatureDoesNotMatch",
+    "InvalidSignatureException",
+    "Signature expired",
+];
+
+struct State {
+    /// The local time minus the one of the server in the last response
+    skew: Option<TimeDelta>,
+    warned: bool,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    skew: None,
+    warned: false,
+});
+
+/// How far the local clock at NOW is ahead of DATE of the server.
+pub fn skew(date: DateTime<Utc>, now: DateTime<Utc>) -> TimeDelta {
+    now - date
+}
+
+/// The skew of HEADERS received at NOW, if they have a Date.
+fn skew_of(headers: &HeaderMap, now: DateTime<Utc>) -> Option<TimeDelta> {
+    let date = headers.get(DATE)?.to_str().ok()?;
+    let date = httpdate::parse_http_date(date).ok()?;
+    Some(skew(date.into(), now))
+}
+
+/// The warning of SKEW, if it is beyond THRESHOLD.
+pub fn message(skew: TimeDelta, threshold: TimeDelta) -> Option<String> {
+    if skew.abs() <= threshold {
+        return None;
+    }
+    let direction = match skew > TimeDelta::zero() {
+        true => "ahead of",
+        false => "behind",
+    };
+    let mut message = format!(
+        "Your clock appears to be {} seconds {} the server",
+        skew.num_seconds().abs(),
+        direction
+    );
+    if skew.abs() > MAX_CLOCK_SKEW {
+        message.push_str(", which is more than the 15 minutes AWS accepts for the signatures");
+    }
+    Some(message)
+}
+
+/// Whether the response of STATUS was rejected for the signature or the time.
+fn time_error(status: StatusCode, headers: &HeaderMap, body: &[u8]) -> bool {
+    if !status.is_client_error() {
+        return false;
+    }
+    let error_type = headers
+        .get("x-amzn-errortype")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let body = String::from_utf8_lossy(body);
+    TIME_ERRORS
+        .iter()
+        .any(|error| error_type.contains(error) || body.contains(error))
+}
+
+fn enabled(args: &Args) -> bool {
+    // --datetime is checked by itself.
+    !args.no_clock_check && args.datetime.is_none()
+}
+
+fn warn(message: String) {
+    let mut state = STATE.lock().unwrap();
+    if !state.warned {
+        state.warned = true;
+        logging::warning(message);
+    }
+}
+
+/// Keep the skew of the response HEADERS for the next request.
+pub fn record(headers: &HeaderMap) {
+    if let Some(skew) = skew_of(headers, Utc::now()) {
+        STATE.lock().unwrap().skew = Some(skew);
+    }
+}
+
+/// Warn before sending if an earlier response tells that the clock is wrong.
+pub fn check(args: &Args) {
+    if !enabled(args) {
+        return;
+    }
+    let skew = STATE.lock().unwrap().skew;
+    if let Some(message) = skew.and_then(|skew| message(skew, OBVIOUS_SKEW)) {
+        warn(message);
+    }
+}
+
+/// Warn if the response was rejected for the signature or the time and its Date
+/// tells that the clock is wrong.
+pub fn check_failure(args: &Args, status: StatusCode, headers: &HeaderMap, body: &[u8]) {
+    if !enabled(args) || !time_error(status, headers, body) {
+        return;
+    }
+    let skew = skew_of(headers, Utc::now());
+    if let Some(message) = skew.and_then(|skew| message(skew, SUSPICIOUS_SKEW)) {
+        warn(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeDelta, Utc};
+    use http::{HeaderMap, StatusCode};
+
+    use super::{message, skew, skew_of, time_error, OBVIOUS_SKEW, SUSPICIOUS_SKEW};
+
+    fn time(raw: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(raw).unwrap().to_utc()
+    }
+
+    #[test]
+    fn skews() {
+        let server = time("2015-10-21T07:28:00Z");
+        assert_eq!(
+            skew(server, time("2015-10-21T07:38:00Z")),
+            TimeDelta::minutes(10)
+        );
+        assert_eq!(
+            skew(server, time("2015-10-21T07:27:30Z")),
+            TimeDelta::seconds(-30)
+        );
+
+        let headers = [(
+            "date".parse().unwrap(),
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        )]
+        .into_iter()
+        .collect::<HeaderMap>();
+        assert_eq!(
+            skew_of(&headers, time("2015-10-21T07:28:02Z")),
+            Some(TimeDelta::seconds(2))
+        );
+        assert_eq!(skew_of(&HeaderMap::new(), server), None);
+    }
+
+    #[test]
+    fn thresholds() {
+        assert_eq!(message(TimeDelta::seconds(30), SUSPICIOUS_SKEW), None);
+        assert_eq!(
+            message(TimeDelta::seconds(31), SUSPICIOUS_SKEW).unwrap(),
+            "Your clock appears to be 31 seconds ahead of the server"
+        );
+        assert_eq!(message(TimeDelta::minutes(-4), OBVIOUS_SKEW), None);
+        assert_eq!(
+            message(TimeDelta::minutes(-6), OBVIOUS_SKEW).unwrap(),
+            "Your clock appears to be 360 seconds behind the server"
+        );
+        assert_eq!(
+            message(TimeDelta::minutes(20), OBVIOUS_SKEW).unwrap(),
+            "Your clock appears to be 1200 seconds ahead of the server, which is more than the 15 minutes AWS accepts for the signatures"
+        );
+    }
+
+    #[test]
+    fn time_errors() {
+        let none = HeaderMap::new();
+        let s3 = b"<Error><Code>RequestTimeTooSkewed</Code></Error>";
+        assert!(time_error(StatusCode::FORBIDDEN, &none, s3));
+        let api_gateway = br#"{"message":"Signature expired: 20130524T000000Z is now earlier than 20130524T000500Z"}"#;
+        assert!(time_error(StatusCode::FORBIDDEN, &none, api_gateway));
+        let headers = [(
+            "x-amzn-errortype".parse().unwrap(),
+            "InvalidSignatureException:".parse().unwrap(),
+        )]
+        .into_iter()
+        .collect::<HeaderMap>();
+        assert!(time_error(StatusCode::BAD_REQUEST, &headers, b""));
+        assert!(!time_error(StatusCode::FORBIDDEN, &none, b"AccessDenied"));
+        assert!(!time_error(StatusCode::INTERNAL_SERVER_ERROR, &none, s3));
+    }
+}
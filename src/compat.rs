@@ -0,0 +1,192 @@
+//! Signing for the services outside AWS which implement SigV4 (`--compat`)
+//!
+//! A preset gives the default service and region, which are not told by the
+//! host, and the details of the canonical request which such a service expects.
+//! Each of them can be overridden by its own option (`--uri-encoding`,
+//! `--normalize-path` and `--host-without-port`), so that a new service does not
+//! need a new preset.
+
+use aws_sigv4::http_request::{PercentEncodingMode, SigningSettings};
+use clap::ValueEnum;
+
+use crate::Args;
+
+/// The region which the services outside AWS commonly accept.
+const DEFAULT_REGION: &str = "us-east-1";
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Preset {
+    /// MinIO and the other S3-compatible storage: s3, and the path signed as it is
+    Minio,
+    /// The plain SigV4 of the AWS test suite, without x-amz-content-sha256
+    Generic,
+}
+
+/// How the path is percent-encoded in the canonical request.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum UriEncoding {
+    /// Encode the path once, same as S3
+    Single,
+    /// Encode the encoded path again, same as the other AWS services
+    Double,
+}
+
+impl Preset {
+    fn service(self) -> Option<&'static str> {
+        match self {
+            Preset::Minio => Some("s3"),
+            Preset::Generic => None,
+        }
+    }
+
+    fn uri_encoding(self) -> UriEncoding {
+        match self {
+            Preset::Minio => UriEncoding::Single,
+            Preset::Generic => UriEncoding::Double,
+        }
+    }
+
+    fn normalize_path(self) -> bool {
+        match self {
+            Preset::Minio => false,
+            Preset::Generic => true,
+        }
+    }
+}
+
+/// Set the defaults of --compat to ARGS, which are overridden by the options.
+pub fn install(args: &mut Args) {
+    let Some(preset) = args.compat else {
+        return;
+    };
+    if let Some(service) = preset.service() {
+        args.service.get_or_insert_with(|| service.to_string());
+    }
+    if preset == Preset::Generic {
+        args.no_content_sha256 = true;
+    }
+}
+
+/// The region of --compat, used when neither --region nor the environment
+/// gives one.
+pub fn region(args: &Args) -> Option<&'static str> {
+    args.compat.map(|_| DEFAULT_REGION)
+}
+
+/// Apply --compat, --uri-encoding and --normalize-path to SETTINGS.
+pub fn configure(args: &Args, settings: &mut SigningSettings) {
+    let uri_encoding = args.uri_encoding.or(args.compat.map(Preset::uri_encoding));
+    match uri_encoding {
+        Some(UriEncoding::Single) => settings.percent_encoding_mode = PercentEncodingMode::Single,
+        Some(UriEncoding::Double) => settings.percent_encoding_mode = PercentEncodingMode::Double,
+        None => {}
+    }
+    let normalize_path = args
+        .normalize_path
+        .or(args.compat.map(Preset::normalize_path));
+    if let Some(normalize_path) = normalize_path {
+        settings.uri_path_normalization_mode = normalize_path.into();
+    }
+}
+
+/// The Host header of --host-without-port for URL, which is signed and sent
+/// instead of the one with the port.
+pub fn host_header(args: &Args, url: &str) -> Option<String> {
+    if !args.host_without_port {
+        return None;
+    }
+    reqwest::Url::parse(url)
+        .ok()?
+        .host_str()
+        .map(|host| host.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sigv4::http_request::{PercentEncodingMode, SigningSettings, UriPathNormalizationMode};
+    use clap::Parser;
+
+    use super::{configure, host_header, install, region};
+    use crate::Args;
+
+    fn parse(options: &[&str]) -> Args {
+        let mut args = Args::parse_from(
+            ["awscurl", "http://localhost:9000/b/k"]
+                .iter()
+                .chain(options),
+        );
+        install(&mut args);
+        args
+    }
+
+    fn configured(args: &Args) -> SigningSettings {
+        let mut settings = SigningSettings::default();
+        configure(args, &mut settings);
+        settings
+    }
+
+    #[test]
+    fn minio_preset() {
+        let args = parse(&["--compat", "minio"]);
+        assert_eq!(args.service.as_deref(), Some("s3"));
+        assert_eq!(region(&args), Some("us-east-1"));
+        assert!(!args.no_content_sha256);
+        let settings = configured(&args);
+        assert_eq!(settings.percent_encoding_mode, PercentEncodingMode::Single);
+        assert_eq!(
+            settings.uri_path_normalization_mode,
+            UriPathNormalizationMode::Disabled
+        );
+
+        let args = parse(&["--compat", "minio", "--service", "custom"]);
+        assert_eq!(args.service.as_deref(), Some("custom"));
+    }
+
+    #[test]
+    fn generic_preset() {
+        let args = parse(&["--compat", "generic", "--service", "gateway"]);
+        assert_eq!(args.service.as_deref(), Some("gateway"));
+        assert!(args.no_content_sha256);
+        let settings = configured(&args);
+        assert_eq!(settings.percent_encoding_mode, PercentEncodingMode::Double);
+        assert_eq!(
+            settings.uri_path_normalization_mode,
+            UriPathNormalizationMode::Enabled
+        );
+    }
+
+    #[test]
+    fn override_preset() {
+        let args = parse(&[
+            "--compat",
+            "minio",
+            "--uri-encoding",
+            "double",
+            "--normalize-path",
+            "true",
+        ]);
+        let settings = configured(&args);
+        assert_eq!(settings.percent_encoding_mode, PercentEncodingMode::Double);
+        assert_eq!(
+            settings.uri_path_normalization_mode,
+            UriPathNormalizationMode::Enabled
+        );
+
+        let args = parse(&["--uri-encoding", "single"]);
+        assert_eq!(region(&args), None);
+        assert_eq!(
+            configured(&args).percent_encoding_mode,
+            PercentEncodingMode::Single
+        );
+    }
+
+    #[test]
+    fn host_without_port() {
+        let args = parse(&["--host-without-port"]);
+        assert_eq!(
+            host_header(&args, "http://localhost:9000/b/k").as_deref(),
+            Some("localhost")
+        );
+        assert_eq!(host_header(&parse(&[]), "http://localhost:9000/b/k"), None);
+    }
+}
@@ -0,0 +1,67 @@
+//! Locations of the files awscurl keeps between invocations, and the settings
+//! in config.toml.
+
+use std::{collections::BTreeMap, env, fs, io, path::PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::environment::Environment;
+
+/// Directory for awscurl's own files.
+///
+/// `$XDG_CONFIG_HOME/awscurl` if set, otherwise `~/.config/awscurl`.
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir).join("awscurl"));
+    }
+    env::var_os("HOME")
+        .filter(|d| !d.is_empty())
+        .map(|home| PathBuf::from(home).join(".config").join("awscurl"))
+}
+
+/// The settings in config.toml of the config directory.
+#[derive(Deserialize, Debug, Default)]
+pub struct Settings {
+    /// The environments of --env
+    #[serde(default)]
+    pub env: BTreeMap<String, Environment>,
+}
+
+pub fn settings_path() -> anyhow::Result<PathBuf> {
+    let dir = config_dir().context("Unable to decide the config directory")?;
+    Ok(dir.join("config.toml"))
+}
+
+/// The path and the contents of config.toml, or None if it does not exist.
+fn read() -> anyhow::Result<Option<(PathBuf, String)>> {
+    let path = settings_path()?;
+    match fs::read_to_string(&path) {
+        Ok(raw) => Ok(Some((path, raw))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Unable to read {}", path.display())),
+    }
+}
+
+/// The settings of config.toml, which may not exist.
+pub fn load() -> anyhow::Result<Settings> {
+    let Some((path, raw)) = read()? else {
+        return Ok(Settings::default());
+    };
+    toml::from_str(&raw).with_context(|| format!("Invalid config {}", path.display()))
+}
+
+/// confirm_mutations of config.toml, which asks before sending DELETE, PUT,
+/// POST and PATCH as with --confirm. It is read apart from the settings, so
+/// that an invalid environment does not affect it.
+pub fn confirm_mutations() -> anyhow::Result<bool> {
+    let Some((path, raw)) = read()? else {
+        return Ok(false);
+    };
+    let table = toml::from_str::<toml::Table>(&raw)
+        .with_context(|| format!("Invalid config {}", path.display()))?;
+    Ok(table
+        .get("confirm_mutations")
+        .and_then(toml::Value::as_bool)
+        .unwrap_or(false))
+}
@@ -0,0 +1,138 @@
+//! Asking before sending the request which changes things (`--confirm`)
+//!
+//! DELETE, PUT, POST and PATCH are sent only after `y` is typed at the prompt,
+//! which shows the method, the URL, the URL of --mirror which is sent as well,
+//! and the principal which signed the request.
+//! The prompt comes after signing, so that --verbose has printed the request,
+//! and nothing is sent before the answer. --multipart-upload asks once before
+//! the upload is initiated, and `send` of --interactive asks for every request.
+//! Without a terminal, the request fails unless --yes is given.
+
+use std::io::{self, IsTerminal};
+
+use anyhow::bail;
+use aws_credential_types::Credentials;
+
+use crate::{config, editor, logging, Args};
+
+/// The methods which are confirmed.
+const METHODS: [&str; 4] = ["DELETE", "PUT", "POST", "PATCH"];
+
+/// Whether the request of METHOD is confirmed, by --confirm or by
+/// confirm_mutations in config.toml. The config which can not be read is
+/// warned about, and does not fail the request.
+pub fn required(args: &Args, method: &str) -> bool {
+    if args.yes || args.dry_run || !METHODS.contains(&method) {
+        return false;
+    }
+    if args.confirm {
+        return true;
+    }
+    config::confirm_mutations().unwrap_or_else(|e| {
+        logging::warning(format_args!("{:#}, so confirm_mutations is ignored", e));
+        false
+    })
+}
+
+/// The access key of CREDENTIALS, with the source of --credential-source of
+/// ARGS which gave it (Ex. `AKIDEXAMPLE (the profile dev)`). The provider of
+/// the default chain is not told by the credentials.
+pub fn principal(credentials: &Credentials, args: &Args) -> String {
+    match args.credential_source {
+        Some(source) => format!(
+            "{} ({})",
+            credentials.access_key_id(),
+            source.describe(args)
+        ),
+        None => credentials.access_key_id().to_string(),
+    }
+}
+
+fn prompt(method: &str, url: &str, mirror: Option<&str>, principal: Option<&str>) -> String {
+    let mirror = mirror
+        .map(|mirror| format!("Mirror: {} {}\n", method, mirror))
+        .unwrap_or_default();
+    format!(
+        "{} {}\n{}Principal: {}\nSend it? [y/N] ",
+        method,
+        url,
+        mirror,
+        principal.unwrap_or("unknown (not signed)")
+    )
+}
+
+/// Ask whether the request of METHOD to URL, and to MIRROR if it is given, is
+/// sent.
+pub fn ask(
+    method: &str,
+    url: &str,
+    mirror: Option<&str>,
+    principal: Option<&str>,
+) -> anyhow::Result<()> {
+    if !io::stdin().is_terminal() {
+        bail!(
+            "{} {} is not sent without the confirmation, and stdin is not a terminal to ask it. Pass --yes to send it",
+            method,
+            url
+        );
+    }
+    if !editor::confirm(&prompt(method, url, mirror, principal))? {
+        bail!("{} {} is not sent", method, url);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_credential_types::Credentials;
+    use clap::Parser;
+
+    use super::{principal, prompt, required};
+    use crate::Args;
+
+    #[test]
+    fn principals() {
+        let credentials = Credentials::new("AKIDEXAMPLE", "secret", None, None, "Environment");
+        let args = |options: &[&str]| {
+            Args::parse_from([&["awscurl", "https://example.com"], options].concat())
+        };
+        assert_eq!(principal(&credentials, &args(&[])), "AKIDEXAMPLE");
+        assert_eq!(
+            principal(
+                &credentials,
+                &args(&["--credential-source", "profile", "--profile", "dev"])
+            ),
+            "AKIDEXAMPLE (the profile dev)"
+        );
+        assert_eq!(
+            prompt(
+                "DELETE",
+                "https://example.com/key",
+                None,
+                Some("AKIDEXAMPLE (Environment)")
+            ),
+            "DELETE https://example.com/key\nPrincipal: AKIDEXAMPLE (Environment)\nSend it? [y/N] "
+        );
+        assert_eq!(
+            prompt(
+                "PUT",
+                "https://example.com/key",
+                Some("https://new.example.com/key"),
+                None
+            ),
+            "PUT https://example.com/key\nMirror: PUT https://new.example.com/key\nPrincipal: unknown (not signed)\nSend it? [y/N] "
+        );
+    }
+
+    #[test]
+    fn required_methods() {
+        let args = |options: &[&str]| {
+            Args::parse_from([&["awscurl", "https://example.com", "--confirm"], options].concat())
+        };
+        assert!(required(&args(&[]), "DELETE"));
+        assert!(required(&args(&[]), "PATCH"));
+        assert!(!required(&args(&[]), "GET"));
+        assert!(!required(&args(&["--yes"]), "PUT"));
+        assert!(!required(&args(&["--dry-run"]), "POST"));
+    }
+}
@@ -0,0 +1,168 @@
+//! Combinations of the options which clap accepts but which do not make sense
+//!
+//! Without this, one of the options would be silently ignored. The message says
+//! why the combination is rejected and what to use instead.
+
+use std::path::Path;
+
+use crate::{endpoint, Args, SignHost};
+
+/// The explanation of the first incoherent combination in ARGS.
+pub fn find(args: &Args) -> Option<&'static str> {
+    let head = args.method.as_deref() == Some("HEAD");
+    let binary = args.data_hex.is_some()
+        || args.data_base64.is_some()
+        || args.upload_file.is_some()
+        || !args.form.is_empty()
+        || !args.form_string.is_empty();
+    let has_output_file =
+        args.output.is_some() || args.remote_name || args.output_template.is_some();
+    let put = match args.method.as_deref() {
+        Some(method) => method == "PUT",
+        None => args.upload_file.is_some(),
+    };
+    let rules = [
+        (
+            head && args.output_base64,
+            "the response to -X HEAD has no body for --output-base64. Use --output-format json to print the status and the headers",
+        ),
+        (
+            args.validate_json && binary,
+            "--validate-json checks only the body of --data. Drop it for --upload-file, --data-hex, --data-base64 and -F",
+        ),
+        (
+            args.remote_time && !has_output_file,
+            "--remote-time sets the modification time of the output file. Add --output FILE or --remote-name",
+        ),
+        (
+            args.upload_verify && !put,
+            "--upload-verify checks the object uploaded by PUT. Use -T FILE or -X PUT",
+        ),
+        (
+            args.append && !has_output_file,
+            "--append applies to the output file, and stdout is written as usual. Add --output FILE or --remote-name",
+        ),
+        (
+            args.stderr_append && args.stderr.is_none() && args.log_file.is_none(),
+            "--stderr-append applies to the file of --stderr or --log-file. Add one of them",
+        ),
+        (
+            args.keep_partial && !has_output_file,
+            "--keep-partial keeps the partial output file, while the partial body on stdout is always printed. Add --output FILE or --remote-name",
+        ),
+        (
+            (args.show_secrets || !args.redact_header.is_empty())
+                && !args.verbose
+                && !args.interactive,
+            "--show-secrets and --redact-header apply to the headers printed by --verbose. Add --verbose",
+        ),
+        (
+            args.etag_compare.is_some() && args.cache_dir.is_some(),
+            "--etag-compare would override the ETag kept in --cache-dir. Use --cache-dir alone, which sends If-None-Match by itself",
+        ),
+        (
+            args.sign_host == SignHost::Original && endpoint::resolve(args).is_none(),
+            "--sign-host original applies only to the requests sent to another endpoint. Add --endpoint-url or set AWS_ENDPOINT_URL",
+        ),
+        (
+            args.multipart_upload && args.retry > 0,
+            "--multipart-upload retries the failed parts by itself, and --retry is not used. Drop --retry",
+        ),
+        (
+            args.retry_until_status.is_some()
+                && args.unsigned_payload
+                && args.upload_file.as_deref() == Some(Path::new("-")),
+            "--retry-until-status sends the body again, while the body streamed from stdin is read only once. Drop --unsigned-payload to read stdin up front",
+        ),
+    ];
+    rules
+        .into_iter()
+        .find(|(given, _)| *given)
+        .map(|(_, message)| message)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::find;
+    use crate::Args;
+
+    #[test]
+    fn incoherent_combinations() {
+        let cases: [(&[&str], &str); 12] = [
+            (
+                &["-X", "HEAD", "--output-base64"],
+                "the response to -X HEAD has no body",
+            ),
+            (
+                &["--validate-json", "--data-hex", "00"],
+                "--validate-json checks only",
+            ),
+            (
+                &["--remote-time"],
+                "--remote-time sets the modification time",
+            ),
+            (
+                &["-d", "a=1", "--upload-verify"],
+                "--upload-verify checks the object uploaded by PUT",
+            ),
+            (&["--append"], "--append applies to the output file"),
+            (&["--stderr-append"], "--stderr-append applies to the file"),
+            (
+                &["--keep-partial"],
+                "--keep-partial keeps the partial output file",
+            ),
+            (
+                &["--show-secrets"],
+                "--show-secrets and --redact-header apply",
+            ),
+            (
+                &["--etag-compare", "etag", "--cache-dir", "cache"],
+                "--etag-compare would override",
+            ),
+            (
+                &["--sign-host", "original"],
+                "--sign-host original applies only",
+            ),
+            (
+                &["-T", "file", "--multipart-upload", "--retry", "3"],
+                "--multipart-upload retries the failed parts",
+            ),
+            (
+                &[
+                    "-T",
+                    "-",
+                    "--unsigned-payload",
+                    "--retry-until-status",
+                    "200",
+                ],
+                "--retry-until-status sends the body again",
+            ),
+        ];
+        for (options, expected) in cases {
+            let args = Args::parse_from(["awscurl", "https://example.com"].iter().chain(options));
+            let message = find(&args).unwrap_or_else(|| panic!("{:?} is accepted", options));
+            assert!(message.starts_with(expected), "{:?}: {}", options, message);
+        }
+    }
+
+    #[test]
+    fn coherent_combinations() {
+        let cases: [&[&str]; 9] = [
+            &["-X", "POST", "-d", "a=1"],
+            &["-X", "HEAD", "--output-format", "json"],
+            &["-X", "HEAD", "-d", "a=1"],
+            &["-o", "out", "--remote-time", "--keep-partial", "--append"],
+            &["--verbose", "--show-secrets"],
+            &["--interactive", "--redact-header", "x-secret"],
+            &["-T", "file", "--upload-verify"],
+            &["--log-file", "log", "--stderr-append"],
+            &["-T", "-", "--retry-until-status", "200"],
+        ];
+        for options in cases {
+            let args = Args::parse_from(["awscurl", "https://example.com"].iter().chain(options));
+            assert_eq!(find(&args), None, "{:?}", options);
+        }
+    }
+}
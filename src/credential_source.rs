@@ -0,0 +1,172 @@
+//! Credentials from a single source (`--credential-source`)
+//!
+//! The default chain of the SDK takes the first source which has credentials,
+//! so stale environment variables win over the intended profile silently. With
+//! --credential-source, only the provider of the source is built, and its
+//! failure is reported with the source instead of falling through to the next
+//! one.
+
+use aws_config::{
+    credential_process::CredentialProcessProvider, ecs::EcsCredentialsProvider,
+    environment::EnvironmentVariableCredentialsProvider,
+    imds::credentials::ImdsCredentialsProvider, profile::ProfileFileCredentialsProvider,
+};
+use aws_credential_types::{
+    credential_fn::provide_credentials_fn,
+    provider::{error::CredentialsError, SharedCredentialsProvider},
+};
+use clap::ValueEnum;
+
+use crate::{imds, Args};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    /// AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and AWS_SESSION_TOKEN
+    Env,
+    /// The profile of --profile or AWS_PROFILE in ~/.aws/config and ~/.aws/credentials
+    Profile,
+    /// The instance metadata service of EC2
+    Imds,
+    /// The container endpoint of ECS and EKS Pod Identity
+    Ecs,
+    /// The command of --credential-command, same as credential_process
+    Process,
+}
+
+impl Source {
+    /// The source in the messages (Ex. "the profile dev").
+    pub fn describe(self, args: &Args) -> String {
+        match self {
+            Source::Env => "the environment variables".to_string(),
+            Source::Profile => match &args.profile {
+                Some(profile) => format!("the profile {}", profile),
+                None => "the default profile".to_string(),
+            },
+            Source::Imds => "the instance metadata service".to_string(),
+            Source::Ecs => "the container endpoint".to_string(),
+            Source::Process => match &args.credential_command {
+                Some(command) => format!("the command `{}`", command),
+                None => "the command".to_string(),
+            },
+        }
+    }
+
+    /// The message of the failure E, with the reason which the provider does
+    /// not tell when it finds nothing to load.
+    pub fn failure(self, args: &Args, e: &CredentialsError) -> String {
+        let message = format!("Unable to get credentials from {}", self.describe(args));
+        let reason = match self {
+            Source::Env => "AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY are not set",
+            Source::Profile => "the profile is not found, or has no credentials",
+            Source::Imds => "the instance metadata service is disabled",
+            Source::Ecs => "AWS_CONTAINER_CREDENTIALS_RELATIVE_URI and AWS_CONTAINER_CREDENTIALS_FULL_URI are not set",
+            Source::Process => return message,
+        };
+        match e {
+            CredentialsError::CredentialsNotLoaded(_) => format!("{}: {}", message, reason),
+            _ => message,
+        }
+    }
+}
+
+/// The provider of --credential-source, which replaces the default chain.
+pub fn provider(args: &Args) -> Option<SharedCredentialsProvider> {
+    let provider = match args.credential_source? {
+        Source::Env => {
+            SharedCredentialsProvider::new(EnvironmentVariableCredentialsProvider::new())
+        }
+        Source::Profile => {
+            let mut builder = ProfileFileCredentialsProvider::builder();
+            if let Some(profile) = &args.profile {
+                builder = builder.profile_name(profile);
+            }
+            SharedCredentialsProvider::new(builder.build())
+        }
+        Source::Imds if imds::disabled(args) => {
+            SharedCredentialsProvider::new(provide_credentials_fn(|| async {
+                Err(CredentialsError::not_loaded(
+                    "the instance metadata service is disabled",
+                ))
+            }))
+        }
+        Source::Imds => SharedCredentialsProvider::new(
+            ImdsCredentialsProvider::builder()
+                .imds_client(imds::client(args))
+                .build(),
+        ),
+        Source::Ecs => SharedCredentialsProvider::new(EcsCredentialsProvider::builder().build()),
+        Source::Process => SharedCredentialsProvider::new(CredentialProcessProvider::new(
+            args.credential_command.clone().unwrap_or_default(),
+        )),
+    };
+    Some(provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_credential_types::provider::{error::CredentialsError, ProvideCredentials};
+    use clap::Parser;
+
+    use super::provider;
+    use crate::Args;
+
+    #[tokio::test]
+    async fn process() {
+        let args = Args::parse_from([
+            "awscurl",
+            "https://example.com",
+            "--credential-source",
+            "process",
+            "--credential-command",
+            r#"echo '{"Version": 1, "AccessKeyId": "AKIDPROCESS", "SecretAccessKey": "secret"}'"#,
+        ]);
+        let credentials = provider(&args)
+            .unwrap()
+            .provide_credentials()
+            .await
+            .unwrap();
+        assert_eq!(credentials.access_key_id(), "AKIDPROCESS");
+        assert_eq!(credentials.secret_access_key(), "secret");
+    }
+
+    #[test]
+    fn default_chain() {
+        let args = Args::parse_from(["awscurl", "https://example.com"]);
+        assert!(provider(&args).is_none());
+    }
+
+    #[test]
+    fn failure() {
+        let args = Args::parse_from([
+            "awscurl",
+            "https://example.com",
+            "--credential-source",
+            "env",
+        ]);
+        let source = args.credential_source.unwrap();
+        assert_eq!(
+            source.failure(&args, &CredentialsError::not_loaded("unset")),
+            "Unable to get credentials from the environment variables: AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY are not set"
+        );
+        assert_eq!(
+            source.failure(&args, &CredentialsError::provider_error("invalid")),
+            "Unable to get credentials from the environment variables"
+        );
+    }
+
+    #[test]
+    fn describe() {
+        let args = Args::parse_from([
+            "awscurl",
+            "https://example.com",
+            "--credential-source",
+            "profile",
+            "--profile",
+            "dev",
+        ]);
+        assert_eq!(
+            args.credential_source.unwrap().describe(&args),
+            "the profile dev"
+        );
+    }
+}
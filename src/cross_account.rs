@@ -0,0 +1,131 @@
+//! The headers of the cross-account access to S3 (`--expected-bucket-owner`
+//! and `--requester-pays`)
+//!
+//! The headers are added before the request is signed, so that they are in
+//! SignedHeaders. They are only for S3, and ignored with a warning for the
+//! other services.
+
+use crate::{logging, AwsCurlParam};
+
+pub const EXPECTED_BUCKET_OWNER_HEADER: &str = "x-amz-expected-bucket-owner";
+pub const REQUEST_PAYER_HEADER: &str = "x-amz-request-payer";
+
+/// The services which take the headers.
+const SERVICES: [&str; 2] = ["s3", "s3express"];
+
+/// Parse RAW of --expected-bucket-owner, which is the 12 digits of an account.
+pub fn parse_account_id(raw: &str) -> Result<String, String> {
+    if raw.len() != 12 || !raw.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!(
+            "expected the 12 digits of an AWS account id: {}",
+            raw
+        ));
+    }
+    Ok(raw.to_string())
+}
+
+/// Add the headers of --expected-bucket-owner and --requester-pays to PARAM,
+/// unless -H gives them.
+pub fn install(param: &mut AwsCurlParam) -> anyhow::Result<()> {
+    let mut headers = Vec::new();
+    if let Some(owner) = &param.args.expected_bucket_owner {
+        headers.push((
+            "--expected-bucket-owner",
+            EXPECTED_BUCKET_OWNER_HEADER,
+            owner.clone(),
+        ));
+    }
+    if param.args.requester_pays {
+        headers.push((
+            "--requester-pays",
+            REQUEST_PAYER_HEADER,
+            "requester".to_string(),
+        ));
+    }
+    if headers.is_empty() {
+        return Ok(());
+    }
+    if !SERVICES.contains(&param.service()) {
+        for (option, _, _) in headers {
+            logging::warning(format_args!(
+                "{} is ignored, since it is only for S3, not {}",
+                option,
+                param.service()
+            ));
+        }
+        return Ok(());
+    }
+    let given = param
+        .headers()?
+        .into_keys()
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>();
+    for (option, name, value) in headers {
+        if given.iter().any(|given| given == name) {
+            logging::warning(format_args!(
+                "{} is not added, since -H gives {}",
+                option, name
+            ));
+            continue;
+        }
+        param.args.header.push(format!("{}: {}", name, value));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_config::SdkConfig;
+    use clap::Parser;
+
+    use super::{install, parse_account_id};
+    use crate::{Args, AwsCurlParam};
+
+    fn headers(options: &[&str]) -> Vec<String> {
+        let args = Args::parse_from(
+            [
+                &["awscurl", "https://examplebucket.s3.amazonaws.com/key"],
+                options,
+            ]
+            .concat(),
+        );
+        let mut param = AwsCurlParam::new(args, SdkConfig::builder().build());
+        install(&mut param).unwrap();
+        param.args.header
+    }
+
+    #[test]
+    fn account_ids() {
+        assert_eq!(parse_account_id("111122223333").unwrap(), "111122223333");
+        assert!(parse_account_id("11112222333").is_err());
+        assert!(parse_account_id("1111-2222-3333").is_err());
+    }
+
+    #[test]
+    fn install_headers() {
+        assert_eq!(
+            headers(&[
+                "--service",
+                "s3",
+                "--expected-bucket-owner",
+                "111122223333",
+                "--requester-pays"
+            ]),
+            [
+                "x-amz-expected-bucket-owner: 111122223333",
+                "x-amz-request-payer: requester"
+            ]
+        );
+        assert_eq!(
+            headers(&[
+                "--service",
+                "s3",
+                "-H",
+                "X-Amz-Request-Payer: requester",
+                "--requester-pays"
+            ]),
+            ["X-Amz-Request-Payer: requester"]
+        );
+        assert!(headers(&["--requester-pays"]).is_empty());
+    }
+}
@@ -0,0 +1,249 @@
+//! Request body given by `--data`, `--data-urlencode`, `--data-hex` and `--data-base64`
+//!
+//! Same as curl, the values of `--data`, `--data-urlencode` and `--expand-data`
+//! are joined with "&" in the order they are given. `--data-hex` and `--data-base64` give the binary
+//! body as it is. `--validate-json` checks the body before it is signed.
+
+use std::{fs, io::Read};
+
+use anyhow::{anyhow, bail, Context};
+use base64::{prelude::BASE64_STANDARD, DecodeError, Engine};
+use clap::ArgMatches;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::{body_size, variable::Variables};
+
+/// The number of characters around the error printed by `--validate-json`.
+const SNIPPET_WIDTH: usize = 60;
+
+/// Characters which are encoded by `--data-urlencode`. Only the unreserved
+/// characters of RFC 3986 are left as they are.
+pub const URLENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Resolve the data options in the order they are given on the command line.
+/// `--expand-data` is expanded with VARIABLES before it is read like `--data`.
+/// stdin longer than LIMIT of --max-inline-body fails while it is read.
+pub fn collect(
+    matches: &ArgMatches,
+    variables: &Variables,
+    limit: Option<u64>,
+) -> anyhow::Result<Vec<String>> {
+    let mut parts = Vec::new();
+    for id in ["data", "data_urlencode", "expand_data"] {
+        let (Some(values), Some(indices)) =
+            (matches.get_many::<String>(id), matches.indices_of(id))
+        else {
+            continue;
+        };
+        for (value, index) in values.zip(indices) {
+            let part = match id {
+                "data_urlencode" => urlencode(value, limit)?,
+                "expand_data" => read_data(&variables.expand(value)?, limit)?,
+                _ => read_data(value, limit)?,
+            };
+            parts.push((index, part));
+        }
+    }
+    parts.sort_by_key(|(index, _)| *index);
+    Ok(parts.into_iter().map(|(_, part)| part).collect())
+}
+
+/// `--data` value. "@FILE" is replaced with the contents of FILE ("-" for
+/// stdin) without carriage returns and newlines, which is same as curl.
+pub fn read_data(raw: &str, limit: Option<u64>) -> anyhow::Result<String> {
+    let Some(path) = raw.strip_prefix('@') else {
+        return Ok(raw.to_string());
+    };
+    Ok(read_body_file(path, limit)?.replace(['\r', '\n'], ""))
+}
+
+/// `--data-urlencode` value, which is one of "content", "=content",
+/// "name=content", "@FILE" and "name@FILE".
+pub fn urlencode(raw: &str, limit: Option<u64>) -> anyhow::Result<String> {
+    let encode = |content: &str| utf8_percent_encode(content, URLENCODE).to_string();
+    Ok(match raw.find(['=', '@']) {
+        Some(0) if raw.starts_with('=') => encode(&raw[1..]),
+        Some(0) => encode(&read_body_file(&raw[1..], limit)?),
+        Some(i) if raw[i..].starts_with('=') => format!("{}={}", &raw[..i], encode(&raw[i + 1..])),
+        Some(i) => format!(
+            "{}={}",
+            &raw[..i],
+            encode(&read_body_file(&raw[i + 1..], limit)?)
+        ),
+        None => encode(raw),
+    })
+}
+
+/// `--data-hex` value. Whitespace is ignored, and an invalid digit is reported
+/// with its offset.
+pub fn decode_hex(raw: &str) -> anyhow::Result<Vec<u8>> {
+    let digits = raw
+        .char_indices()
+        .filter(|(_, c)| !c.is_ascii_whitespace())
+        .map(|(offset, c)| match c.to_digit(16) {
+            Some(digit) => Ok(digit as u8),
+            None => bail!("Invalid hex digit {:?} at offset {}", c, offset),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if digits.len() % 2 != 0 {
+        bail!("Odd number of hex digits: {}", digits.len());
+    }
+    Ok(digits
+        .chunks(2)
+        .map(|pair| pair[0] << 4 | pair[1])
+        .collect())
+}
+
+/// `--data-base64` value, with the padding.
+pub fn decode_base64(raw: &str) -> anyhow::Result<Vec<u8>> {
+    BASE64_STANDARD.decode(raw.trim()).map_err(|e| match e {
+        DecodeError::InvalidByte(offset, byte) => {
+            anyhow!(
+                "Invalid base64 character {:?} at offset {}",
+                byte as char,
+                offset
+            )
+        }
+        e => anyhow!("Invalid base64: {}", e),
+    })
+}
+
+/// Check that BODY is JSON (`--validate-json`). The error has the position of
+/// the syntax error and the line with a caret under it.
+pub fn validate_json(body: &str) -> anyhow::Result<()> {
+    let e = match serde_json::from_str::<serde::de::IgnoredAny>(body) {
+        Ok(_) => return Ok(()),
+        Err(e) => e,
+    };
+    let line = body.lines().nth(e.line().saturating_sub(1)).unwrap_or("");
+    let chars = line.chars().collect::<Vec<_>>();
+    let column = e.column().saturating_sub(1).min(chars.len());
+    let start = column.saturating_sub(SNIPPET_WIDTH / 2);
+    let end = (start + SNIPPET_WIDTH).min(chars.len());
+    bail!(
+        "Invalid JSON body: {}\n  {}\n  {}^",
+        e,
+        chars[start..end].iter().collect::<String>(),
+        " ".repeat(column - start)
+    )
+}
+
+/// The body in the file at PATH, or in stdin for "-" which fails as soon as it
+/// exceeds LIMIT.
+pub fn read_body_file(path: &str, limit: Option<u64>) -> anyhow::Result<String> {
+    if path == "-" {
+        return String::from_utf8(body_size::read_stdin(limit)?)
+            .context("stdin did not contain valid UTF-8");
+    }
+    read_file(path)
+}
+
+/// The contents of the file at PATH, or stdin for "-".
+pub fn read_file(path: &str) -> anyhow::Result<String> {
+    if path == "-" {
+        let mut ret = String::new();
+        std::io::stdin().read_to_string(&mut ret)?;
+        return Ok(ret);
+    }
+    fs::read_to_string(path).with_context(|| format!("Unable to read {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::{collect, decode_base64, decode_hex, read_data, urlencode, validate_json};
+    use crate::{variable::Variables, Args};
+
+    #[test]
+    fn keep_the_given_order() {
+        let matches = Args::command().get_matches_from([
+            "awscurl",
+            "https://example.com",
+            "-d",
+            "Action=SendMessage",
+            "--data-urlencode",
+            "MessageBody=hello world",
+            "-d",
+            "Version=2012-11-05",
+        ]);
+        assert_eq!(
+            collect(&matches, &Variables::default(), None).unwrap(),
+            [
+                "Action=SendMessage",
+                "MessageBody=hello%20world",
+                "Version=2012-11-05"
+            ]
+        );
+    }
+
+    #[test]
+    fn urlencode_forms() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"a&b").unwrap();
+        let path = file.path().to_str().unwrap();
+
+        assert_eq!(urlencode("a b", None).unwrap(), "a%20b");
+        assert_eq!(urlencode("=a=b", None).unwrap(), "a%3Db");
+        assert_eq!(urlencode("name=a=b", None).unwrap(), "name=a%3Db");
+        assert_eq!(urlencode(&format!("@{}", path), None).unwrap(), "a%26b");
+        assert_eq!(
+            urlencode(&format!("name@{}", path), None).unwrap(),
+            "name=a%26b"
+        );
+    }
+
+    #[test]
+    fn data_from_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"a=1\r\n&b=2\n").unwrap();
+        let raw = format!("@{}", file.path().display());
+        assert_eq!(read_data(&raw, None).unwrap(), "a=1&b=2");
+        assert_eq!(read_data("a=1", None).unwrap(), "a=1");
+    }
+
+    #[test]
+    fn decode_binary() {
+        assert_eq!(
+            decode_hex("deadBEEF 00").unwrap(),
+            [0xde, 0xad, 0xbe, 0xef, 0x00]
+        );
+        assert_eq!(
+            decode_hex("dead-beef").unwrap_err().to_string(),
+            "Invalid hex digit '-' at offset 4"
+        );
+        assert_eq!(
+            decode_hex("abc").unwrap_err().to_string(),
+            "Odd number of hex digits: 3"
+        );
+        assert_eq!(decode_base64("AQID\n").unwrap(), [1, 2, 3]);
+        assert_eq!(
+            decode_base64("AQ*D").unwrap_err().to_string(),
+            "Invalid base64 character '*' at offset 2"
+        );
+    }
+
+    #[test]
+    fn invalid_json() {
+        assert!(validate_json("{\"a\": [1, 2]}").is_ok());
+        assert_eq!(
+            validate_json("{\n  \"a\": 1\n  \"b\": 2\n}")
+                .unwrap_err()
+                .to_string(),
+            "Invalid JSON body: expected `,` or `}` at line 3 column 3\n    \"b\": 2\n    ^"
+        );
+        let long = format!("[{}x]", "1, ".repeat(40));
+        assert_eq!(
+            validate_json(&long).unwrap_err().to_string(),
+            format!(
+                "Invalid JSON body: expected value at line 1 column 122\n  {}\n  {}^",
+                &long[91..],
+                " ".repeat(30)
+            )
+        );
+    }
+}
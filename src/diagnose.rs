@@ -0,0 +1,396 @@
+//! Diagnosis of the connection when the request fails (`--diagnose`)
+//!
+//! What dig, nc and openssl s_client would tell is printed after a failure at
+//! the transport level: the addresses of the host, the TCP connection to each of
+//! them, the TLS handshake with the certificate of the server, and whether the
+//! server responds to an unsigned HEAD. The resolution and the handshake use
+//! --resolve, --dns-servers, --ipv4/--ipv6 and the TLS options of the request.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use reqwest::dns::{Name, Resolve};
+use rustls::{
+    client::{
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        WebPkiServerVerifier,
+    },
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use tokio::net::TcpStream;
+
+use crate::{client, dns::Resolver, logging, retry::Failure, tls, Args};
+
+/// The timeout of each step.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether E is a failure which the diagnosis can tell about. The errors of
+/// native TLS are known only as the ones of connecting.
+pub fn applies(e: &anyhow::Error) -> bool {
+    let connect = e.chain().any(|cause| {
+        cause
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(reqwest::Error::is_connect)
+    });
+    connect
+        || matches!(
+            Failure::classify(e),
+            Failure::Dns
+                | Failure::ConnectionRefused
+                | Failure::ConnectionReset
+                | Failure::Timeout
+                | Failure::Tls
+        )
+}
+
+/// Diagnose the connection to URL and print the results as the notes.
+pub async fn run(args: &Args, client: &reqwest::Client, url: &reqwest::Url) {
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return;
+    };
+    // The brackets of an IPv6 address are only in the URL.
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    logging::note(format_args!("Diagnosis of {}:{}", host, port));
+
+    let start = Instant::now();
+    let ips = match resolve(args, host).await {
+        Ok(ips) => ips,
+        Err(e) => {
+            logging::note(format_args!("DNS {}: {:#}", host, e));
+            return;
+        }
+    };
+    let list = ips.iter().map(IpAddr::to_string).collect::<Vec<_>>();
+    logging::note(format_args!(
+        "DNS {}: {} ({}ms)",
+        host,
+        list.join(", "),
+        start.elapsed().as_millis()
+    ));
+
+    let mut connected = None;
+    for ip in ips {
+        let addr = SocketAddr::new(ip, port);
+        let start = Instant::now();
+        let result = match tokio::time::timeout(TIMEOUT, TcpStream::connect(addr)).await {
+            Ok(Ok(stream)) => {
+                connected.get_or_insert(stream);
+                "connected".to_string()
+            }
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => "timed out".to_string(),
+        };
+        logging::note(format_args!(
+            "TCP {}: {} ({}ms)",
+            addr,
+            result,
+            start.elapsed().as_millis()
+        ));
+    }
+    let Some(stream) = connected else {
+        logging::note("No address accepted the connection");
+        return;
+    };
+
+    match url.scheme() {
+        "https" => handshake(args, host, stream).await,
+        _ => drop(stream),
+    }
+
+    let start = Instant::now();
+    let result = match client.head(url.clone()).timeout(TIMEOUT).send().await {
+        Ok(res) => res.status().to_string(),
+        Err(e) => format!("{:#}", anyhow::Error::new(e)),
+    };
+    logging::note(format_args!(
+        "HTTP HEAD (unsigned): {} ({}ms)",
+        result,
+        start.elapsed().as_millis()
+    ));
+}
+
+/// The addresses of HOST by --resolve or the resolver of the request.
+async fn resolve(args: &Args, host: &str) -> anyhow::Result<Vec<IpAddr>> {
+    if let Ok(ip) = host.parse() {
+        return Ok(vec![ip]);
+    }
+    if let Some((_, addrs)) = args.resolve.iter().find(|(name, _)| name == host) {
+        return Ok(addrs.iter().map(SocketAddr::ip).collect());
+    }
+    let resolver = Resolver::new(&args.dns_servers, client::family(args))?;
+    let name = Name::from_str(host)?;
+    let addrs = resolver
+        .resolve(name)
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+    Ok(addrs.map(|addr| addr.ip()).collect())
+}
+
+/// Hand shake over STREAM and print the parameters and the certificate, which
+/// is printed even if it is not trusted.
+async fn handshake(args: &Args, host: &str, stream: TcpStream) {
+    let recorder = match Recorder::new() {
+        Ok(recorder) => Arc::new(recorder),
+        Err(e) => {
+            logging::note(format_args!("TLS: {:#}", e));
+            return;
+        }
+    };
+    let start = Instant::now();
+    let result = async {
+        let mut config = tls::http1_config(args)?;
+        config
+            .dangerous()
+            .set_certificate_verifier(recorder.clone());
+        let server_name = ServerName::try_from(host.to_string())?;
+        let connect =
+            tokio_rustls::TlsConnector::from(Arc::new(config)).connect(server_name, stream);
+        let stream = tokio::time::timeout(TIMEOUT, connect)
+            .await
+            .context("timed out")??;
+        anyhow::Ok(tls::negotiated(stream.get_ref().1))
+    }
+    .await;
+    let result = result.unwrap_or_else(|e| format!("TLS handshake failed: {:#}", e));
+    logging::note(format_args!(
+        "{} ({}ms)",
+        result,
+        start.elapsed().as_millis()
+    ));
+    let certificate = recorder.certificate.lock().unwrap().take();
+    if let Some(certificate) = certificate.as_deref().and_then(Certificate::parse) {
+        logging::note(format_args!(
+            "Certificate: {}",
+            certificate.describe(Utc::now())
+        ));
+    }
+}
+
+/// The verifier of the web PKI which keeps the certificate of the server.
+#[derive(Debug)]
+struct Recorder {
+    inner: Arc<WebPkiServerVerifier>,
+    certificate: Mutex<Option<Vec<u8>>>,
+}
+
+impl Recorder {
+    fn new() -> anyhow::Result<Self> {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let inner = WebPkiServerVerifier::builder_with_provider(
+            Arc::new(roots),
+            Arc::new(rustls::crypto::ring::default_provider()),
+        )
+        .build()?;
+        Ok(Recorder {
+            inner,
+            certificate: Mutex::new(None),
+        })
+    }
+}
+
+impl ServerCertVerifier for Recorder {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        *self.certificate.lock().unwrap() = Some(end_entity.to_vec());
+        self.inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// The parts of an X.509 certificate in the diagnosis.
+#[derive(Debug, PartialEq)]
+struct Certificate {
+    subject: String,
+    not_after: DateTime<Utc>,
+}
+
+/// The tag, the contents and the rest of the DER element at the start of DER.
+fn element(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = match first {
+        0..0x80 => (first as usize, rest),
+        _ => {
+            let n = (first & 0x7f) as usize;
+            if n == 0 || n > 4 || rest.len() < n {
+                return None;
+            }
+            let (bytes, rest) = rest.split_at(n);
+            (bytes.iter().fold(0, |len, &b| len << 8 | b as usize), rest)
+        }
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let (contents, rest) = rest.split_at(len);
+    Some((tag, contents, rest))
+}
+
+/// The elements in the contents of a SEQUENCE or a SET.
+fn elements(mut der: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    std::iter::from_fn(move || {
+        let (tag, contents, rest) = element(der)?;
+        der = rest;
+        Some((tag, contents))
+    })
+}
+
+impl Certificate {
+    fn parse(der: &[u8]) -> Option<Self> {
+        let (_, certificate, _) = element(der)?;
+        let (_, tbs, _) = element(certificate)?;
+        let (tag, _, rest) = element(tbs)?;
+        // The version is optional before the serial number.
+        let rest = match tag {
+            0xa0 => element(rest)?.2,
+            _ => rest,
+        };
+        let (_, _signature, rest) = element(rest)?;
+        let (_, _issuer, rest) = element(rest)?;
+        let (_, validity, rest) = element(rest)?;
+        let (_, subject, _) = element(rest)?;
+        // notBefore and notAfter
+        let (tag, not_after) = elements(validity).nth(1)?;
+        Some(Certificate {
+            subject: name(subject),
+            not_after: time(tag, not_after)?,
+        })
+    }
+
+    fn describe(&self, now: DateTime<Utc>) -> String {
+        let expiry = match self.not_after < now {
+            true => "expired",
+            false => "expires",
+        };
+        format!(
+            "{}, {} {}",
+            self.subject,
+            expiry,
+            self.not_after.format("%Y-%m-%d %H:%M:%S UTC")
+        )
+    }
+}
+
+/// The Name of X.509 (Ex. "O=Example, CN=example.com").
+fn name(der: &[u8]) -> String {
+    elements(der)
+        .flat_map(|(_, set)| elements(set))
+        .filter_map(|(_, attribute)| {
+            let mut parts = elements(attribute);
+            let (_, oid) = parts.next()?;
+            let (_, value) = parts.next()?;
+            let key = match oid {
+                [0x55, 0x04, 0x03] => "CN",
+                [0x55, 0x04, 0x06] => "C",
+                [0x55, 0x04, 0x0a] => "O",
+                [0x55, 0x04, 0x0b] => "OU",
+                _ => return None,
+            };
+            Some(format!("{}={}", key, String::from_utf8_lossy(value)))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The UTCTime (tag 0x17) or GeneralizedTime (0x18) of RAW.
+fn time(tag: u8, raw: &[u8]) -> Option<DateTime<Utc>> {
+    let raw = std::str::from_utf8(raw).ok()?;
+    let raw = match tag {
+        // The years 50 to 99 are in 1900s.
+        0x17 if raw < "50" => format!("20{}", raw),
+        0x17 => format!("19{}", raw),
+        0x18 => raw.to_string(),
+        _ => return None,
+    };
+    NaiveDateTime::parse_from_str(&raw, "%Y%m%d%H%M%SZ")
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{prelude::BASE64_STANDARD, Engine};
+    use chrono::{TimeZone, Utc};
+
+    use super::{time, Certificate};
+
+    /// The self-signed certificate of O=Example, CN=example.com.
+    const CERTIFICATE: &str = "MIIBpTCCAUugAwIBAgIUW+a/muj8F30kstr2ugZGjzlI1YowCgYIKoZIzj0EAwIwKDEQMA4GA1UECgwHRXhhbXBsZTEUMBIGA1UEAwwLZXhhbXBsZS5jb20wHhcNMjUwMTAxMDAwMDAwWhcNMzAwMTAxMDAwMDAwWjAoMRAwDgYDVQQKDAdFeGFtcGxlMRQwEgYDVQQDDAtleGFtcGxlLmNvbTBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABM7tilgV0T8SxZI8Wcyy6MV3Liyl0Dax7g5BNTvTOpWWDEr3ShqyRH3Mlqg0QMg3fbCxhJYR42s55YJNyVqmn5yjUzBRMB0GA1UdDgQWBBTJCTSVk2tNMmFWE+MZqgvoUIeLyTAfBgNVHSMEGDAWgBTJCTSVk2tNMmFWE+MZqgvoUIeLyTAPBgNVHRMBAf8EBTADAQH/MAoGCCqGSM49BAMCA0gAMEUCIQCDRxGf12/vU3B6YvDbxr5xPt3I3aN9uqVSYSbMZG+6CQIgLVO7Ese7nO+CmTvOY7eh4XmSDQi7Edeo8u1DDIZqVAg=";
+
+    #[test]
+    fn parse_certificate() {
+        let der = BASE64_STANDARD.decode(CERTIFICATE).unwrap();
+        let certificate = Certificate::parse(&der).unwrap();
+        let not_after = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            certificate,
+            Certificate {
+                subject: "O=Example, CN=example.com".to_string(),
+                not_after,
+            }
+        );
+        assert_eq!(
+            certificate.describe(Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()),
+            "O=Example, CN=example.com, expires 2030-01-01 00:00:00 UTC"
+        );
+        assert_eq!(
+            certificate.describe(Utc.with_ymd_and_hms(2031, 1, 1, 0, 0, 0).unwrap()),
+            "O=Example, CN=example.com, expired 2030-01-01 00:00:00 UTC"
+        );
+        assert!(Certificate::parse(&der[..100]).is_none());
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(
+            time(0x17, b"491231235959Z"),
+            Utc.with_ymd_and_hms(2049, 12, 31, 23, 59, 59).single()
+        );
+        assert_eq!(
+            time(0x17, b"500101000000Z"),
+            Utc.with_ymd_and_hms(1950, 1, 1, 0, 0, 0).single()
+        );
+        assert_eq!(
+            time(0x18, b"20500101000000Z"),
+            Utc.with_ymd_and_hms(2050, 1, 1, 0, 0, 0).single()
+        );
+        assert_eq!(time(0x04, b"20500101000000Z"), None);
+    }
+}
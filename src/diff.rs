@@ -0,0 +1,426 @@
+//! Comparing the responses of two URLs (`--diff`)
+//!
+//! The same request is signed and sent to both URLs with the same credentials
+//! and client. JSON bodies are compared after sorting the keys and removing the
+//! fields of `--diff-ignore`, and the others line by line. The differing status
+//! and headers are printed as a table before the unified diff of the bodies.
+
+use std::{collections::BTreeMap, fmt, process::ExitCode};
+
+use http::{HeaderMap, StatusCode};
+use serde_json::Value;
+use similar::TextDiff;
+
+use crate::{
+    interrupt, output, print_request_verbose, print_response_verbose, retry, upload::Upload, Args,
+    AwsCurlParam,
+};
+
+/// The headers which differ between any two responses.
+const VOLATILE_HEADERS: [&str; 9] = [
+    "date",
+    "x-amz-apigw-id",
+    "x-amz-cf-id",
+    "x-amz-cf-pop",
+    "x-amz-id-2",
+    "x-amz-request-id",
+    "x-amzn-requestid",
+    "x-amzn-trace-id",
+    "x-cache",
+];
+
+/// The lines of the context around each change of the body.
+const CONTEXT_LINES: usize = 3;
+
+/// A step of the path of `--diff-ignore`.
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Field(String),
+    Index(usize),
+    /// "*" or "[*]", which is every member or element
+    Wildcard,
+}
+
+/// The path of `--diff-ignore`, which is a subset of JMESPath: fields joined by
+/// ".", "[N]", and "*" or "[*]" for all of them (Ex. items[*].updatedAt).
+#[derive(Clone, Debug, PartialEq)]
+pub struct IgnorePath(Vec<Step>);
+
+impl fmt::Display for IgnorePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, step) in self.0.iter().enumerate() {
+            match step {
+                Step::Field(name) if i == 0 => write!(f, "{}", name)?,
+                Step::Field(name) => write!(f, ".{}", name)?,
+                Step::Index(index) => write!(f, "[{}]", index)?,
+                Step::Wildcard => write!(f, "[*]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse RAW of `--diff-ignore`.
+pub fn parse_ignore(raw: &str) -> Result<IgnorePath, String> {
+    let invalid = || format!("expected a path like items[*].updatedAt: {}", raw);
+    let mut steps = Vec::new();
+    for (i, segment) in raw.split('.').enumerate() {
+        let (name, mut brackets) = segment.split_at(segment.find('[').unwrap_or(segment.len()));
+        match name {
+            "" if i == 0 && !brackets.is_empty() => {}
+            "" => return Err(invalid()),
+            "*" => steps.push(Step::Wildcard),
+            name => steps.push(Step::Field(name.to_string())),
+        }
+        while !brackets.is_empty() {
+            let end = brackets.find(']').ok_or_else(invalid)?;
+            let step = match &brackets[1..end] {
+                "*" => Step::Wildcard,
+                index => Step::Index(index.parse().map_err(|_| invalid())?),
+            };
+            steps.push(step);
+            brackets = &brackets[end + 1..];
+            if !brackets.is_empty() && !brackets.starts_with('[') {
+                return Err(invalid());
+            }
+        }
+    }
+    Ok(IgnorePath(steps))
+}
+
+impl IgnorePath {
+    /// Remove the values at the path from VALUE.
+    fn remove(&self, value: &mut Value) {
+        remove(value, &self.0);
+    }
+}
+
+fn remove(value: &mut Value, steps: &[Step]) {
+    let Some((step, rest)) = steps.split_first() else {
+        return;
+    };
+    if rest.is_empty() {
+        match (step, value) {
+            (Step::Field(name), Value::Object(object)) => {
+                object.remove(name);
+            }
+            (Step::Index(index), Value::Array(array)) if *index < array.len() => {
+                array.remove(*index);
+            }
+            (Step::Wildcard, Value::Object(object)) => object.clear(),
+            (Step::Wildcard, Value::Array(array)) => array.clear(),
+            _ => {}
+        }
+        return;
+    }
+    match (step, value) {
+        (Step::Field(name), Value::Object(object)) => {
+            if let Some(child) = object.get_mut(name) {
+                remove(child, rest);
+            }
+        }
+        (Step::Index(index), Value::Array(array)) => {
+            if let Some(child) = array.get_mut(*index) {
+                remove(child, rest);
+            }
+        }
+        (Step::Wildcard, Value::Object(object)) => {
+            object.values_mut().for_each(|child| remove(child, rest));
+        }
+        (Step::Wildcard, Value::Array(array)) => {
+            array.iter_mut().for_each(|child| remove(child, rest));
+        }
+        _ => {}
+    }
+}
+
+/// The response kept for the comparison.
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+/// Send REQ of PARAM and the same request to the URL of --diff, and print their
+/// differences. The exit code is 1 if they differ.
+pub async fn run(
+    mut param: AwsCurlParam,
+    client: &reqwest::Client,
+    req: reqwest::Request,
+) -> anyhow::Result<ExitCode> {
+    let left_url = param.url()?.to_string();
+    let left = fetch(&param, client, req).await?;
+    let right_url = param.args.diff.clone().unwrap_or_default();
+    param.args.url = Some(right_url.clone());
+    let mut req = param.sendable(param.build_request().await?)?;
+    if let Some(Upload::Bytes(bytes)) = &param.upload {
+        *req.body_mut() = Some(bytes.clone().into());
+    }
+    if param.args.verbose {
+        print_request_verbose(&req, &param.args);
+    }
+    let right = fetch(&param, client, req).await?;
+
+    match differences(
+        &left_url,
+        &left,
+        &right_url,
+        &right,
+        &param.args.diff_ignore,
+    ) {
+        Some(differences) => {
+            print!("{}", differences);
+            Ok(ExitCode::FAILURE)
+        }
+        None => Ok(ExitCode::SUCCESS),
+    }
+}
+
+/// The differences of the responses of LEFT_URL and RIGHT_URL: the table of the
+/// status and the headers, and the diff of the bodies without the IGNORE paths.
+pub fn differences(
+    left_url: &str,
+    left: &Response,
+    right_url: &str,
+    right: &Response,
+    ignore: &[IgnorePath],
+) -> Option<String> {
+    let table = header_table(left, right);
+    let body = body_diff(
+        &normalize(&left.body, ignore),
+        &normalize(&right.body, ignore),
+    );
+    if table.is_empty() && body.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "--- {}\n+++ {}\n{}{}",
+        left_url, right_url, table, body
+    ))
+}
+
+async fn fetch(
+    param: &AwsCurlParam,
+    client: &reqwest::Client,
+    req: reqwest::Request,
+) -> anyhow::Result<Response> {
+    let res = retry::send(param, client, req).await?;
+    if param.args.verbose {
+        print_response_verbose(&res, &param.args);
+    }
+    read(res, &param.args).await
+}
+
+/// RES with the whole body to compare.
+pub async fn read(res: reqwest::Response, args: &Args) -> anyhow::Result<Response> {
+    let status = res.status();
+    let headers = res.headers().clone();
+    let body = output::read_body(
+        res,
+        args.ignore_content_length,
+        args.max_response_size,
+        interrupt::interrupted(),
+        None,
+    )
+    .await?;
+    Ok(Response {
+        status,
+        headers,
+        body,
+    })
+}
+
+/// The body to compare: JSON pretty-printed with the sorted keys and without
+/// the IGNORE paths, and other text as it is. A binary body is the error.
+fn normalize(body: &[u8], ignore: &[IgnorePath]) -> Result<String, Vec<u8>> {
+    if let Ok(mut value) = serde_json::from_slice::<Value>(body) {
+        for path in ignore {
+            path.remove(&mut value);
+        }
+        // The keys of serde_json's map are sorted.
+        let mut text = serde_json::to_string_pretty(&value).unwrap_or_default();
+        text.push('\n');
+        return Ok(text);
+    }
+    String::from_utf8(body.to_vec()).map_err(|e| e.into_bytes())
+}
+
+/// The unified diff of the bodies, or the sizes of the binary bodies which differ.
+fn body_diff(left: &Result<String, Vec<u8>>, right: &Result<String, Vec<u8>>) -> String {
+    match (left, right) {
+        (Ok(left), Ok(right)) => TextDiff::from_lines(left, right)
+            .unified_diff()
+            .context_radius(CONTEXT_LINES)
+            .to_string(),
+        (left, right) => {
+            let bytes = |body: &Result<String, Vec<u8>>| match body {
+                Ok(text) => text.as_bytes().to_vec(),
+                Err(bytes) => bytes.clone(),
+            };
+            let (left, right) = (bytes(left), bytes(right));
+            if left == right {
+                return String::new();
+            }
+            format!(
+                "Binary bodies differ: {} bytes and {} bytes\n",
+                left.len(),
+                right.len()
+            )
+        }
+    }
+}
+
+/// The table of the status and the headers which differ, except VOLATILE_HEADERS.
+fn header_table(left: &Response, right: &Response) -> String {
+    let mut rows = Vec::new();
+    if left.status != right.status {
+        rows.push((
+            "status".to_string(),
+            left.status.as_u16().to_string(),
+            right.status.as_u16().to_string(),
+        ));
+    }
+    let values = |headers: &HeaderMap| {
+        let mut values = BTreeMap::<String, Vec<String>>::new();
+        for (name, value) in headers {
+            values
+                .entry(name.to_string())
+                .or_default()
+                .push(String::from_utf8_lossy(value.as_bytes()).into_owned());
+        }
+        values
+    };
+    let (left, right) = (values(&left.headers), values(&right.headers));
+    let mut names = left.keys().chain(right.keys()).collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    for name in names {
+        if VOLATILE_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        let value = |values: &BTreeMap<String, Vec<String>>| {
+            values
+                .get(name)
+                .map_or("-".to_string(), |values| values.join(", "))
+        };
+        let (left, right) = (value(&left), value(&right));
+        if left != right {
+            rows.push((name.clone(), left, right));
+        }
+    }
+    if rows.is_empty() {
+        return String::new();
+    }
+    let name_width = rows
+        .iter()
+        .map(|(name, _, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    let left_width = rows
+        .iter()
+        .map(|(_, left, _)| left.len())
+        .max()
+        .unwrap_or(0);
+    rows.iter()
+        .map(|(name, left, right)| {
+            format!("{:name_width$}  {:left_width$}  {}\n", name, left, right)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{HeaderMap, HeaderValue, StatusCode};
+    use serde_json::json;
+
+    use super::{body_diff, header_table, normalize, parse_ignore, Response, Step};
+
+    #[test]
+    fn ignore_paths() {
+        assert_eq!(
+            parse_ignore("items[*].updatedAt").unwrap().0,
+            [
+                Step::Field("items".to_string()),
+                Step::Wildcard,
+                Step::Field("updatedAt".to_string())
+            ]
+        );
+        assert_eq!(
+            parse_ignore("[0].id").unwrap().0,
+            [Step::Index(0), Step::Field("id".to_string())]
+        );
+        assert_eq!(parse_ignore("*.id").unwrap().to_string(), "[*].id");
+        for raw in ["a..b", "a[x]", "a[1", "a[1]b", ""] {
+            assert!(parse_ignore(raw).is_err(), "{}", raw);
+        }
+
+        let mut value = json!({
+            "requestId": "1",
+            "items": [{"id": 1, "updatedAt": "t1"}, {"id": 2, "updatedAt": "t2"}],
+            "meta": {"a": {"ts": 1}, "b": {"ts": 2, "x": 3}},
+        });
+        for raw in [
+            "requestId",
+            "items[*].updatedAt",
+            "meta.*.ts",
+            "missing.field",
+        ] {
+            parse_ignore(raw).unwrap().remove(&mut value);
+        }
+        assert_eq!(
+            value,
+            json!({"items": [{"id": 1}, {"id": 2}], "meta": {"a": {}, "b": {"x": 3}}})
+        );
+    }
+
+    #[test]
+    fn diff_bodies() {
+        let ignore = [parse_ignore("requestId").unwrap()];
+        let left = normalize(br#"{"b":1,"a":[1,2],"requestId":"x"}"#, &ignore);
+        let right = normalize(br#"{"a":[1,3],"requestId":"y","b":1}"#, &ignore);
+        assert_eq!(
+            body_diff(&left, &right),
+            "@@ -1,7 +1,7 @@\n {\n   \"a\": [\n     1,\n-    2\n+    3\n   ],\n   \"b\": 1\n }\n"
+        );
+        let same = normalize(br#"{"requestId":"z","a":[1,2],"b":1}"#, &ignore);
+        assert_eq!(body_diff(&left, &same), "");
+
+        assert_eq!(
+            body_diff(&normalize(b"a\nb\n", &[]), &normalize(b"a\nc\n", &[])),
+            "@@ -1,2 +1,2 @@\n a\n-b\n+c\n"
+        );
+        assert_eq!(
+            body_diff(&normalize(b"\xff\x00", &[]), &normalize(b"\xff", &[])),
+            "Binary bodies differ: 2 bytes and 1 bytes\n"
+        );
+    }
+
+    #[test]
+    fn differing_headers() {
+        let response = |status: u16, headers: &[(&'static str, &'static str)]| {
+            let mut map = HeaderMap::new();
+            for (name, value) in headers {
+                map.append(*name, HeaderValue::from_static(value));
+            }
+            Response {
+                status: StatusCode::from_u16(status).unwrap(),
+                headers: map,
+                body: vec![],
+            }
+        };
+        let left = response(
+            200,
+            &[
+                ("content-type", "application/json"),
+                ("date", "1"),
+                ("x-a", "1"),
+            ],
+        );
+        let right = response(404, &[("content-type", "application/json"), ("date", "2")]);
+        assert_eq!(
+            header_table(&left, &right),
+            "status  200  404\nx-a     1    -\n"
+        );
+        assert_eq!(header_table(&left, &left), "");
+    }
+}
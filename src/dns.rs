@@ -0,0 +1,197 @@
+//! Name resolution of the request host (`--dns-servers`, `--ipv4`, `--ipv6`)
+
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::Mutex,
+};
+
+use hickory_resolver::{
+    config::{LookupIpStrategy, NameServerConfig, ResolverConfig},
+    net::runtime::TokioRuntimeProvider,
+    TokioResolver,
+};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Family {
+    V4,
+    V6,
+}
+
+impl Family {
+    fn matches(self, ip: &IpAddr) -> bool {
+        match self {
+            Family::V4 => ip.is_ipv4(),
+            Family::V6 => ip.is_ipv6(),
+        }
+    }
+}
+
+/// The error of the resolution, which is reported with the exit code 6 like curl.
+#[derive(Debug)]
+pub struct ResolveError {
+    host: String,
+    reason: String,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Could not resolve host: {} ({})", self.host, self.reason)
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+/// The resolvers of --dns-servers built in this process, for `clear_cache`.
+static RESOLVERS: Mutex<Vec<TokioResolver>> = Mutex::new(Vec::new());
+
+/// Forget the answers cached in this process, including the negative ones, so
+/// that the next lookup asks the DNS servers again. The system resolver has no
+/// cache here.
+pub fn clear_cache() {
+    for resolver in RESOLVERS.lock().unwrap().iter() {
+        resolver.clear_cache();
+    }
+}
+
+/// Resolves with the given DNS servers, or the system resolver if there is none.
+pub struct Resolver {
+    dns: Option<TokioResolver>,
+    family: Option<Family>,
+}
+
+impl Resolver {
+    pub fn new(servers: &[SocketAddr], family: Option<Family>) -> anyhow::Result<Self> {
+        if servers.is_empty() {
+            return Ok(Self { dns: None, family });
+        }
+        let name_servers = servers
+            .iter()
+            .map(|server| {
+                let mut config = NameServerConfig::udp_and_tcp(server.ip());
+                for connection in &mut config.connections {
+                    connection.port = server.port();
+                }
+                config
+            })
+            .collect();
+        let mut builder = TokioResolver::builder_with_config(
+            ResolverConfig::from_name_servers(name_servers),
+            TokioRuntimeProvider::default(),
+        );
+        builder.options_mut().ip_strategy = match family {
+            Some(Family::V4) => LookupIpStrategy::Ipv4Only,
+            Some(Family::V6) => LookupIpStrategy::Ipv6Only,
+            None => LookupIpStrategy::Ipv4AndIpv6,
+        };
+        let dns = builder.build()?;
+        RESOLVERS.lock().unwrap().push(dns.clone());
+        Ok(Self {
+            dns: Some(dns),
+            family,
+        })
+    }
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        let dns = self.dns.clone();
+        let family = self.family;
+        Box::pin(async move {
+            let error = |reason: String| ResolveError {
+                host: host.clone(),
+                reason,
+            };
+            let ips: Vec<IpAddr> = match dns {
+                Some(dns) => dns
+                    .lookup_ip(host.as_str())
+                    .await
+                    .map_err(|e| error(e.to_string()))?
+                    .iter()
+                    .collect(),
+                None => tokio::net::lookup_host((host.as_str(), 0))
+                    .await
+                    .map_err(|e| error(e.to_string()))?
+                    .map(|addr| addr.ip())
+                    .collect(),
+            };
+            let addrs = ips
+                .into_iter()
+                .filter(|ip| family.is_none_or(|family| family.matches(ip)))
+                .map(|ip| SocketAddr::new(ip, 0))
+                .collect::<Vec<_>>();
+            if addrs.is_empty() {
+                return Err(error("no address of the requested family".to_string()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Parse a DNS server address. The port is 53 if omitted.
+pub fn parse_server(raw: &str) -> Result<SocketAddr, String> {
+    if let Ok(addr) = raw.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    let ip = raw.trim_start_matches('[').trim_end_matches(']');
+    match ip.parse::<IpAddr>() {
+        Ok(ip) => Ok(SocketAddr::new(ip, 53)),
+        Err(_) => Err(format!("expected IP[:PORT]: {}", raw)),
+    }
+}
+
+/// Parse a `--resolve` entry (HOST:PORT:ADDRESS[,ADDRESS]...).
+pub fn parse_resolve(raw: &str) -> Result<(String, Vec<SocketAddr>), String> {
+    let invalid = || format!("expected HOST:PORT:ADDRESS: {}", raw);
+    let mut parts = raw.splitn(3, ':');
+    let (Some(host), Some(port), Some(addrs)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid());
+    };
+    let port = port.parse::<u16>().map_err(|_| invalid())?;
+    let addrs = addrs
+        .split(',')
+        .map(|addr| {
+            let addr = addr.trim().trim_start_matches('[').trim_end_matches(']');
+            addr.parse::<IpAddr>()
+                .map(|ip| SocketAddr::new(ip, port))
+                .map_err(|_| invalid())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((host.to_string(), addrs))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::{parse_resolve, parse_server};
+
+    #[test]
+    fn dns_server() {
+        assert_eq!(
+            parse_server("10.0.0.2").unwrap(),
+            "10.0.0.2:53".parse::<SocketAddr>().unwrap()
+        );
+        assert_eq!(
+            parse_server("[::1]:5353").unwrap(),
+            "[::1]:5353".parse::<SocketAddr>().unwrap()
+        );
+        assert!(parse_server("dns.example.com").is_err());
+    }
+
+    #[test]
+    fn resolve_entry() {
+        let (host, addrs) = parse_resolve("example.com:443:127.0.0.1,[::1]").unwrap();
+        assert_eq!(host, "example.com");
+        assert_eq!(
+            addrs,
+            [
+                "127.0.0.1:443".parse::<SocketAddr>().unwrap(),
+                "[::1]:443".parse().unwrap()
+            ]
+        );
+        assert!(parse_resolve("example.com:127.0.0.1").is_err());
+    }
+}
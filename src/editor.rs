@@ -0,0 +1,94 @@
+//! Compose the request body with an external editor (`--edit`)
+
+use std::{
+    env, fs,
+    io::{self, BufRead, IsTerminal, Write},
+    process::Command,
+};
+
+use anyhow::{bail, Context};
+
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Open the editor on a temporary file which contains `initial`.
+///
+/// Returns `None` when the user aborts because the body was left empty or unchanged.
+pub fn edit_body(initial: &str, json: bool) -> anyhow::Result<Option<String>> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        bail!("--edit requires stdin and stdout to be a terminal");
+    }
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string());
+    let body = run_editor(&editor, initial, json)?;
+    if body.trim().is_empty() || body == initial {
+        let reason = if body.trim().is_empty() {
+            "empty"
+        } else {
+            "unchanged"
+        };
+        if !confirm(&format!(
+            "The request body is {}. Send it anyway? [y/N] ",
+            reason
+        ))? {
+            return Ok(None);
+        }
+    }
+    Ok(Some(body))
+}
+
+fn run_editor(editor: &str, initial: &str, json: bool) -> anyhow::Result<String> {
+    let mut file = tempfile::Builder::new()
+        .prefix("awscurl-body-")
+        .suffix(if json { ".json" } else { ".txt" })
+        .tempfile()?;
+    file.write_all(initial.as_bytes())?;
+    file.flush()?;
+
+    // The editor may be given with arguments (Ex. "code --wait").
+    let words = shlex::split(editor).with_context(|| format!("Invalid editor: {}", editor))?;
+    let (program, args) = words
+        .split_first()
+        .with_context(|| format!("Invalid editor: {}", editor))?;
+    let status = Command::new(program)
+        .args(args)
+        .arg(file.path())
+        .status()
+        .with_context(|| format!("Unable to start the editor: {}", editor))?;
+    if !status.success() {
+        bail!("The editor exited with {}", status);
+    }
+    fs::read_to_string(file.path()).context("Unable to read the edited body")
+}
+
+/// Ask PROMPT on stderr, which is answered with y or yes.
+pub fn confirm(prompt: &str) -> anyhow::Result<bool> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run_editor;
+
+    #[test]
+    fn use_saved_contents() {
+        let body = run_editor(r#"sh -c 'printf "{\"edited\": true}" > "$0"'"#, "{}", true).unwrap();
+        assert_eq!(body, r#"{"edited": true}"#);
+    }
+
+    #[test]
+    fn keep_initial_contents() {
+        assert_eq!(run_editor("true", "initial", false).unwrap(), "initial");
+    }
+
+    #[test]
+    fn editor_failure() {
+        assert!(run_editor("false", "", false).is_err());
+    }
+}
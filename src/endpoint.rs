@@ -0,0 +1,114 @@
+//! Redirecting the request to another endpoint (`--endpoint-url`), Ex. LocalStack
+//!
+//! Same as the AWS CLI, AWS_ENDPOINT_URL_<SERVICE> and AWS_ENDPOINT_URL are used
+//! when the option is not given.
+
+use std::env;
+
+use anyhow::Context;
+use clap::ValueEnum;
+use http::{header::HOST, HeaderValue};
+
+use crate::Args;
+
+/// The host which the Host header and the signature use.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum SignHost {
+    /// The host of the URL, and the request is sent to the endpoint
+    Original,
+    /// The host of the endpoint, same as the AWS CLI
+    #[default]
+    Endpoint,
+}
+
+/// The endpoint given by --endpoint-url or the environment variables.
+pub fn resolve(args: &Args) -> Option<String> {
+    if let Some(endpoint) = &args.endpoint_url {
+        return Some(endpoint.clone());
+    }
+    let service = args.service.as_deref().unwrap_or(crate::DEFAULT_SERVICE);
+    let name = format!(
+        "AWS_ENDPOINT_URL_{}",
+        service.to_ascii_uppercase().replace(['-', ' '], "_")
+    );
+    env::var(name)
+        .or_else(|_| env::var("AWS_ENDPOINT_URL"))
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty())
+}
+
+/// Replace the scheme, host and port of URL with the ones of ENDPOINT, keeping
+/// the path under the path prefix of ENDPOINT and the query.
+pub fn rewrite(url: &str, endpoint: &str) -> anyhow::Result<reqwest::Url> {
+    let mut url = reqwest::Url::parse(url)?;
+    let endpoint = reqwest::Url::parse(endpoint)
+        .with_context(|| format!("Invalid endpoint URL: {}", endpoint))?;
+    let host = endpoint
+        .host_str()
+        .with_context(|| format!("The endpoint URL has no host: {}", endpoint))?;
+    url.set_scheme(endpoint.scheme())
+        .ok()
+        .context("Unable to change the scheme to the one of the endpoint")?;
+    url.set_host(Some(host))?;
+    url.set_port(endpoint.port())
+        .ok()
+        .context("Unable to change the port to the one of the endpoint")?;
+    let prefix = endpoint.path().trim_end_matches('/');
+    if !prefix.is_empty() {
+        let path = format!("{}{}", prefix, url.path());
+        url.set_path(&path);
+    }
+    Ok(url)
+}
+
+/// The value of the Host header for URL, with the port if it is given.
+pub fn host(url: &reqwest::Url) -> String {
+    match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    }
+}
+
+/// Send the request signed for the original host to the endpoint, keeping the
+/// original host in the Host header (`--sign-host original`).
+pub fn redirect(req: &mut reqwest::Request, endpoint: &str) -> anyhow::Result<()> {
+    let url = req.url().clone();
+    req.headers_mut()
+        .insert(HOST, HeaderValue::from_str(&host(&url))?);
+    *req.url_mut() = rewrite(url.as_str(), endpoint)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rewrite;
+
+    #[test]
+    fn rewrite_url() {
+        assert_eq!(
+            rewrite(
+                "https://bucket.s3.amazonaws.com/dir/key?versionId=1",
+                "http://localhost:4566"
+            )
+            .unwrap()
+            .as_str(),
+            "http://localhost:4566/dir/key?versionId=1"
+        );
+        assert_eq!(
+            rewrite("http://localhost:4566/key", "https://vpce.example.com")
+                .unwrap()
+                .as_str(),
+            "https://vpce.example.com/key"
+        );
+        assert_eq!(
+            rewrite(
+                "https://abc.execute-api.us-east-1.amazonaws.com/prod/users?id=1",
+                "https://proxy.example.com/prefix/"
+            )
+            .unwrap()
+            .as_str(),
+            "https://proxy.example.com/prefix/prod/users?id=1"
+        );
+        assert!(rewrite("https://example.com/", "localhost").is_err());
+    }
+}
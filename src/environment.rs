@@ -0,0 +1,205 @@
+//! Named environments in ~/.config/awscurl/config.toml (`--env`)
+//!
+//! Each `[env.NAME]` table has the base URL of the environment and the
+//! profile, the region, the service and the headers used with it. The URL
+//! given as a path (Ex. /users/42?verbose=1) is appended to the path of the
+//! base URL before the request is signed. The options on the command line
+//! take precedence over the ones of the environment.
+
+use anyhow::bail;
+use serde::Deserialize;
+
+use crate::{config, Args};
+
+/// The value of --env which lists the environments.
+pub const LIST: &str = "list";
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Environment {
+    pub base_url: String,
+    pub profile: Option<String>,
+    pub region: Option<String>,
+    pub service: Option<String>,
+    #[serde(default)]
+    pub headers: Vec<String>,
+}
+
+/// The URL of PATH under BASE, keeping the queries of both.
+fn resolve(base: &str, path: &str) -> String {
+    let (base, base_query) = match base.split_once('?') {
+        Some((base, query)) => (base, Some(query)),
+        None => (base, None),
+    };
+    let (path, query) = match path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path, None),
+    };
+    let mut url = base.trim_end_matches('/').to_string();
+    if !path.is_empty() {
+        url.push('/');
+        url.push_str(path.trim_start_matches('/'));
+    }
+    let query = [base_query, query]
+        .into_iter()
+        .flatten()
+        .filter(|query| !query.is_empty())
+        .collect::<Vec<_>>();
+    if !query.is_empty() {
+        url.push('?');
+        url.push_str(&query.join("&"));
+    }
+    url
+}
+
+/// Whether URL is a path resolved against the base URL, not a whole URL or a
+/// template ("@NAME").
+fn is_relative(url: &str) -> bool {
+    !url.contains("://") && !url.starts_with('@')
+}
+
+impl Environment {
+    /// Give the URL and the defaults of the environment to ARGS.
+    fn apply(self, args: &mut Args) {
+        args.url = match args.url.take() {
+            Some(url) if !is_relative(&url) => Some(url),
+            url => Some(resolve(&self.base_url, url.as_deref().unwrap_or_default())),
+        };
+        args.profile = args.profile.take().or(self.profile);
+        args.region = args.region.take().or(self.region);
+        args.service = args.service.take().or(self.service);
+        // The headers of -H come later, which replace the ones with the same name.
+        args.header.splice(0..0, self.headers);
+    }
+}
+
+/// Apply the environment of --env to ARGS.
+pub fn install(args: &mut Args) -> anyhow::Result<()> {
+    let Some(name) = args.env.as_deref().filter(|name| *name != LIST) else {
+        return Ok(());
+    };
+    let mut environments = config::load()?.env;
+    let Some(environment) = environments.remove(name) else {
+        let path = config::settings_path()?;
+        if environments.is_empty() {
+            bail!(
+                "Unknown environment {}. Define it as [env.{}] in {}",
+                name,
+                name,
+                path.display()
+            );
+        }
+        bail!(
+            "Unknown environment {}. The environments in {} are {}",
+            name,
+            path.display(),
+            environments.into_keys().collect::<Vec<_>>().join(", ")
+        );
+    };
+    environment.apply(args);
+    Ok(())
+}
+
+/// The lines of `--env list`, each of which has the name and the base URL.
+pub fn list() -> anyhow::Result<Vec<String>> {
+    let environments = config::load()?.env;
+    let width = environments
+        .keys()
+        .map(String::len)
+        .max()
+        .unwrap_or_default();
+    Ok(environments
+        .iter()
+        .map(|(name, environment)| format!("{:width$}  {}", name, environment.base_url))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::{resolve, Environment};
+    use crate::{config::Settings, Args};
+
+    #[test]
+    fn resolve_paths() {
+        let base = "https://abc.execute-api.eu-west-1.amazonaws.com/stage";
+        assert_eq!(
+            resolve(base, "/users/42"),
+            "https://abc.execute-api.eu-west-1.amazonaws.com/stage/users/42"
+        );
+        assert_eq!(
+            resolve(&format!("{}/", base), "users/42?b=2&a=1"),
+            "https://abc.execute-api.eu-west-1.amazonaws.com/stage/users/42?b=2&a=1"
+        );
+        assert_eq!(resolve(base, ""), base);
+        assert_eq!(
+            resolve("https://example.com/v1?key=k", "/items?page=2"),
+            "https://example.com/v1/items?key=k&page=2"
+        );
+        assert_eq!(
+            resolve("https://example.com", "/?q=1"),
+            "https://example.com/?q=1"
+        );
+    }
+
+    #[test]
+    fn parse_config() {
+        let config: Settings = toml::from_str(
+            r#"
+            [env.staging]
+            base_url = "https://abc.execute-api.eu-west-1.amazonaws.com/stage"
+            profile = "staging"
+            service = "execute-api"
+            headers = ["x-api-version: 2"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.env["staging"],
+            Environment {
+                base_url: "https://abc.execute-api.eu-west-1.amazonaws.com/stage".to_string(),
+                profile: Some("staging".to_string()),
+                region: None,
+                service: Some("execute-api".to_string()),
+                headers: vec!["x-api-version: 2".to_string()],
+            }
+        );
+        assert!(toml::from_str::<Settings>("[env.dev]\nbase = \"https://example.com\"").is_err());
+    }
+
+    #[test]
+    fn command_line_wins() {
+        let environment = Environment {
+            base_url: "https://example.com/stage".to_string(),
+            profile: Some("staging".to_string()),
+            region: Some("eu-west-1".to_string()),
+            service: Some("execute-api".to_string()),
+            headers: vec!["x-api-version: 2".to_string()],
+        };
+        let mut args = Args::parse_from([
+            "awscurl",
+            "/users/42",
+            "--region",
+            "us-east-1",
+            "-H",
+            "x-api-version: 3",
+        ]);
+        environment.apply(&mut args);
+        assert_eq!(
+            args.url.as_deref(),
+            Some("https://example.com/stage/users/42")
+        );
+        assert_eq!(args.profile.as_deref(), Some("staging"));
+        assert_eq!(args.region.as_deref(), Some("us-east-1"));
+        assert_eq!(args.header, ["x-api-version: 2", "x-api-version: 3"]);
+
+        let environment = Environment {
+            base_url: "https://example.com/stage".to_string(),
+            ..Default::default()
+        };
+        let mut args = Args::parse_from(["awscurl", "https://other.example.com/items"]);
+        environment.apply(&mut args);
+        assert_eq!(args.url.as_deref(), Some("https://other.example.com/items"));
+    }
+}
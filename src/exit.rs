@@ -0,0 +1,83 @@
+//! Exit codes of the failed requests (`--exit-code-mode`)
+//!
+//! By default, any failure exits with 1 except the few codes of curl below. The
+//! detailed mode tells the client errors from the server errors, and uses the
+//! codes of curl for the errors of sending the request.
+
+use std::process::ExitCode;
+
+use clap::ValueEnum;
+use http::StatusCode;
+
+use crate::{
+    dns, interrupt,
+    output::TooLarge,
+    retry::{DeadlineExceeded, Failure, FirstByteTimeout},
+};
+
+/// Exit code when the response is larger than --max-response-size, which is
+/// same as curl (--max-filesize).
+const EXIT_FILESIZE_EXCEEDED: u8 = 63;
+/// Exit code when the host can not be resolved, which is same as curl.
+const EXIT_COULDNT_RESOLVE_HOST: u8 = 6;
+/// Exit code for a 4xx response in the detailed mode.
+const EXIT_CLIENT_ERROR: u8 = 4;
+/// Exit code for a 5xx response in the detailed mode.
+const EXIT_SERVER_ERROR: u8 = 5;
+
+/// Exit code when --total-deadline or --max-time-to-first-byte is exceeded, and
+/// for the timeouts in the detailed mode.
+const EXIT_OPERATION_TIMEDOUT: u8 = 28;
+
+// The codes of curl in the detailed mode.
+const EXIT_COULDNT_CONNECT: u8 = 7;
+const EXIT_SSL_CONNECT_ERROR: u8 = 35;
+const EXIT_RECV_ERROR: u8 = 56;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ExitCodeMode {
+    /// 1 for any failed response
+    #[default]
+    Simple,
+    /// 4 for 4xx, 5 for 5xx, and the codes of curl for the connection errors
+    Detailed,
+}
+
+/// The exit code for the failed response of STATUS.
+pub fn for_status(status: StatusCode, mode: ExitCodeMode) -> ExitCode {
+    match mode {
+        ExitCodeMode::Detailed if status.is_client_error() => ExitCode::from(EXIT_CLIENT_ERROR),
+        ExitCodeMode::Detailed if status.is_server_error() => ExitCode::from(EXIT_SERVER_ERROR),
+        _ => ExitCode::FAILURE,
+    }
+}
+
+/// The exit code for the error E.
+pub fn for_error(e: &anyhow::Error, mode: ExitCodeMode) -> ExitCode {
+    if e.chain().any(|cause| cause.is::<TooLarge>()) {
+        return ExitCode::from(EXIT_FILESIZE_EXCEEDED);
+    }
+    if mode == ExitCodeMode::Detailed {
+        return match Failure::classify(e) {
+            Failure::Dns => ExitCode::from(EXIT_COULDNT_RESOLVE_HOST),
+            Failure::ConnectionRefused => ExitCode::from(EXIT_COULDNT_CONNECT),
+            Failure::Timeout => ExitCode::from(EXIT_OPERATION_TIMEDOUT),
+            Failure::Tls => ExitCode::from(EXIT_SSL_CONNECT_ERROR),
+            Failure::ConnectionReset => ExitCode::from(EXIT_RECV_ERROR),
+            Failure::Interrupted => ExitCode::from(interrupt::EXIT_INTERRUPTED),
+            Failure::Status(_) | Failure::Other => ExitCode::FAILURE,
+        };
+    }
+    if e.chain().any(|cause| cause.is::<dns::ResolveError>()) {
+        ExitCode::from(EXIT_COULDNT_RESOLVE_HOST)
+    } else if e
+        .chain()
+        .any(|cause| cause.is::<DeadlineExceeded>() || cause.is::<FirstByteTimeout>())
+    {
+        ExitCode::from(EXIT_OPERATION_TIMEDOUT)
+    } else if e.chain().any(|cause| cause.is::<interrupt::Interrupted>()) {
+        ExitCode::from(interrupt::EXIT_INTERRUPTED)
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,340 @@
+//! Fixtures of the requests and the responses (`--fixture-out`, `--fixture-verify`)
+//!
+//! --fixture-out writes two files for each request to the directory:
+//! NAME.request, the canonical request of SigV4, and NAME.response, the status
+//! line, the headers and the body of the response. NAME is the hash of what the
+//! command gives (the method, the URL, the headers, the body, the service and
+//! the region), so the same command writes the same files. --datetime is pinned
+//! to the time of the recording unless it is given, and the credentials are
+//! masked in both files. --fixture-verify signs the same request at the time in
+//! NAME.request without sending it, and fails with the differences if the
+//! canonical request has changed.
+
+use std::{
+    borrow::Cow,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use anyhow::Context;
+use aws_sigv4::http_request::{PercentEncodingMode, SigningSettings, UriPathNormalizationMode};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use http::{header::AUTHORIZATION, HeaderMap, StatusCode, Version};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use similar::TextDiff;
+
+use crate::{calc_sha256_hex_digest, redact, Args, AwsCurlParam, CONTENT_SHA256_HEADER};
+
+/// The characters which SigV4 leaves as they are.
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+const PATH: &AsciiSet = &UNRESERVED.remove(b'/');
+
+/// The hex digits of the hash in the file names.
+const NAME_LENGTH: usize = 16;
+
+/// The name of the fixture files of the request of PARAM.
+pub fn name(param: &AwsCurlParam) -> anyhow::Result<String> {
+    let body = param.args.body().unwrap_or_default();
+    let inputs = serde_json::json!([
+        param.method(),
+        param.url()?,
+        param.args.header,
+        param.payload_hash(&body),
+        param.service(),
+        param.region()?,
+    ]);
+    let hash = calc_sha256_hex_digest(&inputs.to_string());
+    Ok(hash[..NAME_LENGTH].to_string())
+}
+
+/// Pin --datetime to the time of the recording, or to the one of the fixture to
+/// verify.
+pub fn pin(param: &mut AwsCurlParam) -> anyhow::Result<()> {
+    if let Some(dir) = &param.args.fixture_verify {
+        let path = dir.join(format!("{}.request", name(param)?));
+        let stored = fs::read_to_string(&path).with_context(|| {
+            format!(
+                "No fixture of the request at {}. Record it with --fixture-out",
+                path.display()
+            )
+        })?;
+        let datetime = stored
+            .lines()
+            .find_map(|line| line.strip_prefix("x-amz-date:"))
+            .and_then(|raw| NaiveDateTime::parse_from_str(raw, "%Y%m%dT%H%M%SZ").ok())
+            .with_context(|| format!("No x-amz-date in {}", path.display()))?;
+        param.args.datetime = Some(datetime.and_utc().fixed_offset());
+    } else if param.args.fixture_out.is_some() && param.args.datetime.is_none() {
+        // The signature has the precision of seconds.
+        let now = DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap_or_default();
+        param.args.datetime = Some(now.fixed_offset());
+    }
+    Ok(())
+}
+
+/// The canonical request of the signed REQ, with the credentials masked.
+pub fn canonical_request(
+    req: &http::Request<String>,
+    settings: &SigningSettings,
+    args: &Args,
+) -> anyhow::Result<String> {
+    let signed_headers = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value
+                .split([' ', ','])
+                .find_map(|param| param.strip_prefix("SignedHeaders="))
+        })
+        .context("The request has no signature of SigV4 to record")?;
+
+    let mut path = Cow::Borrowed(req.uri().path());
+    if settings.uri_path_normalization_mode == UriPathNormalizationMode::Enabled {
+        path = Cow::Owned(normalize_path(&path));
+    }
+    if settings.percent_encoding_mode == PercentEncodingMode::Double {
+        path = Cow::Owned(utf8_percent_encode(&path, PATH).to_string());
+    }
+
+    let mut lines = vec![
+        req.method().to_string(),
+        path.into_owned(),
+        canonical_query(req.uri().query().unwrap_or_default()),
+    ];
+    for name in signed_headers.split(';') {
+        let mut values = req
+            .headers()
+            .get_all(name)
+            .iter()
+            .map(|value| trim_all(&String::from_utf8_lossy(value.as_bytes())))
+            .collect::<Vec<_>>();
+        if name == "host" && values.is_empty() {
+            values.extend(req.uri().authority().map(|authority| authority.to_string()));
+        }
+        let value = values.join(",");
+        let value = match redact::is_sensitive(name, args) {
+            true => redact::mask(name, &value).into_owned(),
+            false => value,
+        };
+        lines.push(format!("{}:{}", name, value));
+    }
+    lines.push(String::new());
+    lines.push(signed_headers.to_string());
+    lines.push(match req.headers().get(CONTENT_SHA256_HEADER) {
+        Some(hash) => String::from_utf8_lossy(hash.as_bytes()).into_owned(),
+        None => calc_sha256_hex_digest(req.body()),
+    });
+    Ok(lines.join("\n"))
+}
+
+/// The query string with the parameters sorted and encoded as SigV4 does.
+fn canonical_query(query: &str) -> String {
+    let decode = |raw: &str| {
+        percent_decode_str(&raw.replace('+', " "))
+            .decode_utf8_lossy()
+            .into_owned()
+    };
+    let mut params = query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            (decode(key), decode(value))
+        })
+        .collect::<Vec<_>>();
+    params.sort();
+    params
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(key, UNRESERVED),
+                utf8_percent_encode(value, UNRESERVED)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// PATH without the empty, "." and ".." segments.
+fn normalize_path(path: &str) -> String {
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    let mut normalized = format!("/{}", segments.join("/"));
+    let directory = ["/", "/.", "/.."].iter().any(|end| path.ends_with(end));
+    if directory && !normalized.ends_with('/') {
+        normalized.push('/');
+    }
+    normalized
+}
+
+/// TEXT with the spaces around it removed and the consecutive ones squeezed.
+fn trim_all(text: &str) -> String {
+    text.split(' ')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Write the fixture of the request to DIR.
+pub fn record(
+    param: &AwsCurlParam,
+    dir: &Path,
+    canonical_request: &str,
+    version: Version,
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> anyhow::Result<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Unable to create the directory {}", dir.display()))?;
+    let name = name(param)?;
+    let mut response = format!("{:?} {}\n", version, status).into_bytes();
+    for (key, value) in headers {
+        let value = String::from_utf8_lossy(value.as_bytes());
+        let value = match redact::is_sensitive(key.as_str(), &param.args) {
+            true => redact::mask(key.as_str(), &value).into_owned(),
+            false => value.into_owned(),
+        };
+        response.extend_from_slice(format!("{}: {}\n", key, value).as_bytes());
+    }
+    response.push(b'\n');
+    match std::str::from_utf8(body) {
+        Ok(text) => response.extend_from_slice(redact::scrub(text).as_bytes()),
+        Err(_) => response.extend_from_slice(body),
+    }
+    let write = |path: PathBuf, contents: &[u8]| {
+        fs::write(&path, contents).with_context(|| format!("Unable to write {}", path.display()))
+    };
+    write(
+        dir.join(format!("{}.request", name)),
+        format!("{}\n", canonical_request).as_bytes(),
+    )?;
+    write(dir.join(format!("{}.response", name)), &response)
+}
+
+/// Compare CANONICAL_REQUEST with the one recorded in DIR, and print the
+/// differences if any.
+pub fn verify(
+    param: &AwsCurlParam,
+    dir: &Path,
+    canonical_request: &str,
+) -> anyhow::Result<ExitCode> {
+    let path = dir.join(format!("{}.request", name(param)?));
+    let stored =
+        fs::read_to_string(&path).with_context(|| format!("Unable to read {}", path.display()))?;
+    let current = format!("{}\n", canonical_request);
+    if stored == current {
+        return Ok(ExitCode::SUCCESS);
+    }
+    println!("--- {}\n+++ the canonical request now", path.display());
+    print!(
+        "{}",
+        TextDiff::from_lines(&stored, &current)
+            .unified_diff()
+            .context_radius(3)
+    );
+    Ok(ExitCode::FAILURE)
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sigv4::http_request::{PercentEncodingMode, SigningSettings};
+    use clap::Parser;
+
+    use super::{canonical_query, canonical_request, normalize_path};
+    use crate::Args;
+
+    fn args() -> Args {
+        Args::parse_from(["awscurl", "https://example.amazonaws.com"])
+    }
+
+    #[test]
+    fn get_vanilla() {
+        // The example of the test suite of SigV4.
+        let req = http::Request::builder()
+            .uri("https://example.amazonaws.com/")
+            .header("x-amz-date", "20150830T123600Z")
+            .header("authorization", "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, SignedHeaders=host;x-amz-date, Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31")
+            .body(String::new())
+            .unwrap();
+        assert_eq!(
+            canonical_request(&req, &SigningSettings::default(), &args()).unwrap(),
+            "GET
+/
+
+host:example.amazonaws.com
+x-amz-date:20150830T123600Z
+
+host;x-amz-date
+e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn masked_and_encoded() {
+        let req = http::Request::builder()
+            .method("POST")
+            .uri("https://example.com/a%20b/./c?b=2&a=x+y&a=1")
+            .header("x-amz-date", "20150830T123600Z")
+            .header("x-amz-security-token", "token")
+            .header("x-custom", "  a   b ")
+            .header("x-amz-content-sha256", "UNSIGNED-PAYLOAD")
+            .header("authorization", "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/s3/aws4_request, SignedHeaders=host;x-amz-content-sha256;x-amz-date;x-amz-security-token;x-custom, Signature=0")
+            .body("body".to_string())
+            .unwrap();
+        let mut settings = SigningSettings::default();
+        settings.percent_encoding_mode = PercentEncodingMode::Single;
+        assert_eq!(
+            canonical_request(&req, &settings, &args()).unwrap(),
+            "POST
+/a%20b/c
+a=1&a=x%20y&b=2
+host:example.com
+x-amz-content-sha256:UNSIGNED-PAYLOAD
+x-amz-date:20150830T123600Z
+x-amz-security-token:****
+x-custom:a b
+
+host;x-amz-content-sha256;x-amz-date;x-amz-security-token;x-custom
+UNSIGNED-PAYLOAD"
+        );
+        // The path is encoded again except for S3.
+        settings.percent_encoding_mode = PercentEncodingMode::Double;
+        assert!(canonical_request(&req, &settings, &args())
+            .unwrap()
+            .starts_with("POST\n/a%2520b/c\n"));
+    }
+
+    #[test]
+    fn unsigned() {
+        let req = http::Request::builder()
+            .uri("https://example.com/")
+            .body(String::new())
+            .unwrap();
+        assert!(canonical_request(&req, &SigningSettings::default(), &args()).is_err());
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(normalize_path("/"), "/");
+        assert_eq!(normalize_path("/a//b/../c/."), "/a/c/");
+        assert_eq!(normalize_path("/../a"), "/a");
+        assert_eq!(canonical_query(""), "");
+        assert_eq!(canonical_query("k=%7e&k=a%2Fb"), "k=a%2Fb&k=~");
+    }
+}
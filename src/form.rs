@@ -0,0 +1,322 @@
+//! multipart/form-data request body given by `-F` and `--form-string`
+//!
+//! Same as curl, a `-F` part is one of "name=content", "name=@FILE" for a file
+//! upload and "name=<FILE" for the contents of FILE as a text field, followed by
+//! the attributes ";type=...", ";filename=..." and ";headers=...". A value in
+//! double quotes can contain ";", with "\"" and "\\" escaped. `--form-string`
+//! takes "name=content" as it is. The parts are in the order on the command line.
+
+use std::{fs, io::Read, path::Path};
+
+use anyhow::{bail, Context};
+use clap::ArgMatches;
+
+/// The content type of a file part, which is guessed by its extension like curl.
+const DEFAULT_FILE_TYPE: &str = "application/octet-stream";
+const FILE_TYPES: [(&str, &str); 11] = [
+    ("gif", "image/gif"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("svg", "image/svg+xml"),
+    ("txt", "text/plain"),
+    ("htm", "text/html"),
+    ("html", "text/html"),
+    ("json", "application/json"),
+    ("pdf", "application/pdf"),
+    ("xml", "application/xml"),
+];
+
+/// Where the content of a part comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Content {
+    Text(String),
+    /// "@FILE", uploaded with its filename
+    File(String),
+    /// "<FILE", sent as a text field
+    FileContents(String),
+}
+
+/// A part of the multipart body.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Part {
+    pub name: String,
+    pub content: Content,
+    pub content_type: Option<String>,
+    pub filename: Option<String>,
+    pub headers: Vec<String>,
+}
+
+impl Part {
+    /// Parse RAW of `-F`.
+    pub fn parse(raw: &str) -> anyhow::Result<Self> {
+        let (name, rest) = raw
+            .split_once('=')
+            .with_context(|| format!("-F {} has no \"=\". Use NAME=CONTENT", raw))?;
+        let (content, mut rest) = match rest.chars().next() {
+            Some('@') => {
+                let (path, rest) = word(&rest[1..])?;
+                (Content::File(path), rest)
+            }
+            Some('<') => {
+                let (path, rest) = word(&rest[1..])?;
+                (Content::FileContents(path), rest)
+            }
+            _ => {
+                let (text, rest) = word(rest)?;
+                (Content::Text(text), rest)
+            }
+        };
+        let mut part = Self::new(name, content);
+        while let Some(attribute) = rest.strip_prefix(';') {
+            let attribute = attribute.trim_start();
+            let (key, value) = attribute
+                .split_once('=')
+                .with_context(|| format!("Invalid attribute {:?} in -F {}", attribute, raw))?;
+            let (value, next) = word(value)?;
+            match key.trim() {
+                "type" => part.content_type = Some(value),
+                "filename" => part.filename = Some(value),
+                "headers" => part.headers.push(value),
+                key => bail!(
+                    "Unknown attribute {:?} in -F {}. Use type, filename or headers, or quote the value with \"",
+                    key,
+                    raw
+                ),
+            }
+            rest = next;
+        }
+        if !rest.is_empty() {
+            bail!("Unexpected {:?} after the quoted value in -F {}", rest, raw);
+        }
+        Ok(part)
+    }
+
+    /// Parse RAW of `--form-string`, whose content is taken as it is.
+    pub fn literal(raw: &str) -> anyhow::Result<Self> {
+        let (name, content) = raw
+            .split_once('=')
+            .with_context(|| format!("--form-string {} has no \"=\". Use NAME=STRING", raw))?;
+        Ok(Self::new(name, Content::Text(content.to_string())))
+    }
+
+    fn new(name: &str, content: Content) -> Self {
+        Self {
+            name: name.to_string(),
+            content,
+            content_type: None,
+            filename: None,
+            headers: vec![],
+        }
+    }
+
+    /// The headers and the body of the part, without the boundary.
+    fn encode(&self) -> anyhow::Result<Vec<u8>> {
+        let (data, filename, content_type) = match &self.content {
+            Content::Text(text) => (text.as_bytes().to_vec(), None, None),
+            Content::FileContents(path) => (read(path)?, None, None),
+            Content::File(path) => {
+                let filename = Path::new(path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned());
+                (read(path)?, filename, Some(guess_type(path)))
+            }
+        };
+        let mut head = format!(
+            "Content-Disposition: form-data; name=\"{}\"",
+            escape(&self.name)
+        );
+        if let Some(filename) = self.filename.clone().or(filename) {
+            head.push_str(&format!("; filename=\"{}\"", escape(&filename)));
+        }
+        head.push_str("\r\n");
+        if let Some(content_type) = self.content_type.as_deref().or(content_type) {
+            head.push_str(&format!("Content-Type: {}\r\n", content_type));
+        }
+        for header in &self.headers {
+            head.push_str(&format!("{}\r\n", header));
+        }
+        head.push_str("\r\n");
+        let mut ret = head.into_bytes();
+        ret.extend(data);
+        Ok(ret)
+    }
+}
+
+/// Resolve `-F` and `--form-string` in the order they are given on the command line.
+pub fn collect(matches: &ArgMatches) -> anyhow::Result<Vec<Part>> {
+    let mut parts = Vec::new();
+    for id in ["form", "form_string"] {
+        let (Some(values), Some(indices)) =
+            (matches.get_many::<String>(id), matches.indices_of(id))
+        else {
+            continue;
+        };
+        for (value, index) in values.zip(indices) {
+            let part = match id {
+                "form_string" => Part::literal(value)?,
+                _ => Part::parse(value)?,
+            };
+            parts.push((index, part));
+        }
+    }
+    parts.sort_by_key(|(index, _)| *index);
+    Ok(parts.into_iter().map(|(_, part)| part).collect())
+}
+
+/// A boundary which is unlikely to be in the contents, which is same as curl.
+pub fn boundary() -> String {
+    let random = uuid::Uuid::new_v4().simple().to_string();
+    format!("------------------------{}", &random[..16])
+}
+
+/// The content-type header of the body with BOUNDARY.
+pub fn content_type(boundary: &str) -> String {
+    format!("multipart/form-data; boundary={}", boundary)
+}
+
+/// The multipart body of PARTS separated by BOUNDARY.
+pub fn body(parts: &[Part], boundary: &str) -> anyhow::Result<Vec<u8>> {
+    let mut ret = Vec::new();
+    for part in parts {
+        ret.extend(format!("--{}\r\n", boundary).into_bytes());
+        ret.extend(part.encode()?);
+        ret.extend(b"\r\n");
+    }
+    ret.extend(format!("--{}--\r\n", boundary).into_bytes());
+    Ok(ret)
+}
+
+/// A value up to the next ";", or the one in double quotes, and the rest.
+fn word(raw: &str) -> anyhow::Result<(String, &str)> {
+    let Some(quoted) = raw.strip_prefix('"') else {
+        let end = raw.find(';').unwrap_or(raw.len());
+        return Ok((raw[..end].to_string(), &raw[end..]));
+    };
+    let mut ret = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((ret, &quoted[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, c @ ('"' | '\\'))) => ret.push(c),
+                Some((_, c)) => {
+                    ret.push('\\');
+                    ret.push(c);
+                }
+                None => ret.push('\\'),
+            },
+            c => ret.push(c),
+        }
+    }
+    bail!("Unterminated \" in {}", raw)
+}
+
+/// NAME in double quotes, where the quotes and the newlines are percent-encoded
+/// like curl.
+fn escape(name: &str) -> String {
+    name.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn guess_type(path: &str) -> &'static str {
+    let extension = Path::new(path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    FILE_TYPES
+        .iter()
+        .find(|(known, _)| extension.as_deref() == Some(*known))
+        .map_or(DEFAULT_FILE_TYPE, |(_, content_type)| content_type)
+}
+
+/// The contents of the file at PATH, or stdin for "-".
+fn read(path: &str) -> anyhow::Result<Vec<u8>> {
+    if path == "-" {
+        let mut ret = Vec::new();
+        std::io::stdin().read_to_end(&mut ret)?;
+        return Ok(ret);
+    }
+    fs::read(path).with_context(|| format!("Unable to read {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{body, Content, Part};
+
+    #[test]
+    fn parse_attributes() {
+        let part =
+            Part::parse(r#"file=@report.pdf;type=application/pdf;filename=renamed.pdf"#).unwrap();
+        assert_eq!(part.name, "file");
+        assert_eq!(part.content, Content::File("report.pdf".to_string()));
+        assert_eq!(part.content_type.as_deref(), Some("application/pdf"));
+        assert_eq!(part.filename.as_deref(), Some("renamed.pdf"));
+
+        let part = Part::parse(
+            r#"note="a;b \"c\"";type=text/plain; headers="X-Note: 1;2";headers=X-Other: 3"#,
+        )
+        .unwrap();
+        assert_eq!(part.content, Content::Text(r#"a;b "c""#.to_string()));
+        assert_eq!(part.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(part.headers, ["X-Note: 1;2", "X-Other: 3"]);
+
+        let part = Part::parse("body=<message.txt").unwrap();
+        assert_eq!(
+            part.content,
+            Content::FileContents("message.txt".to_string())
+        );
+
+        assert_eq!(
+            Part::parse("note=a;b").unwrap_err().to_string(),
+            "Invalid attribute \"b\" in -F note=a;b"
+        );
+        assert!(Part::parse("note=a;size=1")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Unknown attribute \"size\""));
+        assert!(Part::parse(r#"note="a"#).is_err());
+        assert!(Part::parse(r#"note="a"b"#).is_err());
+        assert!(Part::parse("note").is_err());
+    }
+
+    #[test]
+    fn literal() {
+        let part = Part::literal("name=@not-a-file;type=x").unwrap();
+        assert_eq!(
+            part.content,
+            Content::Text("@not-a-file;type=x".to_string())
+        );
+        assert_eq!(part.content_type, None);
+    }
+
+    #[test]
+    fn multipart_body() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.pdf");
+        std::fs::write(&path, b"%PDF").unwrap();
+        let parts = [
+            Part::parse("title=Report").unwrap(),
+            Part::parse(&format!("file=@{}", path.display())).unwrap(),
+            Part::parse(&format!("copy=@{};filename=\"a\\\"b.pdf\"", path.display())).unwrap(),
+        ];
+        assert_eq!(
+            String::from_utf8(body(&parts, "BOUNDARY").unwrap()).unwrap(),
+            "--BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"title\"\r\n\
+             \r\n\
+             Report\r\n\
+             --BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"report.pdf\"\r\n\
+             Content-Type: application/pdf\r\n\
+             \r\n\
+             %PDF\r\n\
+             --BOUNDARY\r\n\
+             Content-Disposition: form-data; name=\"copy\"; filename=\"a%22b.pdf\"\r\n\
+             Content-Type: application/pdf\r\n\
+             \r\n\
+             %PDF\r\n\
+             --BOUNDARY--\r\n"
+        );
+    }
+}
@@ -0,0 +1,526 @@
+//! Formatting the response body for reading (`--pretty`, `--raw`)
+//!
+//! The formatters are tried in the order of FORMATTERS, and the first one which
+//! accepts the Content-Type of the response formats the body: JSON and XML (the
+//! errors and the listings of S3) are indented, an AWS event stream is decoded
+//! into its messages, and a binary body for a terminal becomes a hex dump. The
+//! body is formatted when it is printed to a terminal or with --pretty, and left
+//! as it is with --raw. A body which the formatter fails to read is also left as
+//! it is.
+
+use std::{fmt::Write as _, io::IsTerminal};
+
+use anyhow::{bail, ensure, Context};
+use http::{header::CONTENT_TYPE, HeaderMap};
+use xmlparser::{ElementEnd, Token, Tokenizer};
+
+use crate::{checksum, logging, Args};
+
+/// A way to show the response body of some content types.
+pub trait Formatter {
+    /// The name for --verbose (Ex. "JSON")
+    fn name(&self) -> &'static str;
+
+    /// Whether BODY of CONTENT_TYPE, the lowercase media type without the
+    /// parameters, is formatted. TERMINAL tells that it is printed to a terminal.
+    fn accepts(&self, content_type: &str, body: &[u8], terminal: bool) -> bool;
+
+    fn format(&self, body: &[u8]) -> anyhow::Result<Vec<u8>>;
+}
+
+const FORMATTERS: [&dyn Formatter; 4] = [&Json, &Xml, &EventStream, &HexDump];
+
+/// The formatter for BODY of CONTENT_TYPE, if any.
+fn select(content_type: &str, body: &[u8], terminal: bool) -> Option<&'static dyn Formatter> {
+    FORMATTERS
+        .into_iter()
+        .find(|formatter| formatter.accepts(content_type, body, terminal))
+}
+
+/// BODY of the response with HEADERS formatted as ARGS tells. TO_STDOUT tells
+/// that it is printed to stdout, rather than written to a file.
+pub fn apply(body: Vec<u8>, headers: &HeaderMap, args: &Args, to_stdout: bool) -> Vec<u8> {
+    let terminal = to_stdout && std::io::stdout().is_terminal();
+    if args.raw || !(args.pretty || terminal) || body.is_empty() {
+        return body;
+    }
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let Some(formatter) = select(&content_type, &body, terminal) else {
+        return body;
+    };
+    match formatter.format(&body) {
+        Ok(formatted) => formatted,
+        Err(e) => {
+            if args.verbose {
+                logging::note(format_args!(
+                    "The body is printed as it is, not as {}: {}",
+                    formatter.name(),
+                    e
+                ));
+            }
+            body
+        }
+    }
+}
+
+struct Json;
+
+impl Formatter for Json {
+    fn name(&self) -> &'static str {
+        "JSON"
+    }
+
+    // application/json, application/x-amz-json-1.1, application/problem+json ...
+    fn accepts(&self, content_type: &str, _body: &[u8], _terminal: bool) -> bool {
+        content_type.contains("json")
+    }
+
+    /// Indented with 2 spaces, keeping the order of the keys and the numbers as
+    /// they are.
+    fn format(&self, body: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let text = std::str::from_utf8(body)?;
+        serde_json::from_str::<serde::de::IgnoredAny>(text)?;
+        let mut out = String::with_capacity(text.len() * 2);
+        let mut depth = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if in_string {
+                out.push(c);
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => {
+                    in_string = true;
+                    out.push(c);
+                }
+                '{' | '[' => {
+                    out.push(c);
+                    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                    // An empty object or array stays on the line.
+                    if let Some(close) = chars.next_if(|c| matches!(c, '}' | ']')) {
+                        out.push(close);
+                        continue;
+                    }
+                    depth += 1;
+                    newline(&mut out, depth);
+                }
+                '}' | ']' => {
+                    depth -= 1;
+                    newline(&mut out, depth);
+                    out.push(c);
+                }
+                ',' => {
+                    out.push(c);
+                    newline(&mut out, depth);
+                }
+                ':' => out.push_str(": "),
+                _ if c.is_whitespace() => {}
+                _ => out.push(c),
+            }
+        }
+        out.push('\n');
+        Ok(out.into_bytes())
+    }
+}
+
+fn newline(out: &mut String, depth: usize) {
+    out.push('\n');
+    out.push_str(&"  ".repeat(depth));
+}
+
+struct Xml;
+
+impl Formatter for Xml {
+    fn name(&self) -> &'static str {
+        "XML"
+    }
+
+    fn accepts(&self, content_type: &str, _body: &[u8], _terminal: bool) -> bool {
+        content_type.ends_with("/xml") || content_type.ends_with("+xml")
+    }
+
+    /// An element on each line indented with 2 spaces. The element which has
+    /// only text is kept on a line (Ex. <Key>a.txt</Key>).
+    fn format(&self, body: &[u8]) -> anyhow::Result<Vec<u8>> {
+        #[derive(PartialEq)]
+        enum Last {
+            Open,
+            Text,
+            Close,
+        }
+        let text = std::str::from_utf8(body)?;
+        let mut out = String::with_capacity(text.len() * 2);
+        let mut depth = 0;
+        let mut last = Last::Close;
+        for token in Tokenizer::from(text) {
+            match token? {
+                Token::ElementStart { span, .. } => {
+                    if !out.is_empty() {
+                        newline(&mut out, depth);
+                    }
+                    out.push_str(&span);
+                }
+                Token::Attribute { span, .. } => {
+                    out.push(' ');
+                    out.push_str(&span);
+                }
+                Token::ElementEnd { end, span } => match end {
+                    ElementEnd::Open => {
+                        out.push('>');
+                        depth += 1;
+                        last = Last::Open;
+                    }
+                    ElementEnd::Empty => {
+                        out.push_str("/>");
+                        last = Last::Close;
+                    }
+                    ElementEnd::Close(..) => {
+                        depth = depth.checked_sub(1).context("unexpected end tag")?;
+                        if last == Last::Close {
+                            newline(&mut out, depth);
+                        }
+                        out.push_str(&span);
+                        last = Last::Close;
+                    }
+                },
+                Token::Text { text } if text.trim().is_empty() => {}
+                Token::Text { text } => {
+                    if last == Last::Close {
+                        newline(&mut out, depth);
+                    }
+                    out.push_str(text.trim());
+                    last = Last::Text;
+                }
+                Token::Cdata { span, .. } => {
+                    if last == Last::Close {
+                        newline(&mut out, depth);
+                    }
+                    out.push_str(&span);
+                    last = Last::Text;
+                }
+                token => {
+                    if !out.is_empty() {
+                        newline(&mut out, depth);
+                    }
+                    out.push_str(token_span(&token));
+                    last = Last::Close;
+                }
+            }
+        }
+        ensure!(depth == 0, "unclosed element");
+        out.push('\n');
+        Ok(out.into_bytes())
+    }
+}
+
+/// The text of the declaration, the comment and the DTD tokens.
+fn token_span<'a>(token: &Token<'a>) -> &'a str {
+    match token {
+        Token::Declaration { span, .. }
+        | Token::ProcessingInstruction { span, .. }
+        | Token::Comment { span, .. }
+        | Token::DtdStart { span, .. }
+        | Token::EmptyDtd { span, .. }
+        | Token::EntityDeclaration { span, .. }
+        | Token::DtdEnd { span } => span.as_str(),
+        _ => "",
+    }
+}
+
+/// The messages of an AWS event stream (application/vnd.amazon.eventstream),
+/// which S3 Select, Transcribe and Bedrock respond with.
+struct EventStream;
+
+impl Formatter for EventStream {
+    fn name(&self) -> &'static str {
+        "event stream"
+    }
+
+    fn accepts(&self, content_type: &str, _body: &[u8], _terminal: bool) -> bool {
+        content_type == "application/vnd.amazon.eventstream"
+    }
+
+    /// The headers of each message as "name: value" and its payload, followed
+    /// by a blank line.
+    fn format(&self, mut body: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut out = String::new();
+        while !body.is_empty() {
+            ensure!(body.len() >= 16, "truncated message");
+            let total = u32::from_be_bytes(body[0..4].try_into()?) as usize;
+            let headers_len = u32::from_be_bytes(body[4..8].try_into()?) as usize;
+            ensure!(
+                total <= body.len() && 16 + headers_len <= total,
+                "truncated message"
+            );
+            let prelude_crc = u32::from_be_bytes(body[8..12].try_into()?);
+            ensure!(
+                checksum::crc32(0xEDB8_8320, &body[..8]) == prelude_crc,
+                "prelude CRC mismatch"
+            );
+            let message_crc = u32::from_be_bytes(body[total - 4..total].try_into()?);
+            ensure!(
+                checksum::crc32(0xEDB8_8320, &body[..total - 4]) == message_crc,
+                "message CRC mismatch"
+            );
+            let mut headers = &body[12..12 + headers_len];
+            while !headers.is_empty() {
+                let (name, value, rest) = event_header(headers).context("invalid header")?;
+                let _ = writeln!(out, "{}: {}", name, value);
+                headers = rest;
+            }
+            let payload = &body[12 + headers_len..total - 4];
+            match std::str::from_utf8(payload) {
+                Ok("") => {}
+                Ok(text) => {
+                    let _ = writeln!(out, "{}", text.trim_end());
+                }
+                Err(_) => {
+                    let _ = writeln!(out, "({} bytes)", payload.len());
+                }
+            }
+            out.push('\n');
+            body = &body[total..];
+        }
+        Ok(out.into_bytes())
+    }
+}
+
+/// The name and the value of the first header in RAW, and the rest of RAW.
+fn event_header(raw: &[u8]) -> Option<(String, String, &[u8])> {
+    let (&name_len, raw) = raw.split_first()?;
+    let name = String::from_utf8_lossy(raw.get(..name_len as usize)?).into_owned();
+    let raw = &raw[name_len as usize..];
+    let (&kind, raw) = raw.split_first()?;
+    let fixed = |len: usize| raw.get(..len).map(|value| (value, &raw[len..]));
+    let integer = |value: &[u8]| {
+        let mut buf = [0; 8];
+        buf[8 - value.len()..].copy_from_slice(value);
+        // Sign-extended from the length of the value.
+        (i64::from_be_bytes(buf) << (64 - value.len() * 8)) >> (64 - value.len() * 8)
+    };
+    let (value, rest) = match kind {
+        0 => ("true".to_string(), raw),
+        1 => ("false".to_string(), raw),
+        2 | 3 | 4 | 5 | 8 => {
+            // byte, short, integer, long and timestamp (milliseconds)
+            let len = match kind {
+                2 => 1,
+                3 => 2,
+                4 => 4,
+                _ => 8,
+            };
+            let (value, rest) = fixed(len)?;
+            (integer(value).to_string(), rest)
+        }
+        6 | 7 => {
+            let len = u16::from_be_bytes(raw.get(..2)?.try_into().ok()?) as usize;
+            let value = raw.get(2..2 + len)?;
+            let value = match kind {
+                6 => hex::encode(value),
+                _ => String::from_utf8_lossy(value).into_owned(),
+            };
+            (value, &raw[2 + len..])
+        }
+        9 => {
+            let (value, rest) = fixed(16)?;
+            let hex = hex::encode(value);
+            let uuid = format!(
+                "{}-{}-{}-{}-{}",
+                &hex[..8],
+                &hex[8..12],
+                &hex[12..16],
+                &hex[16..20],
+                &hex[20..]
+            );
+            (uuid, rest)
+        }
+        _ => return None,
+    };
+    Some((name, value, rest))
+}
+
+/// The binary body printed to a terminal, which would break it otherwise.
+struct HexDump;
+
+impl Formatter for HexDump {
+    fn name(&self) -> &'static str {
+        "hex dump"
+    }
+
+    fn accepts(&self, _content_type: &str, body: &[u8], terminal: bool) -> bool {
+        terminal && std::str::from_utf8(body).is_err()
+    }
+
+    /// The offset, 16 bytes in hex and their printable characters on each line,
+    /// same as `hexdump -C`.
+    fn format(&self, body: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if body.is_empty() {
+            bail!("empty body");
+        }
+        let mut out = String::new();
+        for (i, line) in body.chunks(16).enumerate() {
+            let _ = write!(out, "{:08x} ", i * 16);
+            for j in 0..16 {
+                if j == 8 {
+                    out.push(' ');
+                }
+                match line.get(j) {
+                    Some(b) => {
+                        let _ = write!(out, " {:02x}", b);
+                    }
+                    None => out.push_str("   "),
+                }
+            }
+            let text = line
+                .iter()
+                .map(|&b| match b {
+                    0x20..=0x7e => b as char,
+                    _ => '.',
+                })
+                .collect::<String>();
+            let _ = writeln!(out, "  |{}|", text);
+        }
+        let _ = writeln!(out, "{:08x}", body.len());
+        Ok(out.into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{select, EventStream, Formatter, HexDump, Json, Xml};
+    use crate::checksum;
+
+    fn formatted(formatter: &dyn Formatter, body: &[u8]) -> String {
+        String::from_utf8(formatter.format(body).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn selection() {
+        let name = |content_type, body, terminal| {
+            select(content_type, body, terminal).map(|formatter| formatter.name())
+        };
+        assert_eq!(name("application/json", b"{}", false), Some("JSON"));
+        assert_eq!(
+            name("application/x-amz-json-1.1", b"{}", false),
+            Some("JSON")
+        );
+        assert_eq!(name("application/xml", b"<a/>", false), Some("XML"));
+        assert_eq!(name("application/atom+xml", b"<a/>", false), Some("XML"));
+        assert_eq!(
+            name("application/vnd.amazon.eventstream", b"\0", true),
+            Some("event stream")
+        );
+        assert_eq!(name("image/png", b"\x89PNG\xff", true), Some("hex dump"));
+        assert_eq!(name("image/png", b"\x89PNG\xff", false), None);
+        assert_eq!(name("text/plain", b"text", true), None);
+    }
+
+    #[test]
+    fn json() {
+        let body = br#"{"b":1.50,"a":[1, {}, [ ]],"s":"x,{\"y\":[]}"}"#;
+        insta::assert_snapshot!(formatted(&Json, body), @r#"
+        {
+          "b": 1.50,
+          "a": [
+            1,
+            {},
+            []
+          ],
+          "s": "x,{\"y\":[]}"
+        }
+        "#);
+        assert!(Json.format(b"{\"a\":").is_err());
+        assert!(Json.format(b"{}\n{}").is_err());
+    }
+
+    #[test]
+    fn xml() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?>
+<ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Name>bucket</Name><Prefix></Prefix><Contents><Key>a&amp;b.txt</Key><Size>3</Size></Contents><IsTruncated>false</IsTruncated><Empty/></ListBucketResult>"#;
+        insta::assert_snapshot!(formatted(&Xml, body), @r#"
+        <?xml version="1.0" encoding="UTF-8"?>
+        <ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+          <Name>bucket</Name>
+          <Prefix></Prefix>
+          <Contents>
+            <Key>a&amp;b.txt</Key>
+            <Size>3</Size>
+          </Contents>
+          <IsTruncated>false</IsTruncated>
+          <Empty/>
+        </ListBucketResult>
+        "#);
+        assert!(Xml.format(b"<a><b></a>").is_err());
+        assert!(Xml.format(b"<a>").is_err());
+    }
+
+    /// An event stream message with a string header and PAYLOAD.
+    fn message(headers: &[(&str, &str)], payload: &[u8]) -> Vec<u8> {
+        let mut raw_headers = Vec::new();
+        for (name, value) in headers {
+            raw_headers.push(name.len() as u8);
+            raw_headers.extend_from_slice(name.as_bytes());
+            raw_headers.push(7);
+            raw_headers.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            raw_headers.extend_from_slice(value.as_bytes());
+        }
+        let total = 16 + raw_headers.len() + payload.len();
+        let mut message = Vec::new();
+        message.extend_from_slice(&(total as u32).to_be_bytes());
+        message.extend_from_slice(&(raw_headers.len() as u32).to_be_bytes());
+        let crc = checksum::crc32(0xEDB8_8320, &message);
+        message.extend_from_slice(&crc.to_be_bytes());
+        message.extend_from_slice(&raw_headers);
+        message.extend_from_slice(payload);
+        let crc = checksum::crc32(0xEDB8_8320, &message);
+        message.extend_from_slice(&crc.to_be_bytes());
+        message
+    }
+
+    #[test]
+    fn event_stream() {
+        let mut body = message(
+            &[(":message-type", "event"), (":event-type", "Records")],
+            b"a,b\n",
+        );
+        body.extend(message(&[(":event-type", "End")], b""));
+        insta::assert_snapshot!(formatted(&EventStream, &body), @r"
+        :message-type: event
+        :event-type: Records
+        a,b
+
+        :event-type: End
+        ");
+
+        let len = body.len();
+        body[len - 1] ^= 1;
+        assert_eq!(
+            EventStream.format(&body).unwrap_err().to_string(),
+            "message CRC mismatch"
+        );
+        assert!(EventStream.format(&body[..20]).is_err());
+    }
+
+    #[test]
+    fn hex_dump() {
+        insta::assert_snapshot!(formatted(&HexDump, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\x01"), @r"
+        00000000  89 50 4e 47 0d 0a 1a 0a  00 00 00 0d 49 48 44 52  |.PNG........IHDR|
+        00000010  00 01                                             |..|
+        00000012
+        ");
+    }
+}
@@ -0,0 +1,498 @@
+//! Requests given as curl command lines (`--from-curl`)
+//!
+//! The command is split into words like the shell does, so that a command
+//! copied from the documents can be pasted as it is, with its quotes and the
+//! backslashes at the ends of the lines. The options which make the request
+//! (the URL, -X, -H, the body ...) are taken, and the others are ignored with a
+//! warning, since curl options of the same name in awscurl do not always mean
+//! the same.
+
+use anyhow::{bail, Context};
+
+use crate::{body_size, data, form::Part, logging, parse_aws_sigv4, parse_user, Args};
+
+/// The options of curl which take a value, with their short names. The value of
+/// an unknown option is taken as a URL, so the ones ignored are listed too.
+const VALUE_OPTIONS: [(Option<char>, &str); 45] = [
+    // Taken
+    (Some('X'), "--request"),
+    (Some('H'), "--header"),
+    (Some('d'), "--data"),
+    (None, "--data-ascii"),
+    (None, "--data-binary"),
+    (None, "--data-raw"),
+    (None, "--data-urlencode"),
+    (None, "--json"),
+    (Some('F'), "--form"),
+    (None, "--form-string"),
+    (Some('T'), "--upload-file"),
+    (None, "--url"),
+    (Some('u'), "--user"),
+    (None, "--aws-sigv4"),
+    (Some('A'), "--user-agent"),
+    (Some('e'), "--referer"),
+    (Some('b'), "--cookie"),
+    // Ignored
+    (Some('o'), "--output"),
+    (None, "--output-dir"),
+    (Some('w'), "--write-out"),
+    (Some('D'), "--dump-header"),
+    (Some('c'), "--cookie-jar"),
+    (Some('C'), "--continue-at"),
+    (Some('r'), "--range"),
+    (Some('x'), "--proxy"),
+    (Some('U'), "--proxy-user"),
+    (None, "--proxy-header"),
+    (Some('m'), "--max-time"),
+    (None, "--connect-timeout"),
+    (None, "--retry"),
+    (None, "--retry-delay"),
+    (None, "--retry-max-time"),
+    (None, "--max-redirs"),
+    (None, "--limit-rate"),
+    (Some('E'), "--cert"),
+    (None, "--cert-type"),
+    (None, "--key"),
+    (None, "--cacert"),
+    (None, "--capath"),
+    (None, "--resolve"),
+    (None, "--connect-to"),
+    (None, "--unix-socket"),
+    (None, "--oauth2-bearer"),
+    (Some('K'), "--config"),
+    (Some('z'), "--time-cond"),
+];
+
+/// The flags of curl which are taken, with their short names.
+const FLAGS: [(Option<char>, &str); 2] = [(Some('G'), "--get"), (Some('I'), "--head")];
+
+/// The flags which only change the progress and the errors printed by curl,
+/// which are ignored without a warning.
+const QUIET_FLAGS: [(Option<char>, &str); 4] = [
+    (Some('s'), "--silent"),
+    (Some('S'), "--show-error"),
+    (None, "--no-progress-meter"),
+    (Some('#'), "--progress-bar"),
+];
+
+/// A body option of curl with its value.
+#[derive(Debug, PartialEq)]
+enum Data {
+    /// -d, --data-ascii: "@FILE" without the newlines
+    Ascii(String),
+    /// --data-raw: taken as it is
+    Raw(String),
+    /// --data-binary, --json: "@FILE" as it is
+    Binary(String),
+    /// --data-urlencode
+    Urlencode(String),
+}
+
+impl Data {
+    fn read(&self, limit: Option<u64>) -> anyhow::Result<String> {
+        match self {
+            Data::Ascii(raw) => data::read_data(raw, limit),
+            Data::Raw(raw) => Ok(raw.clone()),
+            Data::Binary(raw) => match raw.strip_prefix('@') {
+                Some(path) => data::read_body_file(path, limit),
+                None => Ok(raw.clone()),
+            },
+            Data::Urlencode(raw) => data::urlencode(raw, limit),
+        }
+    }
+}
+
+/// The request of a curl command line.
+#[derive(Debug, Default, PartialEq)]
+struct Request {
+    urls: Vec<String>,
+    method: Option<String>,
+    headers: Vec<String>,
+    data: Vec<Data>,
+    /// The parts of -F and --form-string, which is true for the latter
+    form: Vec<(String, bool)>,
+    upload_file: Option<String>,
+    get: bool,
+    user: Option<String>,
+    aws_sigv4: Option<String>,
+    /// The options which are not taken, in the order they are given
+    ignored: Vec<String>,
+}
+
+impl Request {
+    /// Take the option NAME, which is long, with its VALUE.
+    fn apply(&mut self, name: &str, value: Option<String>) {
+        let Some(value) = value else {
+            match name {
+                "--get" => self.get = true,
+                "--head" => self.method = Some("HEAD".to_string()),
+                _ => self.ignore(name),
+            }
+            return;
+        };
+        match name {
+            "--request" => self.method = Some(value),
+            "--header" => self.headers.push(value),
+            "--data" | "--data-ascii" => self.data.push(Data::Ascii(value)),
+            "--data-raw" => self.data.push(Data::Raw(value)),
+            "--data-binary" => self.data.push(Data::Binary(value)),
+            "--data-urlencode" => self.data.push(Data::Urlencode(value)),
+            "--json" => {
+                self.data.push(Data::Binary(value));
+                for header in ["Content-Type: application/json", "Accept: application/json"] {
+                    if !self.headers.iter().any(|h| h == header) {
+                        self.headers.push(header.to_string());
+                    }
+                }
+            }
+            "--form" => self.form.push((value, false)),
+            "--form-string" => self.form.push((value, true)),
+            "--upload-file" => self.upload_file = Some(value),
+            "--url" => self.urls.push(value),
+            "--user" => self.user = Some(value),
+            "--aws-sigv4" => self.aws_sigv4 = Some(value),
+            "--user-agent" => self.headers.push(format!("User-Agent: {}", value)),
+            "--referer" => self.headers.push(format!("Referer: {}", value)),
+            // Without "=", the value is the file of the cookies.
+            "--cookie" if value.contains('=') => self.headers.push(format!("Cookie: {}", value)),
+            _ => self.ignore(name),
+        }
+    }
+
+    fn ignore(&mut self, name: &str) {
+        let quiet = QUIET_FLAGS.iter().any(|(_, long)| *long == name);
+        if !quiet && !self.ignored.iter().any(|ignored| ignored == name) {
+            self.ignored.push(name.to_string());
+        }
+    }
+}
+
+/// The long name of the short option C, and whether it takes a value. An
+/// unknown one is returned as it is.
+fn long_name(c: char) -> (String, bool) {
+    let find = |options: &[(Option<char>, &str)]| {
+        options
+            .iter()
+            .find(|(short, _)| *short == Some(c))
+            .map(|(_, long)| long.to_string())
+    };
+    if let Some(long) = find(&VALUE_OPTIONS) {
+        return (long, true);
+    }
+    let long = find(&FLAGS)
+        .or_else(|| find(&QUIET_FLAGS))
+        .unwrap_or_else(|| format!("-{}", c));
+    (long, false)
+}
+
+/// Parse COMMAND, which starts with "curl".
+fn parse(command: &str) -> anyhow::Result<Request> {
+    // The lines are joined first, since the splitter leaves an empty word for
+    // a backslash at the end of a line which follows a space.
+    let command = command.replace("\r\n", "\n").replace("\\\n", " ");
+    let words = shlex::split(&command)
+        .context("Unable to split the curl command into words. Check the quotes")?;
+    let mut words = words.into_iter().peekable();
+    if words
+        .peek()
+        .is_some_and(|word| word == "curl" || word.ends_with("/curl"))
+    {
+        words.next();
+    }
+    let mut request = Request::default();
+    let mut options = true;
+    while let Some(word) = words.next() {
+        if !options || !word.starts_with('-') || word == "-" {
+            request.urls.push(word);
+            continue;
+        }
+        if word == "--" {
+            options = false;
+            continue;
+        }
+        if word.starts_with("--") {
+            let takes_value = VALUE_OPTIONS.iter().any(|(_, long)| *long == word);
+            let value = match takes_value {
+                true => Some(
+                    words
+                        .next()
+                        .with_context(|| format!("{} needs a value", word))?,
+                ),
+                false => None,
+            };
+            request.apply(&word, value);
+            continue;
+        }
+        // Short options can be joined (Ex. -sSL), and the value can follow the
+        // name (Ex. -XPOST).
+        for (i, c) in word[1..].char_indices() {
+            let (name, takes_value) = long_name(c);
+            if !takes_value {
+                request.apply(&name, None);
+                continue;
+            }
+            let rest = &word[1 + i + c.len_utf8()..];
+            let value = match rest.is_empty() {
+                true => words
+                    .next()
+                    .with_context(|| format!("-{} needs a value", c))?,
+                false => rest.to_string(),
+            };
+            request.apply(&name, Some(value));
+            break;
+        }
+    }
+    Ok(request)
+}
+
+/// URL with BODY appended to its query, which is -G of curl.
+fn append_query(url: &str, body: &str) -> String {
+    let (url, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, format!("#{}", fragment)),
+        None => (url, String::new()),
+    };
+    let separator = match url.contains('?') {
+        true => "&",
+        false => "?",
+    };
+    format!("{}{}{}{}", url, separator, body, fragment)
+}
+
+/// Replace ARGS with the request of --from-curl. The URL, -X, -H and the body on
+/// the command line override the ones in the command.
+pub fn install(args: &mut Args) -> anyhow::Result<()> {
+    let Some(command) = &args.from_curl else {
+        return Ok(());
+    };
+    let command = match command.as_str() {
+        "-" => data::read_file("-")?,
+        command => command.to_string(),
+    };
+    let mut request = parse(&command)?;
+    if request.urls.len() > 1 {
+        bail!(
+            "The curl command has {} URLs. Give one request to --from-curl: {}",
+            request.urls.len(),
+            request.urls.join(" ")
+        );
+    }
+    if request.user.is_some() && request.aws_sigv4.is_none() && args.aws_sigv4.is_none() {
+        // The basic authentication, which is replaced with the signature
+        request.user = None;
+        request.ignore("--user");
+    }
+    if !request.ignored.is_empty() {
+        logging::warning(format_args!(
+            "--from-curl ignores {} of the curl command. Give the options of awscurl for them",
+            request.ignored.join(", ")
+        ));
+    }
+
+    let mut url = args
+        .url
+        .take()
+        .or(request.urls.pop())
+        .context("The curl command has no URL")?;
+    let has_body = !args.data.is_empty()
+        || args.has_form()
+        || args.data_hex.is_some()
+        || args.data_base64.is_some()
+        || args.upload_file.is_some();
+    if !has_body {
+        let data = request
+            .data
+            .iter()
+            .map(|data| data.read(body_size::limit(args)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if request.get {
+            if !data.is_empty() {
+                url = append_query(&url, &data.join("&"));
+            }
+            request.method = request.method.or(Some("GET".to_string()));
+        } else {
+            args.data = data;
+        }
+        for (raw, literal) in request.form {
+            let part = match literal {
+                true => Part::literal(&raw)?,
+                false => Part::parse(&raw)?,
+            };
+            args.form_parts.push(part);
+            match literal {
+                true => args.form_string.push(raw),
+                false => args.form.push(raw),
+            }
+        }
+        args.upload_file = request.upload_file.map(Into::into);
+    }
+    args.url = Some(url);
+    args.method = args.method.take().or(request.method);
+    args.header.splice(0..0, request.headers);
+    if args.aws_sigv4.is_none() {
+        if let Some(raw) = &request.aws_sigv4 {
+            args.aws_sigv4 = Some(parse_aws_sigv4(raw).map_err(anyhow::Error::msg)?);
+        }
+    }
+    if args.user.is_none() {
+        if let Some(raw) = &request.user {
+            args.user = Some(parse_user(raw).map_err(|e| anyhow::anyhow!("Invalid -u: {}", e))?);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::{append_query, install, parse, Data};
+    use crate::Args;
+
+    #[test]
+    fn parse_command() {
+        let request = parse(
+            r#"curl -X POST "https://api.example.com/items?a=1" \
+  -H 'Content-Type: application/json' \
+  -d '{"name": "it'\''s"}' -sSL --compressed"#,
+        )
+        .unwrap();
+        assert_eq!(request.urls, ["https://api.example.com/items?a=1"]);
+        assert_eq!(request.method.as_deref(), Some("POST"));
+        assert_eq!(request.headers, ["Content-Type: application/json"]);
+        assert_eq!(
+            request.data,
+            [Data::Ascii(r#"{"name": "it's"}"#.to_string())]
+        );
+        assert_eq!(request.ignored, ["-L", "--compressed"]);
+    }
+
+    #[test]
+    fn short_options() {
+        let request = parse("curl -XPUT -sd@body.json -Hx-a:1 -A agent -b a=1 -b jar.txt -o out --url https://example.com").unwrap();
+        assert_eq!(request.method.as_deref(), Some("PUT"));
+        assert_eq!(request.data, [Data::Ascii("@body.json".to_string())]);
+        assert_eq!(
+            request.headers,
+            ["x-a:1", "User-Agent: agent", "Cookie: a=1"]
+        );
+        assert_eq!(request.ignored, ["--cookie", "--output"]);
+        assert_eq!(request.urls, ["https://example.com"]);
+
+        let request = parse("curl -G -F a=1 --form-string 'b=@x' --data-raw @c -- -u").unwrap();
+        assert!(request.get);
+        assert_eq!(
+            request.form,
+            [("a=1".to_string(), false), ("b=@x".to_string(), true)]
+        );
+        assert_eq!(request.data, [Data::Raw("@c".to_string())]);
+        assert_eq!(request.urls, ["-u"]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse("curl -d 'a").is_err());
+        assert_eq!(
+            parse("curl https://example.com -H")
+                .unwrap_err()
+                .to_string(),
+            "-H needs a value"
+        );
+        assert_eq!(
+            parse("curl --data-raw").unwrap_err().to_string(),
+            "--data-raw needs a value"
+        );
+    }
+
+    #[test]
+    fn query() {
+        assert_eq!(
+            append_query("https://example.com/a#top", "q=1"),
+            "https://example.com/a?q=1#top"
+        );
+        assert_eq!(
+            append_query("https://example.com/a?x=1", "q=1&r=2"),
+            "https://example.com/a?x=1&q=1&r=2"
+        );
+    }
+
+    fn installed(options: &[&str]) -> anyhow::Result<Args> {
+        let mut args = Args::parse_from(["awscurl"].iter().chain(options));
+        install(&mut args)?;
+        Ok(args)
+    }
+
+    #[test]
+    fn install_request() {
+        let command = "curl -X POST https://api.example.com/items -H 'Accept: text/plain' -d a=1 --data-urlencode 'b=x y'";
+        let args = installed(&["--from-curl", command]).unwrap();
+        assert_eq!(args.url.as_deref(), Some("https://api.example.com/items"));
+        assert_eq!(args.method.as_deref(), Some("POST"));
+        assert_eq!(args.header, ["Accept: text/plain"]);
+        assert_eq!(args.data, ["a=1", "b=x%20y"]);
+
+        // The command line overrides the command.
+        let args = installed(&[
+            "--from-curl",
+            command,
+            "http://localhost:8080/items",
+            "-X",
+            "PUT",
+            "-H",
+            "Accept: application/json",
+            "-d",
+            "c=3",
+        ])
+        .unwrap();
+        assert_eq!(args.url.as_deref(), Some("http://localhost:8080/items"));
+        assert_eq!(args.method.as_deref(), Some("PUT"));
+        assert_eq!(
+            args.header,
+            ["Accept: text/plain", "Accept: application/json"]
+        );
+        assert_eq!(args.data, ["c=3"]);
+
+        let args = installed(&[
+            "--from-curl",
+            "curl -G https://example.com/search -d q=1 -d n=2",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.url.as_deref(),
+            Some("https://example.com/search?q=1&n=2")
+        );
+        assert_eq!(args.method.as_deref(), Some("GET"));
+        assert!(args.data.is_empty());
+
+        let args = installed(&[
+            "--from-curl",
+            "curl --aws-sigv4 aws:amz:us-west-2:es -u AKID:secret https://example.com -F a=1",
+        ])
+        .unwrap();
+        assert_eq!(args.user.unwrap().0, "AKID");
+        assert_eq!(args.aws_sigv4.unwrap().region.as_deref(), Some("us-west-2"));
+        assert_eq!(args.form, ["a=1"]);
+        assert_eq!(args.form_parts.len(), 1);
+
+        // -u without --aws-sigv4 is the basic authentication.
+        let args = installed(&["--from-curl", "curl -u user:pass https://example.com"]).unwrap();
+        assert!(args.user.is_none());
+    }
+
+    #[test]
+    fn install_errors() {
+        assert_eq!(
+            installed(&["--from-curl", "curl -s"])
+                .err()
+                .unwrap()
+                .to_string(),
+            "The curl command has no URL"
+        );
+        assert_eq!(
+            installed(&["--from-curl", "curl https://a.example.com https://b.example.com"])
+                .err()
+                .unwrap()
+                .to_string(),
+            "The curl command has 2 URLs. Give one request to --from-curl: https://a.example.com https://b.example.com"
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! Compressing the request body with gzip (`--gzip-if-larger`)
+//!
+//! The body larger than the threshold is compressed before the request is
+//! signed, so that the payload hash is the one of the compressed body, and
+//! "Content-Encoding: gzip" is added to the signed headers. The body is
+//! deflated with the fixed Huffman codes, and the header has no time, so that
+//! the same body is always compressed to the same bytes.
+
+use crate::{checksum, logging, upload::Upload, AwsCurlParam};
+
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// The candidates compared for each position, which bounds the time.
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// The bits of a deflate stream, which are packed from the least significant.
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, len: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += len;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Write the Huffman CODE, which is packed from the most significant bit.
+    fn write_code(&mut self, code: u32, len: u32) {
+        self.write(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+
+    /// The literal or the length SYMBOL with the fixed Huffman codes.
+    fn symbol(&mut self, symbol: u32) {
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xC0 + symbol - 280, 8),
+        }
+    }
+
+    fn matched(&mut self, length: usize, distance: usize) {
+        let i = LENGTH_BASE
+            .iter()
+            .rposition(|base| *base as usize <= length)
+            .unwrap();
+        self.symbol(257 + i as u32);
+        self.write(
+            (length - LENGTH_BASE[i] as usize) as u32,
+            LENGTH_EXTRA[i] as u32,
+        );
+        let i = DISTANCE_BASE
+            .iter()
+            .rposition(|base| *base as usize <= distance)
+            .unwrap();
+        self.write_code(i as u32, 5);
+        self.write(
+            (distance - DISTANCE_BASE[i] as usize) as u32,
+            DISTANCE_EXTRA[i] as u32,
+        );
+    }
+}
+
+/// Record the position I in the hash chains of DATA.
+fn insert(data: &[u8], i: usize, head: &mut [usize], prev: &mut [usize]) {
+    if i + MIN_MATCH <= data.len() {
+        let h = hash(&data[i..]);
+        prev[i] = head[h];
+        head[h] = i;
+    }
+}
+
+fn hash(data: &[u8]) -> usize {
+    let value = (data[0] as usize) << 16 | (data[1] as usize) << 8 | data[2] as usize;
+    (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) & ((1 << HASH_BITS) - 1)
+}
+
+/// DATA compressed into a single deflate block with the fixed Huffman codes.
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    // The final block with the fixed Huffman codes.
+    writer.write(1, 1);
+    writer.write(1, 2);
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+    let mut i = 0;
+    while i < data.len() {
+        let (mut length, mut distance) = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let max = (data.len() - i).min(MAX_MATCH);
+            let mut candidate = head[hash(&data[i..])];
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || i - candidate > WINDOW {
+                    break;
+                }
+                let len = (0..max)
+                    .take_while(|k| data[candidate + k] == data[i + k])
+                    .count();
+                if len > length {
+                    (length, distance) = (len, i - candidate);
+                    if len == max {
+                        break;
+                    }
+                }
+                candidate = prev[candidate];
+            }
+        }
+        if length >= MIN_MATCH {
+            writer.matched(length, distance);
+            for j in i..i + length {
+                insert(data, j, &mut head, &mut prev);
+            }
+            i += length;
+        } else {
+            writer.symbol(data[i] as u32);
+            insert(data, i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+    writer.symbol(256);
+    writer.finish()
+}
+
+/// DATA in the gzip format (RFC 1952).
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    // No file name and no time, and the OS is unknown.
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+    out.extend(deflate(data));
+    out.extend(checksum::crc32(0xEDB8_8320, data).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out
+}
+
+/// Compress the body of PARAM if it is larger than THRESHOLD bytes.
+pub fn install(param: &mut AwsCurlParam, threshold: u64) -> anyhow::Result<()> {
+    let text = param.args.body();
+    let body = match (&param.upload, &text) {
+        (Some(Upload::Bytes(bytes)), _) => bytes.as_slice(),
+        (Some(Upload::File { .. } | Upload::Stream(_)), _) => {
+            logging::warning("--gzip-if-larger does not compress the streamed body");
+            return Ok(());
+        }
+        (None, Some(text)) => text.as_bytes(),
+        (None, None) => return Ok(()),
+    };
+    let encoded = param
+        .headers()?
+        .keys()
+        .any(|key| key.eq_ignore_ascii_case("content-encoding"));
+    if encoded {
+        logging::warning(
+            "--gzip-if-larger does not compress the body, since -H gives Content-Encoding",
+        );
+        return Ok(());
+    }
+    if body.len() as u64 <= threshold {
+        if param.args.verbose {
+            logging::note(format_args!(
+                "The body of {} bytes is sent uncompressed, not larger than --gzip-if-larger {}",
+                body.len(),
+                threshold
+            ));
+        }
+        return Ok(());
+    }
+    let compressed = encode(body);
+    if param.args.verbose {
+        logging::note(format_args!(
+            "The body is compressed with gzip from {} to {} bytes",
+            body.len(),
+            compressed.len()
+        ));
+    }
+    param.args.header.push("Content-Encoding: gzip".to_string());
+    // Sent instead of the text of --data, which is kept for the method and the
+    // content-type.
+    param.upload = Some(Upload::Bytes(compressed));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, DISTANCE_BASE, DISTANCE_EXTRA, LENGTH_BASE, LENGTH_EXTRA};
+    use crate::checksum;
+
+    /// The bits of a deflate stream from the least significant.
+    struct BitReader<'a> {
+        data: &'a [u8],
+        position: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bit(&mut self) -> u32 {
+            let bit = (self.data[self.position / 8] >> (self.position % 8)) & 1;
+            self.position += 1;
+            bit as u32
+        }
+
+        fn bits(&mut self, len: u8) -> usize {
+            (0..len).fold(0, |value, i| value | (self.bit() as usize) << i)
+        }
+
+        fn code(&mut self, len: u32) -> u32 {
+            (0..len).fold(0, |code, _| code << 1 | self.bit())
+        }
+
+        fn symbol(&mut self) -> u32 {
+            let code = self.code(7);
+            if code <= 0x17 {
+                return 256 + code;
+            }
+            let code = code << 1 | self.bit();
+            match code {
+                0x30..=0xBF => code - 0x30,
+                0xC0..=0xC7 => 280 + code - 0xC0,
+                _ => 144 + (code << 1 | self.bit()) - 0x190,
+            }
+        }
+    }
+
+    /// Decode GZIP with a single block of the fixed Huffman codes.
+    fn decode(gzip: &[u8]) -> Vec<u8> {
+        assert_eq!(gzip[..4], [0x1f, 0x8b, 8, 0]);
+        let mut reader = BitReader {
+            data: &gzip[10..gzip.len() - 8],
+            position: 0,
+        };
+        assert_eq!(reader.bits(3), 0b011);
+        let mut out = Vec::new();
+        loop {
+            let symbol = reader.symbol();
+            match symbol {
+                0..=255 => out.push(symbol as u8),
+                256 => break,
+                _ => {
+                    let i = symbol as usize - 257;
+                    let length = LENGTH_BASE[i] as usize + reader.bits(LENGTH_EXTRA[i]);
+                    let i = reader.code(5) as usize;
+                    let distance = DISTANCE_BASE[i] as usize + reader.bits(DISTANCE_EXTRA[i]);
+                    for _ in 0..length {
+                        out.push(out[out.len() - distance]);
+                    }
+                }
+            }
+        }
+        let trailer = &gzip[gzip.len() - 8..];
+        assert_eq!(
+            trailer[..4],
+            checksum::crc32(0xEDB8_8320, &out).to_le_bytes()
+        );
+        assert_eq!(trailer[4..], (out.len() as u32).to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn round_trip() {
+        let log = (0..2000)
+            .map(|i| {
+                format!(
+                    "{{\"level\":\"info\",\"seq\":{},\"message\":\"request done\"}}\n",
+                    i
+                )
+            })
+            .collect::<String>();
+        let binary = (0..70000u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect::<Vec<_>>();
+        for data in [b"".as_slice(), b"a", b"aaaaaaaaaa", log.as_bytes(), &binary] {
+            assert_eq!(decode(&encode(data)), data);
+        }
+        assert!(encode(log.as_bytes()).len() < log.len() / 5);
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(
+            encode(b"")[..],
+            [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! Sending the header names as they are given (`--preserve-header-case`)
+//!
+//! http::HeaderMap, and so reqwest and hyper, keep the names in lowercase, which
+//! some backends compare case-sensitively. The request is sent on its own
+//! HTTP/1.1 connection instead, whose request head is rewritten to the spelling
+//! of -H on the way out. Only the case changes, so the head keeps its length.
+//! The signature is calculated with the lowercase names as SigV4 requires.
+
+use std::{
+    collections::HashMap,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context as TaskContext, Poll},
+};
+
+use anyhow::Context;
+use bytes::Bytes;
+use http::header::HOST;
+use hyper_util::rt::TokioIo;
+use rustls::pki_types::ServerName;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+
+use crate::{
+    logging::{self, Level},
+    tls, Args,
+};
+
+/// The spellings of the header names given with -H, by their lowercase names.
+pub struct Spellings(HashMap<String, String>);
+
+impl Spellings {
+    pub fn new(headers: &[String]) -> Self {
+        let spellings = headers
+            .iter()
+            .filter_map(|header| header.split_once(':'))
+            .map(|(name, _)| name.trim())
+            .filter(|name| name.is_ascii())
+            .map(|name| (name.to_ascii_lowercase(), name.to_string()))
+            .collect();
+        Spellings(spellings)
+    }
+
+    /// The spelling of NAME, which is NAME itself if it is not given with -H.
+    pub fn get<'a>(&'a self, name: &'a str) -> &'a str {
+        self.0
+            .get(&name.to_ascii_lowercase())
+            .map_or(name, String::as_str)
+    }
+
+    /// Replace the header names in HEAD, the request line and the headers.
+    fn rewrite(&self, head: &mut [u8]) {
+        let mut lines = head.split_mut(|&b| b == b'\n').skip(1);
+        for line in &mut lines {
+            let Some(colon) = line.iter().position(|&b| b == b':') else {
+                continue;
+            };
+            let name = &mut line[..colon];
+            let Ok(lowercase) = std::str::from_utf8(name).map(str::to_ascii_lowercase) else {
+                continue;
+            };
+            if let Some(spelling) = self.0.get(&lowercase) {
+                name.copy_from_slice(spelling.as_bytes());
+            }
+        }
+    }
+}
+
+/// The stream which rewrites the names in the first request head written to it.
+struct CaseWriter<S> {
+    inner: S,
+    spellings: Arc<Spellings>,
+    head: Vec<u8>,
+    /// Whether the whole head is in `head`
+    complete: bool,
+    /// The bytes of `head` written to `inner`
+    written: usize,
+}
+
+impl<S> CaseWriter<S> {
+    fn new(inner: S, spellings: Arc<Spellings>) -> Self {
+        CaseWriter {
+            inner,
+            spellings,
+            head: Vec::new(),
+            complete: false,
+            written: 0,
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> CaseWriter<S> {
+    /// Write the rewritten head to the inner stream.
+    fn poll_head(&mut self, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        while self.complete && self.written < self.head.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.head[self.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.written += n;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CaseWriter<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CaseWriter<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.complete {
+            ready!(this.poll_head(cx))?;
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+        // The end of the head can be split across the writes.
+        let start = this.head.len().saturating_sub(3);
+        let len = this.head.len();
+        this.head.extend_from_slice(buf);
+        let Some(end) = this.head[start..]
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+        else {
+            return Poll::Ready(Ok(buf.len()));
+        };
+        let end = start + end + 4;
+        this.head.truncate(end);
+        let spellings = this.spellings.clone();
+        spellings.rewrite(&mut this.head);
+        this.complete = true;
+        Poll::Ready(Ok(end - len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_head(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_head(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// Send REQ on a new HTTP/1.1 connection with the header names of ARGS.
+pub async fn execute(args: &Args, mut req: reqwest::Request) -> anyhow::Result<reqwest::Response> {
+    let url = req.url().clone();
+    let host = url.host_str().context("URL has no host")?;
+    let port = url.port_or_known_default().context("URL has no port")?;
+    let host_header = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    };
+    let target = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let mut builder = http::Request::builder()
+        .method(req.method())
+        .uri(target)
+        .header(HOST, host_header);
+    for (key, value) in req.headers() {
+        builder = builder.header(key, value);
+    }
+    let body = req.body_mut().take().unwrap_or_else(|| Bytes::new().into());
+    let req = builder.body(body)?;
+
+    // The brackets of an IPv6 address are only in the URL.
+    let address = host.trim_start_matches('[').trim_end_matches(']');
+    let stream = TcpStream::connect((address, port))
+        .await
+        .with_context(|| format!("Unable to connect to {}:{}", host, port))?;
+    let spellings = Arc::new(Spellings::new(&args.header));
+    let res = match url.scheme() {
+        "https" => {
+            let config = tls::http1_config(args)?;
+            let server_name = ServerName::try_from(address.to_string())?;
+            let stream = tokio_rustls::TlsConnector::from(Arc::new(config))
+                .connect(server_name, stream)
+                .await?;
+            send(CaseWriter::new(stream, spellings), req).await?
+        }
+        _ => send(CaseWriter::new(stream, spellings), req).await?,
+    };
+    Ok(res.map(reqwest::Body::wrap).into())
+}
+
+async fn send<S>(
+    stream: S,
+    req: http::Request<reqwest::Body>,
+) -> anyhow::Result<http::Response<hyper::body::Incoming>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) =
+        hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            logging::message(Level::Warn, format_args!("Connection error: {}", e));
+        }
+    });
+    Ok(sender.send_request(req).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{CaseWriter, Spellings};
+
+    fn spellings() -> Spellings {
+        Spellings::new(&[
+            "X-API-Key: secret".to_string(),
+            "Content-Type : text/plain".to_string(),
+            "x-lower: 1".to_string(),
+        ])
+    }
+
+    #[test]
+    fn spelling() {
+        let spellings = spellings();
+        assert_eq!(spellings.get("x-api-key"), "X-API-Key");
+        assert_eq!(spellings.get("content-type"), "Content-Type");
+        assert_eq!(spellings.get("x-amz-date"), "x-amz-date");
+    }
+
+    #[test]
+    fn rewrite_head() {
+        let mut head = b"GET /x-api-key:1 HTTP/1.1\r\nhost: example.com\r\nx-api-key: x-api-key\r\ncontent-type: text/plain\r\n\r\n".to_vec();
+        spellings().rewrite(&mut head);
+        assert_eq!(
+            String::from_utf8(head).unwrap(),
+            "GET /x-api-key:1 HTTP/1.1\r\nhost: example.com\r\nX-API-Key: x-api-key\r\nContent-Type: text/plain\r\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn split_writes() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let mut writer = CaseWriter::new(client, Arc::new(spellings()));
+        for chunk in [
+            &b"POST / HTTP/1.1\r\nx-api"[..],
+            b"-key: 1\r\n\r",
+            b"\nx-api-key: body",
+        ] {
+            writer.write_all(chunk).await.unwrap();
+        }
+        writer.shutdown().await.unwrap();
+        let mut received = String::new();
+        server.read_to_string(&mut received).await.unwrap();
+        assert_eq!(
+            received,
+            "POST / HTTP/1.1\r\nX-API-Key: 1\r\n\r\nx-api-key: body"
+        );
+    }
+}
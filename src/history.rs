@@ -0,0 +1,201 @@
+//! History of the executed requests (`--record-history`, `--history`, `--rerun`)
+//!
+//! Entries are stored as JSON lines in the config directory. The request body
+//! and credential-bearing headers are never stored, and the credentials in the
+//! query of a presigned URL are masked.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{calc_sha256_hex_digest, config::config_dir, redact, AwsCurlParam};
+
+pub const DEFAULT_HISTORY_SIZE: usize = 100;
+
+/// Headers which are dropped before an entry is written.
+const SENSITIVE_HEADERS: [&str; 5] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "x-amz-security-token",
+    "x-api-key",
+];
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Entry {
+    pub timestamp: String,
+    pub url: String,
+    pub method: String,
+    pub headers: Vec<String>,
+    pub body_sha256: String,
+    pub service: Option<String>,
+    pub region: Option<String>,
+    pub status: Option<u16>,
+    pub elapsed_ms: u128,
+}
+
+impl Entry {
+    pub fn new(
+        param: &AwsCurlParam,
+        status: Option<u16>,
+        elapsed: Duration,
+    ) -> anyhow::Result<Self> {
+        let headers = param
+            .args
+            .header
+            .iter()
+            .filter(|header| !is_sensitive(header))
+            .cloned()
+            .collect();
+        Ok(Self {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            url: redact::url(param.url()?),
+            method: param.method().to_string(),
+            headers,
+            body_sha256: calc_sha256_hex_digest(&param.args.body().unwrap_or_default()),
+            service: param.args.service.clone(),
+            region: param.args.region.clone(),
+            status,
+            elapsed_ms: elapsed.as_millis(),
+        })
+    }
+
+    /// Whether the original request had a body, which has to be given again to rerun it.
+    pub fn has_body(&self) -> bool {
+        self.body_sha256 != calc_sha256_hex_digest("")
+    }
+}
+
+fn is_sensitive(header: &str) -> bool {
+    let name = header.split_once(':').map_or(header, |(name, _)| name);
+    let name = name.trim().to_ascii_lowercase();
+    SENSITIVE_HEADERS.contains(&name.as_str()) || name.contains("token")
+}
+
+fn history_path() -> anyhow::Result<PathBuf> {
+    let dir = config_dir().context("Unable to decide the config directory")?;
+    Ok(dir.join("history.jsonl"))
+}
+
+pub fn load() -> anyhow::Result<Vec<Entry>> {
+    load_from(&history_path()?)
+}
+
+fn load_from(path: &Path) -> anyhow::Result<Vec<Entry>> {
+    let raw = match fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e).with_context(|| format!("Unable to read {}", path.display())),
+    };
+    raw.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).context("Broken history entry"))
+        .collect()
+}
+
+/// Append an entry, keeping only the latest `size` entries.
+pub fn append(entry: Entry, size: usize) -> anyhow::Result<()> {
+    append_to(&history_path()?, entry, size)
+}
+
+fn append_to(path: &Path, entry: Entry, size: usize) -> anyhow::Result<()> {
+    let mut entries = load_from(path)?;
+    entries.push(entry);
+    let skip = entries.len().saturating_sub(size);
+
+    // Write to a temporary file and rename it so that the history is never half written.
+    let dir = path.parent().context("Invalid history path")?;
+    fs::create_dir_all(dir)?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    for entry in &entries[skip..] {
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    }
+    file.persist(path)?;
+    Ok(())
+}
+
+/// Print the entries with the numbers accepted by `--rerun`.
+pub fn print(entries: &[Entry]) {
+    for (i, entry) in entries.iter().enumerate() {
+        let status = entry
+            .status
+            .map_or("-".to_string(), |status| status.to_string());
+        println!(
+            "{:>4}  {}  {} {} -> {} ({} ms)",
+            i + 1,
+            entry.timestamp,
+            entry.method,
+            entry.url,
+            status,
+            entry.elapsed_ms
+        );
+    }
+}
+
+pub fn get(entries: Vec<Entry>, number: usize) -> anyhow::Result<Entry> {
+    match number
+        .checked_sub(1)
+        .and_then(|i| entries.into_iter().nth(i))
+    {
+        Some(entry) => Ok(entry),
+        None => bail!("No history entry: {}", number),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{append_to, is_sensitive, load_from, Entry};
+
+    fn entry(url: &str) -> Entry {
+        Entry {
+            timestamp: "2013-05-24T00:00:00Z".to_string(),
+            url: url.to_string(),
+            method: "GET".to_string(),
+            headers: vec![],
+            body_sha256: "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+                .to_string(),
+            service: None,
+            region: None,
+            status: Some(200),
+            elapsed_ms: 10,
+        }
+    }
+
+    #[test]
+    fn keep_latest_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        for i in 0..5 {
+            append_to(&path, entry(&format!("https://example.com/{}", i)), 3).unwrap();
+        }
+        let urls = load_from(&path)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.url)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/2",
+                "https://example.com/3",
+                "https://example.com/4"
+            ]
+        );
+    }
+
+    #[test]
+    fn sensitive_headers() {
+        assert!(is_sensitive("Authorization: AWS4-HMAC-SHA256 ..."));
+        assert!(is_sensitive("cookie: session=1"));
+        assert!(is_sensitive("X-Amz-Security-Token: abc"));
+        assert!(is_sensitive("x-my-auth-token: abc"));
+        assert!(!is_sensitive("content-type: application/json"));
+    }
+}
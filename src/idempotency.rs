@@ -0,0 +1,97 @@
+//! The client token of the idempotent APIs (`--idempotency-token`)
+//!
+//! The header is added to the request once before it is signed, so that all of
+//! its retries send the same token and the server does not run the operation
+//! twice. Each request of --next generates its own token.
+
+use anyhow::{bail, Context};
+use http::{HeaderName, HeaderValue};
+
+use crate::{logging, Args};
+
+/// The header of the token without --idempotency-header.
+pub const DEFAULT_HEADER: &str = "X-Amzn-Client-Token";
+/// The value of --idempotency-token which generates the token.
+pub const AUTO: &str = "auto";
+
+/// The token of VALUE, which is a new UUID for "auto".
+fn token(value: &str) -> String {
+    match value {
+        AUTO => uuid::Uuid::new_v4().to_string(),
+        value => value.to_string(),
+    }
+}
+
+/// Add the header of --idempotency-token to ARGS, unless -H gives it.
+pub fn install(args: &mut Args) -> anyhow::Result<()> {
+    let Some(value) = &args.idempotency_token else {
+        return Ok(());
+    };
+    let name = HeaderName::from_bytes(args.idempotency_header.as_bytes())
+        .with_context(|| format!("Invalid header name: {}", args.idempotency_header))?;
+    let given = args.header.iter().any(|header| {
+        header
+            .split_once(':')
+            .is_some_and(|(key, _)| key.trim().eq_ignore_ascii_case(name.as_str()))
+    });
+    if given {
+        logging::warning(format_args!(
+            "--idempotency-token is not added, since -H gives {}",
+            args.idempotency_header
+        ));
+        return Ok(());
+    }
+    let token = token(value);
+    if token.is_empty() || HeaderValue::from_str(&token).is_err() {
+        bail!(
+            "The idempotency token has to be printable characters: {:?}",
+            token
+        );
+    }
+    if args.verbose {
+        logging::note(format_args!("Idempotency token: {}", token));
+    }
+    args.header
+        .push(format!("{}: {}", args.idempotency_header, token));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::{install, token};
+    use crate::Args;
+
+    fn headers(options: &[&str]) -> anyhow::Result<Vec<String>> {
+        let mut args = Args::parse_from([&["awscurl", "https://example.com"], options].concat());
+        install(&mut args)?;
+        Ok(args.header)
+    }
+
+    #[test]
+    fn tokens() {
+        let generated = token("auto");
+        assert!(uuid::Uuid::parse_str(&generated).is_ok());
+        assert_ne!(token("auto"), generated);
+        assert_eq!(token("order-42"), "order-42");
+    }
+
+    #[test]
+    fn install_header() {
+        assert!(headers(&[]).unwrap().is_empty());
+        assert_eq!(
+            headers(&["--idempotency-token", "order-42"]).unwrap(),
+            ["X-Amzn-Client-Token: order-42"]
+        );
+        let added =
+            headers(&["--idempotency-header", "ClientToken", "--idempotency-token"]).unwrap();
+        assert!(added[0].starts_with("ClientToken: "));
+        assert_eq!(
+            headers(&["-H", "x-amzn-client-token: mine", "--idempotency-token"]).unwrap(),
+            ["x-amzn-client-token: mine"]
+        );
+        assert!(headers(&["--idempotency-header", "bad name", "--idempotency-token"]).is_err());
+        assert!(headers(&["--idempotency-token", "line\nbreak"]).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! The instance metadata service in the default chain (`--no-imds`, ...)
+//!
+//! The default chain of the SDK asks the instance metadata service of EC2 for
+//! the credentials and the region last, which takes seconds of the timeouts
+//! and the retries where it is unreachable, as on a laptop. --imds-timeout and
+//! --imds-retries tune the client of the service, and --no-imds (or
+//! AWS_EC2_METADATA_DISABLED=true) builds the chains without it, so that it is
+//! never contacted.
+
+use std::{env, time::Duration};
+
+use aws_config::{
+    ecs::EcsCredentialsProvider,
+    environment::{EnvironmentVariableCredentialsProvider, EnvironmentVariableRegionProvider},
+    imds::{self, credentials::ImdsCredentialsProvider, region::ImdsRegionProvider},
+    meta::{credentials::CredentialsProviderChain, region::RegionProviderChain},
+    profile::{ProfileFileCredentialsProvider, ProfileFileRegionProvider},
+    web_identity_token::WebIdentityTokenCredentialsProvider,
+    ConfigLoader,
+};
+
+use crate::Args;
+
+/// Whether the instance metadata service is disabled by --no-imds or
+/// AWS_EC2_METADATA_DISABLED.
+pub fn disabled(args: &Args) -> bool {
+    args.no_imds
+        || env::var("AWS_EC2_METADATA_DISABLED")
+            .is_ok_and(|value| value.eq_ignore_ascii_case("true"))
+}
+
+/// The client of the service with --imds-timeout and --imds-retries.
+pub fn client(args: &Args) -> imds::Client {
+    let mut builder = imds::Client::builder();
+    if let Some(timeout) = args.imds_timeout {
+        let timeout = Duration::from_secs_f64(timeout);
+        builder = builder.connect_timeout(timeout).read_timeout(timeout);
+    }
+    if let Some(retries) = args.imds_retries {
+        builder = builder.max_attempts(retries + 1);
+    }
+    builder.build()
+}
+
+/// Set the chains of the credentials and the region to LOADER unless the
+/// defaults of the SDK are used as they are.
+pub async fn configure(args: &Args, loader: ConfigLoader) -> ConfigLoader {
+    let disabled = disabled(args);
+    if !disabled && args.imds_timeout.is_none() && args.imds_retries.is_none() {
+        return loader;
+    }
+    let profile = args.profile.as_deref();
+
+    let mut profile_credentials = ProfileFileCredentialsProvider::builder();
+    let mut profile_region = ProfileFileRegionProvider::builder();
+    if let Some(profile) = profile {
+        profile_credentials = profile_credentials.profile_name(profile);
+        profile_region = profile_region.profile_name(profile);
+    }
+    // The order of the default chains.
+    let mut credentials = CredentialsProviderChain::first_try(
+        "Environment",
+        EnvironmentVariableCredentialsProvider::new(),
+    )
+    .or_else("Profile", profile_credentials.build())
+    .or_else(
+        "WebIdentityToken",
+        WebIdentityTokenCredentialsProvider::builder().build(),
+    )
+    .or_else("EcsContainer", EcsCredentialsProvider::builder().build());
+    let mut region = RegionProviderChain::first_try(EnvironmentVariableRegionProvider::new())
+        .or_else(profile_region.build());
+    if !disabled {
+        let client = client(args);
+        credentials = credentials.or_else(
+            "Ec2InstanceMetadata",
+            ImdsCredentialsProvider::builder()
+                .imds_client(client.clone())
+                .build(),
+        );
+        region = region.or_else(ImdsRegionProvider::builder().imds_client(client).build());
+    }
+    loader.credentials_provider(credentials).region(region)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::disabled;
+    use crate::Args;
+
+    #[test]
+    fn no_imds() {
+        assert!(disabled(&Args::parse_from([
+            "awscurl",
+            "https://example.com",
+            "--no-imds"
+        ])));
+    }
+
+    #[test]
+    fn conflicts() {
+        assert!(Args::try_parse_from([
+            "awscurl",
+            "https://example.com",
+            "--no-imds",
+            "--imds-timeout",
+            "1"
+        ])
+        .is_err());
+    }
+}
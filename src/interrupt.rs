@@ -0,0 +1,60 @@
+//! Ctrl-C handling
+//!
+//! The first Ctrl-C cancels the request in flight, so that the partial output is
+//! handled (`--keep-partial`) before exiting. The second one exits immediately.
+
+use std::{fmt, sync::LazyLock};
+
+use tokio_util::sync::CancellationToken;
+
+/// Exit code when the transfer is interrupted, which is same as a shell (128 + SIGINT).
+pub const EXIT_INTERRUPTED: u8 = 130;
+
+static INTERRUPTED: LazyLock<CancellationToken> = LazyLock::new(CancellationToken::new);
+
+/// The error of the interrupted transfer, which keeps the body received so far.
+#[derive(Debug)]
+pub struct Interrupted {
+    /// The number of the bytes transferred, which are not always kept in `partial`
+    pub bytes: u64,
+    pub partial: Vec<u8>,
+}
+
+impl Interrupted {
+    pub fn with_partial(partial: Vec<u8>) -> Self {
+        Self {
+            bytes: partial.len() as u64,
+            partial,
+        }
+    }
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Transfer interrupted after {} bytes", self.bytes)
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Start handling Ctrl-C instead of being killed by it.
+pub fn install() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        INTERRUPTED.cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(EXIT_INTERRUPTED.into());
+        }
+    });
+}
+
+/// Completes when Ctrl-C is pressed, or immediately if it already was.
+pub async fn interrupted() {
+    INTERRUPTED.cancelled().await
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.is_cancelled()
+}
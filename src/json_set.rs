@@ -0,0 +1,252 @@
+//! Setting the fields of the JSON request body (`--json-set`)
+//!
+//! The body given by `--data`, or "{}" without it, is parsed and each
+//! `PATH=VALUE` is assigned in the order they are given before the body is
+//! hashed and signed. PATH is a subset of jq: fields after ".", which are quoted
+//! for the other characters (Ex. ."a.b"), and "[N]" for an element. The missing
+//! objects and arrays on the way are created, and an array is padded with null
+//! up to N. VALUE is JSON, so a string is quoted (Ex. .Item.id="42").
+
+use std::fmt;
+
+use anyhow::{anyhow, bail, Context};
+use serde_json::{Map, Value};
+
+/// A step of the path of `--json-set`.
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Field(String),
+    Index(usize),
+}
+
+/// `--json-set` value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Assignment {
+    path: Vec<Step>,
+    value: Value,
+}
+
+/// The path of STEPS in the jq syntax, which is "." for no steps.
+struct Path<'a>(&'a [Step]);
+
+impl fmt::Display for Path<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, ".");
+        }
+        for step in self.0 {
+            match step {
+                Step::Field(name) if is_identifier(name) => write!(f, ".{}", name)?,
+                Step::Field(name) => write!(f, ".{}", Value::from(name.as_str()))?,
+                Step::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Parse RAW of `--json-set`.
+pub fn parse_assignment(raw: &str) -> Result<Assignment, String> {
+    let invalid = |reason: &str| format!("{} in {}", reason, raw);
+    let (path, rest) = parse_path(raw).map_err(|reason| invalid(&reason))?;
+    let value = rest
+        .strip_prefix('=')
+        .ok_or_else(|| invalid("expected PATH=VALUE (Ex. .Item.id=\"42\")"))?;
+    let value = serde_json::from_str(value).map_err(|e| {
+        invalid(&format!(
+            "invalid JSON value ({}); quote a string like .name=\"value\"",
+            e
+        ))
+    })?;
+    Ok(Assignment { path, value })
+}
+
+/// The steps at the start of RAW and the rest of it.
+fn parse_path(raw: &str) -> Result<(Vec<Step>, &str), String> {
+    if !raw.starts_with(['.', '[']) {
+        return Err("the path must start with \".\"".to_string());
+    }
+    let mut steps = Vec::new();
+    let mut rest = raw;
+    // "." alone is the whole body.
+    if let Some(after) = rest
+        .strip_prefix('.')
+        .filter(|after| after.starts_with('='))
+    {
+        return Ok((steps, after));
+    }
+    loop {
+        if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or("missing \"]\"")?;
+            let index = after[..end]
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid index [{}]", &after[..end]))?;
+            steps.push(Step::Index(index));
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix(".\"") {
+            let end = after.find('"').ok_or("missing the closing quote")?;
+            steps.push(Step::Field(after[..end].to_string()));
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[', '=']).unwrap_or(after.len());
+            if end == 0 {
+                return Err("empty field name".to_string());
+            }
+            steps.push(Step::Field(after[..end].to_string()));
+            rest = &after[end..];
+        } else {
+            return Ok((steps, rest));
+        }
+    }
+}
+
+/// BODY, or "{}" without it, with ASSIGNMENTS applied.
+pub fn apply(body: Option<&str>, assignments: &[Assignment]) -> anyhow::Result<String> {
+    let mut root = match body {
+        Some(body) => {
+            serde_json::from_str(body).context("--json-set requires the request body to be JSON")?
+        }
+        None => Value::Object(Map::new()),
+    };
+    for assignment in assignments {
+        set(&mut root, &assignment.path, assignment.value.clone())
+            .map_err(|e| anyhow!("Unable to set {}: {}", Path(&assignment.path), e))?;
+    }
+    Ok(root.to_string())
+}
+
+fn set(root: &mut Value, steps: &[Step], value: Value) -> anyhow::Result<()> {
+    let mut current = root;
+    for (i, step) in steps.iter().enumerate() {
+        if current.is_null() {
+            *current = match step {
+                Step::Field(_) => Value::Object(Map::new()),
+                Step::Index(_) => Value::Array(Vec::new()),
+            };
+        }
+        current = match (step, current) {
+            (Step::Field(name), Value::Object(map)) => {
+                map.entry(name.as_str()).or_insert(Value::Null)
+            }
+            (Step::Index(index), Value::Array(array)) => {
+                if array.len() <= *index {
+                    array.resize(index + 1, Value::Null);
+                }
+                &mut array[*index]
+            }
+            (step, current) => {
+                let expected = match step {
+                    Step::Field(_) => "an object",
+                    Step::Index(_) => "an array",
+                };
+                bail!(
+                    "{} is {}, not {}",
+                    Path(&steps[..i]),
+                    kind(current),
+                    expected
+                );
+            }
+        };
+    }
+    *current = value;
+    Ok(())
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{apply, parse_assignment, Assignment};
+
+    fn assignments(raws: &[&str]) -> Vec<Assignment> {
+        raws.iter()
+            .map(|raw| parse_assignment(raw).unwrap())
+            .collect()
+    }
+
+    fn applied(body: Option<&str>, raws: &[&str]) -> serde_json::Value {
+        serde_json::from_str(&apply(body, &assignments(raws)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn set_nested_fields() {
+        let body = r#"{"TableName":"music","Item":{"id":"1","tags":["a","b"]},"Limit":1}"#;
+        assert_eq!(
+            applied(
+                Some(body),
+                &[".Item.id=\"42\"", ".Limit=10", ".Item.tags[1]=\"c\""]
+            ),
+            json!({"TableName": "music", "Item": {"id": "42", "tags": ["a", "c"]}, "Limit": 10})
+        );
+        assert_eq!(
+            applied(Some(r#"{"a":{"b":1}}"#), &[".a={\"c\":[true]}"]),
+            json!({"a": {"c": [true]}})
+        );
+        assert_eq!(applied(Some("[1]"), &[".=null"]), json!(null));
+    }
+
+    #[test]
+    fn create_missing() {
+        assert_eq!(
+            applied(None, &[".Key.pk.S=\"user#1\"", ".Items[2].name=\"x\""]),
+            json!({"Key": {"pk": {"S": "user#1"}}, "Items": [null, null, {"name": "x"}]})
+        );
+        assert_eq!(
+            applied(Some("{\"a\":null}"), &[".a[0][1]=1"]),
+            json!({"a": [[null, 1]]})
+        );
+        assert_eq!(applied(Some("[]"), &["[0].\"a.b\"=1"]), json!([{"a.b": 1}]));
+    }
+
+    #[test]
+    fn type_errors_name_the_path() {
+        let error =
+            |body, raw| format!("{:#}", apply(Some(body), &assignments(&[raw])).unwrap_err());
+        assert_eq!(
+            error(r#"{"Item":{"id":"1"}}"#, ".Item.id.S=\"42\""),
+            "Unable to set .Item.id.S: .Item.id is a string, not an object"
+        );
+        assert_eq!(
+            error(r#"{"Items":{}}"#, ".Items[0]=1"),
+            "Unable to set .Items[0]: .Items is an object, not an array"
+        );
+        assert_eq!(
+            error("[1]", ".\"a b\"=1"),
+            "Unable to set .\"a b\": . is an array, not an object"
+        );
+        assert!(apply(Some("a=1"), &assignments(&[".a=1"])).is_err());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            parse_assignment(".name=value").unwrap_err(),
+            "invalid JSON value (expected value at line 1 column 1); quote a string like .name=\"value\" in .name=value"
+        );
+        assert!(parse_assignment("name=1").is_err());
+        assert!(parse_assignment(".a").is_err());
+        assert!(parse_assignment(".a..b=1").is_err());
+        assert!(parse_assignment(".a[x]=1").is_err());
+        assert!(parse_assignment(".a[0=1").is_err());
+        assert!(parse_assignment(".\"a=1").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! Invoking a Lambda function (`--lambda-invoke`)
+//!
+//! The function is given by its name or ARN, which is invoked with the Invoke
+//! API, or by its function URL, to which the payload is simply POSTed. Both are
+//! signed for the "lambda" service. A function error is reported in the
+//! X-Amz-Function-Error header of a 200 response, which fails the request here.
+
+use std::fmt;
+
+use anyhow::{bail, Context};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use clap::ValueEnum;
+use http::HeaderMap;
+
+use crate::{
+    arn,
+    logging::{self, Level},
+    Args,
+};
+
+pub const SERVICE: &str = "lambda";
+const FUNCTION_ERROR_HEADER: &str = "x-amz-function-error";
+const LOG_RESULT_HEADER: &str = "x-amz-log-result";
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum InvocationType {
+    /// Wait for the response of the function
+    #[value(name = "RequestResponse")]
+    RequestResponse,
+    /// Queue the event and return 202 at once
+    #[value(name = "Event")]
+    Event,
+    /// Only check the parameters and the permission
+    #[value(name = "DryRun")]
+    DryRun,
+}
+
+impl fmt::Display for InvocationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvocationType::RequestResponse => write!(f, "RequestResponse"),
+            InvocationType::Event => write!(f, "Event"),
+            InvocationType::DryRun => write!(f, "DryRun"),
+        }
+    }
+}
+
+/// Set the URL, the service, the method and the headers of ARGS to invoke the
+/// function of --lambda-invoke. The region of the config, CONFIG_REGION, is used
+/// for the Invoke API of a function given by its name.
+pub fn install(args: &mut Args, config_region: Option<&str>) -> anyhow::Result<()> {
+    let target = args
+        .lambda_invoke
+        .clone()
+        .context("--lambda-invoke is not given")?;
+    if target.starts_with("https://") {
+        if args.invocation_type.is_some() || args.lambda_logs {
+            bail!("--invocation-type and --lambda-logs apply to the Invoke API, and not to the function URL. Give the function name or ARN instead");
+        }
+        if let Some(region) = function_url_region(&target) {
+            args.region.get_or_insert_with(|| region.to_string());
+        }
+        args.url = Some(target);
+    } else if arn::is_arn(&target) {
+        if target.split(':').nth(2) != Some(SERVICE) {
+            bail!(
+                "--lambda-invoke takes the ARN of a Lambda function: {}",
+                target
+            );
+        }
+        args.url = Some(target);
+        arn::expand(args)?;
+    } else {
+        let region = args
+            .region
+            .as_deref()
+            .or(config_region)
+            .context("The region of the Invoke API is unknown. Add --region")?;
+        args.url = Some(invoke_url(
+            &format!("https://lambda.{}.amazonaws.com", region),
+            &target,
+        ));
+    }
+    args.service.get_or_insert_with(|| SERVICE.to_string());
+    args.method.get_or_insert_with(|| "POST".to_string());
+    if let Some(invocation_type) = args.invocation_type {
+        args.header
+            .push(format!("X-Amz-Invocation-Type: {}", invocation_type));
+    }
+    if args.lambda_logs {
+        args.header.push("X-Amz-Log-Type: Tail".to_string());
+    }
+    Ok(())
+}
+
+/// The URL of the Invoke API of FUNCTION, which can have a version or an alias
+/// after ":", at ENDPOINT (Ex. https://lambda.us-east-1.amazonaws.com).
+pub fn invoke_url(endpoint: &str, function: &str) -> String {
+    match function.split_once(':') {
+        Some((name, qualifier)) => format!(
+            "{}/2015-03-31/functions/{}/invocations?Qualifier={}",
+            endpoint, name, qualifier
+        ),
+        None => format!("{}/2015-03-31/functions/{}/invocations", endpoint, function),
+    }
+}
+
+/// The region in the host of a function URL (Ex. ID.lambda-url.us-east-1.on.aws).
+fn function_url_region(url: &str) -> Option<&str> {
+    let host = url.strip_prefix("https://")?.split(['/', '?']).next()?;
+    let (_, rest) = host.split_once(".lambda-url.")?;
+    rest.strip_suffix(".on.aws")
+}
+
+/// Print the tail of the log in HEADERS if LOGS, and the function error if
+/// any. Returns whether the function failed.
+pub fn report(headers: &HeaderMap, logs: bool) -> bool {
+    if logs {
+        let log = headers
+            .get(LOG_RESULT_HEADER)
+            .and_then(|value| BASE64_STANDARD.decode(value.as_bytes()).ok());
+        if let Some(log) = log {
+            let log = String::from_utf8_lossy(&log);
+            logging::message(Level::Info, log.trim_end());
+        }
+    }
+    let Some(error) = headers.get(FUNCTION_ERROR_HEADER) else {
+        return false;
+    };
+    logging::message(
+        Level::Error,
+        format_args!("Function error: {}", error.to_str().unwrap_or("-")),
+    );
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::{function_url_region, install};
+    use crate::Args;
+
+    fn installed(options: &[&str], config_region: Option<&str>) -> anyhow::Result<Args> {
+        let mut args = Args::parse_from(["awscurl"].iter().chain(options));
+        install(&mut args, config_region)?;
+        Ok(args)
+    }
+
+    #[test]
+    fn invoke_api() {
+        let args = installed(
+            &[
+                "--lambda-invoke",
+                "my-fn:live",
+                "--invocation-type",
+                "Event",
+                "--lambda-logs",
+            ],
+            Some("us-east-1"),
+        )
+        .unwrap();
+        assert_eq!(
+            args.url.as_deref(),
+            Some("https://lambda.us-east-1.amazonaws.com/2015-03-31/functions/my-fn/invocations?Qualifier=live")
+        );
+        assert_eq!(args.service.as_deref(), Some("lambda"));
+        assert_eq!(args.method.as_deref(), Some("POST"));
+        assert_eq!(
+            args.header,
+            ["X-Amz-Invocation-Type: Event", "X-Amz-Log-Type: Tail"]
+        );
+
+        let args = installed(
+            &[
+                "--lambda-invoke",
+                "arn:aws:lambda:eu-west-1:123456789012:function:my-fn",
+            ],
+            Some("us-east-1"),
+        )
+        .unwrap();
+        assert_eq!(
+            args.url.as_deref(),
+            Some("https://lambda.eu-west-1.amazonaws.com/2015-03-31/functions/my-fn/invocations")
+        );
+        assert_eq!(args.region.as_deref(), Some("eu-west-1"));
+
+        assert!(installed(&["--lambda-invoke", "my-fn"], None).is_err());
+        assert!(installed(
+            &[
+                "--lambda-invoke",
+                "arn:aws:sns:us-east-1:123456789012:topic"
+            ],
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn function_url() {
+        let url = "https://abcdefg.lambda-url.ap-northeast-1.on.aws/path?a=1";
+        let args = installed(&["--lambda-invoke", url], None).unwrap();
+        assert_eq!(args.url.as_deref(), Some(url));
+        assert_eq!(args.region.as_deref(), Some("ap-northeast-1"));
+        assert!(args.header.is_empty());
+        assert_eq!(function_url_region("https://example.com/"), None);
+
+        assert!(installed(&["--lambda-invoke", url, "--lambda-logs"], None).is_err());
+    }
+}
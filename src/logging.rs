@@ -0,0 +1,172 @@
+//! The diagnostics on stderr as text or JSON lines (`--log-format`)
+//!
+//! The text format is the one printed by the `eprintln!`s of the callers, and is
+//! not changed by this module. With `--log-format json`, each event is printed
+//! as one JSON object with the timestamp, the level and the event name instead.
+//! The headers are redacted by the callers in both formats.
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use chrono::{SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    /// The lines prefixed with ">", "<" and "*"
+    #[default]
+    Text,
+    /// One JSON object for each event
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Level {
+    /// The traces of --verbose
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Debug => write!(f, "debug"),
+            Level::Info => write!(f, "info"),
+            Level::Warn => write!(f, "warn"),
+            Level::Error => write!(f, "error"),
+        }
+    }
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Select the format for the rest of the process.
+pub fn init(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+/// Whether the events are printed as JSON, where the callers call `event`
+/// instead of printing the text.
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Print the JSON object of EVENT with FIELDS, which is an object.
+pub fn event(level: Level, event: &str, fields: Value) {
+    eprintln!(
+        "{}",
+        object(
+            &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            level,
+            event,
+            fields
+        )
+    );
+}
+
+fn object(timestamp: &str, level: Level, event: &str, fields: Value) -> Value {
+    let mut object = Map::new();
+    object.insert("timestamp".to_string(), json!(timestamp));
+    object.insert("level".to_string(), json!(level.to_string()));
+    object.insert("event".to_string(), json!(event));
+    if let Value::Object(fields) = fields {
+        for (key, value) in fields {
+            object.entry(key).or_insert(value);
+        }
+    }
+    Value::Object(object)
+}
+
+/// The headers as an object, where the values of a repeated header are an array.
+pub fn headers<'a>(pairs: impl IntoIterator<Item = (&'a str, impl AsRef<str>)>) -> Value {
+    let mut headers = Map::new();
+    for (name, value) in pairs {
+        let value = value.as_ref();
+        match headers.get_mut(name) {
+            Some(Value::Array(values)) => values.push(json!(value)),
+            Some(first) => *first = json!([first.take(), value]),
+            None => {
+                headers.insert(name.to_string(), json!(value));
+            }
+        }
+    }
+    Value::Object(headers)
+}
+
+/// A note of --verbose, which is "* MESSAGE" in text.
+pub fn note(message: impl fmt::Display) {
+    if is_json() {
+        event(
+            Level::Debug,
+            "note",
+            json!({ "message": message.to_string() }),
+        );
+    } else {
+        eprintln!("* {}", message);
+    }
+}
+
+/// A warning, which is "Warning: MESSAGE" in text.
+pub fn warning(message: impl fmt::Display) {
+    if is_json() {
+        event(
+            Level::Warn,
+            "warning",
+            json!({ "message": message.to_string() }),
+        );
+    } else {
+        eprintln!("Warning: {}", message);
+    }
+}
+
+/// Any other line, which is printed as it is in text.
+pub fn message(level: Level, message: impl fmt::Display) {
+    if is_json() {
+        event(level, "message", json!({ "message": message.to_string() }));
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{headers, object, Level};
+
+    #[test]
+    fn event_object() {
+        assert_eq!(
+            object(
+                "2013-05-24T00:00:00.000Z",
+                Level::Warn,
+                "retry",
+                json!({"attempt": 1, "event": "ignored"}),
+            ),
+            json!({
+                "timestamp": "2013-05-24T00:00:00.000Z",
+                "level": "warn",
+                "event": "retry",
+                "attempt": 1,
+            })
+        );
+    }
+
+    #[test]
+    fn repeated_headers() {
+        assert_eq!(
+            headers([
+                ("set-cookie", "a".to_string()),
+                ("etag", "\"1\"".to_string()),
+                ("set-cookie", "b".to_string()),
+                ("set-cookie", "c".to_string()),
+            ]),
+            json!({"set-cookie": ["a", "b", "c"], "etag": "\"1\""})
+        );
+    }
+}
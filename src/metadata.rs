@@ -0,0 +1,188 @@
+//! The user metadata of S3 objects (`--metadata`, `--metadata-file`)
+//!
+//! Each KEY=VALUE becomes the signed header "x-amz-meta-KEY: VALUE". S3 keeps
+//! only US-ASCII in the headers, so the other characters are rejected instead
+//! of being stored broken. The entries of --metadata-file come first, and
+//! --metadata replaces the ones with the same key.
+
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context};
+use serde_json::Value;
+
+use crate::{logging, Args};
+
+pub const HEADER_PREFIX: &str = "x-amz-meta-";
+/// The limit of S3 on the total size of the keys and values.
+const MAX_SIZE: usize = 2048;
+
+/// Parse RAW of `--metadata`.
+pub fn parse_entry(raw: &str) -> Result<(String, String), String> {
+    let (key, value) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE (Ex. author=alice): {}", raw))?;
+    check(key, value)?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Check that KEY and VALUE can be sent in a header and kept by S3.
+fn check(key: &str, value: &str) -> Result<(), String> {
+    if key.is_empty() {
+        return Err("empty metadata key".to_string());
+    }
+    if let Some(c) = key
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+    {
+        return Err(format!(
+            "invalid character {:?} in the metadata key {} (only letters, digits, \"-\", \"_\" and \".\")",
+            c, key
+        ));
+    }
+    if let Some(c) = value.chars().find(|c| !(' '..='~').contains(c)) {
+        return Err(format!(
+            "invalid character {:?} in the metadata value of {} (only printable US-ASCII)",
+            c, key
+        ));
+    }
+    Ok(())
+}
+
+/// The entries of the flat JSON object in PATH. Numbers and booleans are
+/// taken as their text.
+fn load(path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let raw =
+        fs::read_to_string(path).with_context(|| format!("Unable to read {}", path.display()))?;
+    let Ok(Value::Object(map)) = serde_json::from_str(&raw) else {
+        bail!("{} must be a JSON object", path.display());
+    };
+    let mut entries = Vec::new();
+    for (key, value) in map {
+        let value = match value {
+            Value::String(value) => value,
+            Value::Number(_) | Value::Bool(_) => value.to_string(),
+            _ => bail!(
+                "The value of {} in {} must be a string, not nested",
+                key,
+                path.display()
+            ),
+        };
+        check(&key, &value).map_err(|e| anyhow::anyhow!("{} in {}", e, path.display()))?;
+        entries.push((key, value));
+    }
+    Ok(entries)
+}
+
+/// Add the headers of --metadata-file and --metadata to ARGS, and warn when
+/// they are larger than S3 accepts.
+pub fn install(args: &mut Args) -> anyhow::Result<()> {
+    let mut entries = match &args.metadata_file {
+        Some(path) => load(path)?,
+        None => Vec::new(),
+    };
+    for (key, value) in &args.metadata {
+        entries.retain(|(other, _)| !other.eq_ignore_ascii_case(key));
+        entries.push((key.clone(), value.clone()));
+    }
+    let size = size(&entries);
+    if size > MAX_SIZE {
+        logging::warning(format_args!(
+            "The metadata is {} bytes, which is larger than {} bytes S3 accepts",
+            size, MAX_SIZE
+        ));
+    }
+    for (key, value) in entries {
+        args.header
+            .push(format!("{}{}: {}", HEADER_PREFIX, key, value));
+    }
+    Ok(())
+}
+
+/// The size of ENTRIES which S3 counts: the sum of the keys and the values.
+fn size(entries: &[(String, String)]) -> usize {
+    entries
+        .iter()
+        .map(|(key, value)| key.len() + value.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use clap::Parser;
+
+    use super::{install, parse_entry, size};
+    use crate::Args;
+
+    #[test]
+    fn parse_entries() {
+        assert_eq!(
+            parse_entry("author=alice=bob").unwrap(),
+            ("author".to_string(), "alice=bob".to_string())
+        );
+        assert_eq!(
+            parse_entry("empty=").unwrap(),
+            ("empty".to_string(), String::new())
+        );
+        assert!(parse_entry("author").is_err());
+        assert!(parse_entry("=alice").is_err());
+        assert_eq!(
+            parse_entry("a b=c").unwrap_err(),
+            "invalid character ' ' in the metadata key a b (only letters, digits, \"-\", \"_\" and \".\")"
+        );
+        assert_eq!(
+            parse_entry("city=Zürich").unwrap_err(),
+            "invalid character 'ü' in the metadata value of city (only printable US-ASCII)"
+        );
+        assert!(parse_entry("a=b\nc").is_err());
+    }
+
+    #[test]
+    fn file_and_options() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            r#"{{"author": "alice", "revision": 3, "draft": true}}"#
+        )
+        .unwrap();
+        let mut args = Args::parse_from([
+            "awscurl",
+            "https://example.com",
+            "--metadata-file",
+            file.path().to_str().unwrap(),
+            "--metadata",
+            "Author=bob",
+        ]);
+        install(&mut args).unwrap();
+        assert_eq!(
+            args.header,
+            [
+                "x-amz-meta-draft: true",
+                "x-amz-meta-revision: 3",
+                "x-amz-meta-Author: bob"
+            ]
+        );
+
+        for invalid in [r#"{"a": {"b": "c"}}"#, r#"["a"]"#, r#"{"a": "é"}"#] {
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            write!(file, "{}", invalid).unwrap();
+            let mut args = Args::parse_from([
+                "awscurl",
+                "https://example.com",
+                "--metadata-file",
+                file.path().to_str().unwrap(),
+            ]);
+            assert!(install(&mut args).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn metadata_size() {
+        let entries = [
+            ("ab".to_string(), "cde".to_string()),
+            ("f".to_string(), String::new()),
+        ];
+        assert_eq!(size(&entries), 6);
+    }
+}
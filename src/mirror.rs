@@ -0,0 +1,315 @@
+//! Sending the request to a shadow endpoint as well (`--mirror`)
+//!
+//! The request is signed again for the host of the mirror, with the service and
+//! the region inferred from it unless --mirror-service and --mirror-region are
+//! given, and sent along with the primary one. The primary response is printed
+//! and decides the exit code as usual, while the differences of the mirror are
+//! reported to stderr or the file of --mirror-report. Any failure of the mirror
+//! is a warning.
+//!
+//! The primary body is streamed to the output as it is received, and a copy of
+//! up to COMPARED_SIZE bytes is kept to compare. The longer body is not
+//! compared, only the status and the headers are.
+
+use std::{
+    path::Path,
+    pin::Pin,
+    process::ExitCode,
+    sync::{Arc, Mutex},
+    task::{Context as TaskContext, Poll},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use bytes::Bytes;
+use hyper::body::{Body, Frame};
+use reqwest::{ResponseBuilderExt, Url};
+
+use crate::{
+    cache, diff, logging, output_response, print_request_verbose, region, retry, service,
+    upload::Upload, Args, AwsCurlParam,
+};
+
+/// Parse RAW of --mirror, which is the URL of the scheme, the host and the
+/// optional path prefix.
+pub fn parse(raw: &str) -> Result<String, String> {
+    let url = Url::parse(raw).map_err(|e| format!("{}: {}", e, raw))?;
+    if !url.has_host() {
+        return Err(format!("expected a URL with the host: {}", raw));
+    }
+    Ok(raw.to_string())
+}
+
+/// The URL of the mirror for PRIMARY, which has the path and the query of
+/// PRIMARY under BASE.
+fn url(primary: &str, base: &str) -> anyhow::Result<Url> {
+    let primary = Url::parse(primary)?;
+    let mut url = Url::parse(base)?;
+    let prefix = url.path().trim_end_matches('/').to_string();
+    url.set_path(&format!("{}{}", prefix, primary.path()));
+    url.set_query(primary.query());
+    Ok(url)
+}
+
+/// The request to the mirror signed with its own parameters.
+pub struct Mirror {
+    param: AwsCurlParam,
+    req: reqwest::Request,
+}
+
+/// Sign the request of PARAM for the mirror, or warn why it can not be.
+pub async fn prepare(param: &AwsCurlParam) -> Option<Mirror> {
+    match try_prepare(param).await {
+        Ok(mirror) => mirror,
+        Err(e) => {
+            logging::warning(format_args!("The mirror is not sent: {:#}", e));
+            None
+        }
+    }
+}
+
+async fn try_prepare(param: &AwsCurlParam) -> anyhow::Result<Option<Mirror>> {
+    let Some(base) = &param.args.mirror else {
+        return Ok(None);
+    };
+    let url = url(param.url()?, base)?.to_string();
+    let mut args = param.args.clone();
+    args.region = args
+        .mirror_region
+        .clone()
+        .or_else(|| region::find_in_host(&url))
+        .or(args.region);
+    args.service = args
+        .mirror_service
+        .clone()
+        .or_else(|| service::infer(&url).map(|(service, _)| service.to_string()))
+        .or(args.service);
+    args.url = Some(url);
+    args.sign_for = None;
+    args.mirror = None;
+    let mut mirror = AwsCurlParam::new(args, param.config.clone());
+    // The streamed body is read up front with --mirror, so both get the bytes.
+    if let Some(Upload::Bytes(bytes)) = &param.upload {
+        mirror.upload = Some(Upload::Bytes(bytes.clone()));
+    }
+    let mut req = mirror.sendable(mirror.build_request().await?)?;
+    mirror.attach_upload(&mut req);
+    if param.args.verbose {
+        logging::note(format_args!("Mirror {}", req.url()));
+        print_request_verbose(&req, &mirror.args);
+    }
+    Ok(Some(Mirror { param: mirror, req }))
+}
+
+impl Mirror {
+    /// The URL of the request to the mirror.
+    pub fn url(&self) -> &Url {
+        self.req.url()
+    }
+
+    /// Send the request to the mirror, and read its whole response.
+    pub async fn fetch(self, client: &reqwest::Client) -> (Url, anyhow::Result<diff::Response>) {
+        let url = self.req.url().clone();
+        let response = match retry::send(&self.param, client, self.req).await {
+            Ok(res) => diff::read(res, &self.param.args).await,
+            Err(e) => Err(e),
+        };
+        (url, response)
+    }
+}
+
+/// The size of the primary body which is kept to compare.
+const COMPARED_SIZE: usize = 8 * 1024 * 1024;
+
+/// The copy of the primary body, which is dropped when it exceeds COMPARED_SIZE.
+#[derive(Default)]
+struct Copied {
+    body: Vec<u8>,
+    exceeded: bool,
+}
+
+/// The body which copies each chunk into COPIED while it is read.
+struct Tee {
+    inner: reqwest::Body,
+    copied: Arc<Mutex<Copied>>,
+}
+
+impl Body for Tee {
+    type Data = Bytes;
+    type Error = reqwest::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, reqwest::Error>>> {
+        let frame = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &frame {
+            if let Some(data) = frame.data_ref() {
+                let mut copied = self.copied.lock().unwrap();
+                if copied.body.len() + data.len() > COMPARED_SIZE {
+                    *copied = Copied {
+                        body: Vec::new(),
+                        exceeded: true,
+                    };
+                } else if !copied.exceeded {
+                    copied.body.extend_from_slice(data);
+                }
+            }
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+/// RES whose body is copied to COPIED while it is printed as usual.
+fn tee(res: reqwest::Response, copied: &Arc<Mutex<Copied>>) -> anyhow::Result<reqwest::Response> {
+    let url = res.url().clone();
+    let (parts, inner) = http::Response::<reqwest::Body>::from(res).into_parts();
+    let mut builder = http::Response::builder();
+    if let Some(extensions) = builder.extensions_mut() {
+        // The address of the connection, which --verbose prints.
+        *extensions = parts.extensions;
+    }
+    if let Some(headers) = builder.headers_mut() {
+        *headers = parts.headers;
+    }
+    let body = reqwest::Body::wrap(Tee {
+        inner,
+        copied: copied.clone(),
+    });
+    let res = builder
+        .status(parts.status)
+        .version(parts.version)
+        .url(url)
+        .body(body)?;
+    Ok(res.into())
+}
+
+/// Print the primary response RES, and report the differences of MIRRORED.
+pub async fn output(
+    param: &AwsCurlParam,
+    res: reqwest::Response,
+    mirrored: (Url, anyhow::Result<diff::Response>),
+    start: Instant,
+    headers_elapsed: Duration,
+    down: &mut u64,
+) -> anyhow::Result<ExitCode> {
+    let primary_url = res.url().to_string();
+    let status = res.status();
+    let headers = res.headers().clone();
+    let copied = Arc::default();
+    // The wrapped body has no Content-Length, by which output_response fails the
+    // body over --max-response-size before reading it.
+    let too_large = res
+        .content_length()
+        .zip(param.args.max_response_size)
+        .is_some_and(|(length, limit)| length > limit);
+    let res = match too_large {
+        true => res,
+        false => tee(res, &copied)?,
+    };
+    let ret = output_response(param, res, start, headers_elapsed, down).await?;
+    let Copied { body, exceeded } = std::mem::take(&mut *copied.lock().unwrap());
+    let primary = diff::Response {
+        status,
+        headers,
+        body,
+    };
+    let (mirror_url, mirrored) = mirrored;
+    match mirrored {
+        Ok(mut mirrored) => {
+            if exceeded {
+                logging::warning(format_args!(
+                    "The body is longer than {} bytes, so only the status and the headers of the mirror are compared",
+                    COMPARED_SIZE
+                ));
+                mirrored.body.clear();
+            }
+            let differences =
+                diff::differences(&primary_url, &primary, mirror_url.as_str(), &mirrored, &[]);
+            if let Err(e) = report(&param.args, differences.as_deref()) {
+                logging::warning(format_args!("{:#}", e));
+            }
+        }
+        Err(e) => logging::warning(format_args!("The mirror {} failed: {:#}", mirror_url, e)),
+    }
+    Ok(ret)
+}
+
+/// Write DIFFERENCES to the file of --mirror-report, which is empty without
+/// them, or to stderr.
+fn report(args: &Args, differences: Option<&str>) -> anyhow::Result<()> {
+    match (&args.mirror_report, differences) {
+        (Some(path), differences) => write(path, differences.unwrap_or_default()),
+        (None, Some(differences)) => {
+            logging::warning("The mirror responded differently");
+            eprint!("{}", differences);
+            Ok(())
+        }
+        (None, None) => {
+            if args.verbose {
+                logging::note("The mirror responded the same");
+            }
+            Ok(())
+        }
+    }
+}
+
+fn write(path: &Path, report: &str) -> anyhow::Result<()> {
+    cache::write_atomically(path, report.as_bytes())
+        .with_context(|| format!("Unable to write the mirror report {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{parse, tee, url, Copied, COMPARED_SIZE};
+
+    async fn copy(body: Vec<u8>) -> Copied {
+        let res = http::Response::builder().body(body.clone()).unwrap();
+        let copied = Arc::new(Mutex::new(Copied::default()));
+        let res = tee(res.into(), &copied).unwrap();
+        assert_eq!(res.bytes().await.unwrap(), body);
+        let copied = std::mem::take(&mut *copied.lock().unwrap());
+        copied
+    }
+
+    #[tokio::test]
+    async fn copied_bodies() {
+        let copied = copy(b"{\"id\":42}".to_vec()).await;
+        assert_eq!(copied.body, b"{\"id\":42}");
+        assert!(!copied.exceeded);
+
+        let copied = copy(vec![b'a'; COMPARED_SIZE + 1]).await;
+        assert!(copied.body.is_empty());
+        assert!(copied.exceeded);
+    }
+
+    #[test]
+    fn mirror_urls() {
+        assert_eq!(
+            url(
+                "https://old.example.com/users/42?b=2&a=1",
+                "https://new.example.com"
+            )
+            .unwrap()
+            .as_str(),
+            "https://new.example.com/users/42?b=2&a=1"
+        );
+        assert_eq!(
+            url(
+                "https://abc.execute-api.us-east-1.amazonaws.com/prod/users",
+                "http://127.0.0.1:8080/v2/"
+            )
+            .unwrap()
+            .as_str(),
+            "http://127.0.0.1:8080/v2/prod/users"
+        );
+        assert!(parse("https://new.example.com").is_ok());
+        assert!(parse("new.example.com").is_err());
+    }
+}
@@ -0,0 +1,212 @@
+//! Requests to an Amazon OpenSearch domain or collection (`--opensearch`)
+//!
+//! The service is "es" for a domain and "aoss" for a serverless collection,
+//! which is told by the host of the URL. The body is JSON, or NDJSON given by
+//! `--bulk`, which is checked before it is sent because a bulk body without the
+//! last newline is a common cause of 400. The error of a failed request is
+//! printed with its root cause.
+
+use anyhow::{bail, Context};
+use serde_json::Value;
+
+use crate::{
+    body_size, data,
+    logging::{self, Level},
+    Args,
+};
+
+const SERVICE: &str = "es";
+const SERVERLESS_SERVICE: &str = "aoss";
+const SERVERLESS_DOMAIN: &str = ".aoss.amazonaws.com";
+const JSON_CONTENT_TYPE: &str = "application/json";
+const BULK_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Set the service, the body and the content type of ARGS for --opensearch.
+pub fn install(args: &mut Args) -> anyhow::Result<()> {
+    let url = args.url.as_deref().context("URL is not specified")?;
+    let service = match is_serverless(url) {
+        true => SERVERLESS_SERVICE,
+        false => SERVICE,
+    };
+    let service = args.service.get_or_insert_with(|| service.to_string());
+    // The serverless collections reject the request without the hash even
+    // if it has no body.
+    if service == SERVERLESS_SERVICE && args.no_content_sha256 {
+        bail!("OpenSearch Serverless requires the x-amz-content-sha256 header. Remove --no-content-sha256");
+    }
+    if let Some(raw) = &args.bulk {
+        let body = match raw.strip_prefix('@') {
+            Some(path) => data::read_body_file(path, body_size::limit(args))?,
+            None => raw.to_string(),
+        };
+        validate_bulk(&body)?;
+        args.data = vec![body];
+    }
+    let has_content_type = args.header.iter().any(|header| {
+        header
+            .split_once(':')
+            .is_some_and(|(key, _)| key.trim().eq_ignore_ascii_case("content-type"))
+    });
+    if args.has_data() && !has_content_type && !args.no_default_content_type {
+        let content_type = match args.bulk {
+            Some(_) => BULK_CONTENT_TYPE,
+            None => JSON_CONTENT_TYPE,
+        };
+        args.header.push(format!("Content-Type: {}", content_type));
+    }
+    Ok(())
+}
+
+/// Whether URL is of a serverless collection (Ex. ID.us-east-1.aoss.amazonaws.com).
+fn is_serverless(url: &str) -> bool {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.ends_with(SERVERLESS_DOMAIN)))
+        .unwrap_or(false)
+}
+
+/// Check that BODY is the NDJSON of the bulk API: one JSON document on each
+/// line, and a newline at the end.
+fn validate_bulk(body: &str) -> anyhow::Result<()> {
+    if body.is_empty() {
+        bail!("The bulk body is empty");
+    }
+    for (i, line) in body.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        data::validate_json(line)
+            .with_context(|| format!("Invalid line {} of the bulk body", i + 1))?;
+    }
+    if !body.ends_with('\n') {
+        bail!("The bulk body must end with a newline, or OpenSearch responds with 400");
+    }
+    Ok(())
+}
+
+/// The error response BODY pretty-printed, after printing its root causes to
+/// stderr. Other bodies are returned as they are.
+pub fn report_error(body: Vec<u8>) -> Vec<u8> {
+    let Ok(envelope) = serde_json::from_slice::<Value>(&body) else {
+        return body;
+    };
+    let causes = root_causes(&envelope);
+    if causes.is_empty() {
+        return body;
+    }
+    for cause in causes {
+        logging::message(Level::Error, format_args!("Root cause: {}", cause));
+    }
+    serde_json::to_vec_pretty(&envelope).unwrap_or(body)
+}
+
+/// The "TYPE: REASON"s of the root causes in the error ENVELOPE, or of the
+/// error itself without them.
+fn root_causes(envelope: &Value) -> Vec<String> {
+    let describe = |error: &Value| match (error["type"].as_str(), error["reason"].as_str()) {
+        (Some(kind), Some(reason)) => Some(format!("{}: {}", kind, reason)),
+        (Some(kind), None) => Some(kind.to_string()),
+        (None, Some(reason)) => Some(reason.to_string()),
+        (None, None) => None,
+    };
+    let error = &envelope["error"];
+    if let Some(message) = error.as_str() {
+        return vec![message.to_string()];
+    }
+    let causes = error["root_cause"]
+        .as_array()
+        .map(|causes| causes.iter().filter_map(describe).collect::<Vec<_>>())
+        .unwrap_or_default();
+    if causes.is_empty() {
+        describe(error).into_iter().collect()
+    } else {
+        causes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use serde_json::json;
+
+    use super::{install, root_causes, validate_bulk};
+    use crate::Args;
+
+    fn installed(options: &[&str]) -> anyhow::Result<Args> {
+        let mut args = Args::parse_from(["awscurl", "--opensearch"].iter().chain(options));
+        install(&mut args)?;
+        Ok(args)
+    }
+
+    #[test]
+    fn service_and_content_type() {
+        let args = installed(&[
+            "https://search-d.us-east-1.es.amazonaws.com/i/_search",
+            "-d",
+            "{}",
+        ])
+        .unwrap();
+        assert_eq!(args.service.as_deref(), Some("es"));
+        assert_eq!(args.header, ["Content-Type: application/json"]);
+
+        let args = installed(&["https://abc.us-east-1.aoss.amazonaws.com/i/_doc/1"]).unwrap();
+        assert_eq!(args.service.as_deref(), Some("aoss"));
+        assert!(args.header.is_empty());
+
+        let args = installed(&[
+            "https://search-d.us-east-1.es.amazonaws.com/_bulk",
+            "--bulk",
+            "{\"index\":{\"_index\":\"i\"}}\n{\"a\":1}\n",
+            "-H",
+            "content-type: application/json",
+        ])
+        .unwrap();
+        assert_eq!(args.data, ["{\"index\":{\"_index\":\"i\"}}\n{\"a\":1}\n"]);
+        assert_eq!(args.header, ["content-type: application/json"]);
+
+        assert!(installed(&[
+            "https://abc.us-east-1.aoss.amazonaws.com/",
+            "--no-content-sha256"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn bulk_body() {
+        assert!(validate_bulk("{\"delete\":{\"_id\":\"1\"}}\n").is_ok());
+        assert_eq!(
+            validate_bulk("{\"index\":{}}\n{\"a\":1}")
+                .unwrap_err()
+                .to_string(),
+            "The bulk body must end with a newline, or OpenSearch responds with 400"
+        );
+        assert_eq!(
+            validate_bulk("{\"index\":{}}\n{\"a\":\n")
+                .unwrap_err()
+                .to_string(),
+            "Invalid line 2 of the bulk body"
+        );
+        assert!(validate_bulk("").is_err());
+    }
+
+    #[test]
+    fn error_envelope() {
+        let envelope = json!({
+            "error": {
+                "root_cause": [{"type": "index_not_found_exception", "reason": "no such index [i]"}],
+                "type": "index_not_found_exception",
+                "reason": "no such index [i]",
+            },
+            "status": 404,
+        });
+        assert_eq!(
+            root_causes(&envelope),
+            ["index_not_found_exception: no such index [i]"]
+        );
+        let envelope = json!({"error": {"type": "parse_exception", "reason": "x"}, "status": 400});
+        assert_eq!(root_causes(&envelope), ["parse_exception: x"]);
+        let envelope = json!({"error": "Incorrect HTTP method", "status": 405});
+        assert_eq!(root_causes(&envelope), ["Incorrect HTTP method"]);
+        assert!(root_causes(&json!({"message": "Forbidden"})).is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+//! Export a span of the request to an OTLP/HTTP endpoint (`otel` feature)
+//!
+//! The span is encoded as OTLP JSON and posted to
+//! `$OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`, or `$OTEL_EXPORTER_OTLP_ENDPOINT/v1/traces`.
+
+use std::{
+    env,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use http::header::CONTENT_TYPE;
+use serde_json::{json, Value};
+
+use crate::{
+    logging::{self, Level},
+    trace::TraceParent,
+};
+
+// The export runs after the response is printed, and must not keep the process
+// alive for long when the collector is unreachable.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct Span<'a> {
+    pub trace: &'a TraceParent,
+    pub method: &'a str,
+    pub url: &'a str,
+    pub status: Option<u16>,
+    pub start: SystemTime,
+    pub end: SystemTime,
+}
+
+fn endpoint() -> Option<String> {
+    if let Ok(endpoint) = env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") {
+        return Some(endpoint);
+    }
+    let base = env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok()?;
+    Some(format!("{}/v1/traces", base.trim_end_matches('/')))
+}
+
+/// Export the span. Failures are reported to stderr and never change the result of the request.
+pub async fn export(client: &reqwest::Client, span: &Span<'_>) {
+    let Some(endpoint) = endpoint() else {
+        return;
+    };
+    let result = client
+        .post(&endpoint)
+        .timeout(EXPORT_TIMEOUT)
+        .header(CONTENT_TYPE, "application/json")
+        .body(encode(span).to_string())
+        .send()
+        .await
+        .and_then(|res| res.error_for_status());
+    if let Err(e) = result {
+        logging::message(
+            Level::Warn,
+            format_args!("Failed to export the span to {}: {}", endpoint, e),
+        );
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+fn encode(span: &Span) -> Value {
+    let mut attributes = vec![
+        json!({"key": "http.method", "value": {"stringValue": span.method}}),
+        json!({"key": "url.full", "value": {"stringValue": span.url}}),
+    ];
+    if let Some(status) = span.status {
+        attributes
+            .push(json!({"key": "http.status_code", "value": {"intValue": status.to_string()}}));
+    }
+    // STATUS_CODE_ERROR for transport errors and 5xx, STATUS_CODE_UNSET otherwise.
+    let status_code = match span.status {
+        Some(status) if status < 500 => 0,
+        _ => 2,
+    };
+    let mut otlp_span = json!({
+        "traceId": span.trace.trace_id,
+        "spanId": span.trace.span_id,
+        "name": span.method,
+        // SPAN_KIND_CLIENT
+        "kind": 3,
+        "startTimeUnixNano": unix_nanos(span.start),
+        "endTimeUnixNano": unix_nanos(span.end),
+        "attributes": attributes,
+        "status": {"code": status_code},
+    });
+    if let Some(parent) = &span.trace.parent_span_id {
+        otlp_span["parentSpanId"] = json!(parent);
+    }
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [{"key": "service.name", "value": {"stringValue": "awscurl"}}],
+            },
+            "scopeSpans": [{
+                "scope": {"name": "awscurl", "version": env!("CARGO_PKG_VERSION")},
+                "spans": [otlp_span],
+            }],
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use serde_json::json;
+
+    use super::{encode, Span};
+    use crate::trace::TraceParent;
+
+    #[test]
+    fn encode_span() {
+        let trace = TraceParent {
+            trace_id: "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+            span_id: "53995c3f42cd8ad8".to_string(),
+            parent_span_id: Some("00f067aa0ba902b7".to_string()),
+            flags: "01".to_string(),
+        };
+        let span = Span {
+            trace: &trace,
+            method: "GET",
+            url: "https://example.com/",
+            status: Some(200),
+            start: UNIX_EPOCH + Duration::from_secs(1),
+            end: UNIX_EPOCH + Duration::from_millis(1500),
+        };
+        let encoded = encode(&span);
+        let otlp_span = &encoded["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(otlp_span["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(otlp_span["spanId"], "53995c3f42cd8ad8");
+        assert_eq!(otlp_span["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(otlp_span["startTimeUnixNano"], "1000000000");
+        assert_eq!(otlp_span["endTimeUnixNano"], "1500000000");
+        assert_eq!(
+            otlp_span["attributes"][2],
+            json!({"key": "http.status_code", "value": {"intValue": "200"}})
+        );
+        assert_eq!(otlp_span["status"]["code"], 0);
+    }
+}
@@ -0,0 +1,594 @@
+//! Where and how the response is written (`--output`, `--output-format` ...)
+
+use std::{
+    collections::BTreeSet,
+    fmt,
+    future::Future,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use clap::ValueEnum;
+use http::{header::LAST_MODIFIED, HeaderMap, StatusCode};
+use serde_json::{json, Map, Value};
+
+use crate::interrupt::Interrupted;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// Only the response body
+    #[default]
+    Body,
+    /// A JSON document which describes the status, headers, body and timing
+    Json,
+}
+
+pub struct Timing {
+    /// Until the response headers are received
+    pub headers: Duration,
+    /// Until the whole response body is received
+    pub total: Duration,
+}
+
+/// The file name for `--remote-name`, which is the last segment of the URL path.
+pub fn remote_name(url: &str) -> anyhow::Result<PathBuf> {
+    let url = reqwest::Url::parse(url)?;
+    let name = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .with_context(|| format!("Unable to decide the file name from the URL: {}", url))?;
+    let name = percent_encoding::percent_decode_str(name).decode_utf8_lossy();
+    Ok(PathBuf::from(name.as_ref()))
+}
+
+/// The placeholders of `--output-template`.
+const TEMPLATE_VARIABLES: [&str; 5] = ["host", "path", "path_basename", "index", "status"];
+
+/// The output files of `--output-template` written in this process.
+static TEMPLATE_FILES: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Check that TEMPLATE of `--output-template` has only the known placeholders.
+pub fn check_template(template: &str) -> anyhow::Result<()> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let len = rest[start..]
+            .find('}')
+            .with_context(|| format!("Unterminated {{ in --output-template {}", template))?;
+        let name = &rest[start + 1..start + len];
+        if !TEMPLATE_VARIABLES.contains(&name) {
+            bail!(
+                "Unknown placeholder {{{}}} in --output-template. Use {}",
+                name,
+                TEMPLATE_VARIABLES
+                    .map(|name| format!("{{{}}}", name))
+                    .join(", ")
+            );
+        }
+        rest = &rest[start + len + 1..];
+    }
+    Ok(())
+}
+
+/// The output file of `--output-template` for the response of STATUS to URL,
+/// which is the INDEX-th request given with --next (starting at 1).
+///
+/// {path} keeps the "/" between the segments of the URL path, where "." and
+/// ".." are dropped so that the file stays under the directory of the template.
+pub fn expand_template(
+    template: &str,
+    url: &str,
+    index: usize,
+    status: StatusCode,
+) -> anyhow::Result<PathBuf> {
+    let url = reqwest::Url::parse(url)?;
+    let segments = url
+        .path_segments()
+        .into_iter()
+        .flatten()
+        .map(|segment| percent_encoding::percent_decode_str(segment).decode_utf8_lossy())
+        .filter(|segment| !matches!(segment.as_ref(), "" | "." | ".."))
+        .map(|segment| segment.replace(['/', '\\'], "_"))
+        .collect::<Vec<_>>();
+    let mut ret = template.to_string();
+    for name in TEMPLATE_VARIABLES {
+        let placeholder = format!("{{{}}}", name);
+        if !ret.contains(&placeholder) {
+            continue;
+        }
+        let value = match name {
+            "host" => url.host_str().unwrap_or_default().to_string(),
+            "path" => segments.join("/"),
+            "path_basename" => {
+                if url.path().ends_with('/') {
+                    bail!("Unable to decide {{path_basename}} from the URL: {}", url);
+                }
+                segments.last().cloned().unwrap_or_default()
+            }
+            "index" => index.to_string(),
+            _ => status.as_u16().to_string(),
+        };
+        ret = ret.replace(&placeholder, &value);
+    }
+    Ok(PathBuf::from(ret))
+}
+
+/// Record PATH of `--output-template`, which fails when it was written by a
+/// previous request unless it is appended to.
+pub fn claim_template_file(path: &Path, append: bool) -> anyhow::Result<()> {
+    if !TEMPLATE_FILES.lock().unwrap().insert(path.to_path_buf()) && !append {
+        bail!(
+            "--output-template resolved to {} for two requests. Add {{index}} to the template, or add --append",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Set the modification time of the downloaded file to Last-Modified (`--remote-time`).
+///
+/// Nothing is done when the header is missing or unparseable.
+pub fn apply_remote_time(path: &Path, headers: &HeaderMap) -> anyhow::Result<()> {
+    let last_modified = headers
+        .get(LAST_MODIFIED)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok());
+    if let Some(time) = last_modified {
+        filetime::set_file_mtime(path, filetime::FileTime::from_system_time(time))?;
+    }
+    Ok(())
+}
+
+/// Append CONTENTS to the file at PATH, which is created with its directory if
+/// missing (`--append`).
+pub fn append(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Unable to open {}", path.display()))?;
+    file.write_all(contents)
+        .with_context(|| format!("Unable to write {}", path.display()))
+}
+
+/// The error of the response body larger than `--max-response-size`, which
+/// keeps the body up to the limit.
+#[derive(Debug)]
+pub struct TooLarge {
+    pub limit: u64,
+    /// The Content-Length which exceeds the limit, when nothing is read
+    pub content_length: Option<u64>,
+    /// The number of the bytes received before giving up
+    pub received: u64,
+    pub partial: Vec<u8>,
+}
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.content_length {
+            Some(length) => write!(
+                f,
+                "The response body of {} bytes exceeds --max-response-size {}",
+                length, self.limit
+            ),
+            None => write!(
+                f,
+                "The response body exceeded --max-response-size {} after {} bytes were received",
+                self.limit, self.received
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TooLarge {}
+
+/// Read the response body until it ends or `interrupted` completes, which fails
+/// with `Interrupted` keeping the body received so far.
+///
+/// With `--ignore-content-length`, the body which ends before its Content-Length
+/// is returned as it is instead of an error. With `tee`, each chunk is also
+/// written and flushed to it as soon as it is received (`--no-buffer`). The body
+/// longer than `limit` fails with `TooLarge`, before reading it if the
+/// Content-Length tells so.
+pub async fn read_body(
+    mut res: reqwest::Response,
+    ignore_length: bool,
+    limit: Option<u64>,
+    interrupted: impl Future<Output = ()>,
+    mut tee: Option<&mut dyn Write>,
+) -> anyhow::Result<Vec<u8>> {
+    let limit = limit.unwrap_or(u64::MAX);
+    if let Some(length) = res.content_length().filter(|length| *length > limit) {
+        return Err(TooLarge {
+            limit,
+            content_length: Some(length),
+            received: 0,
+            partial: vec![],
+        }
+        .into());
+    }
+    tokio::pin!(interrupted);
+    let mut body = Vec::new();
+    loop {
+        let chunk = tokio::select! {
+            chunk = res.chunk() => chunk,
+            _ = &mut interrupted => return Err(Interrupted::with_partial(body).into()),
+        };
+        match chunk {
+            Ok(Some(chunk)) => {
+                let received = (body.len() + chunk.len()) as u64;
+                let kept = match received > limit {
+                    true => &chunk[..(limit as usize - body.len())],
+                    false => &chunk[..],
+                };
+                if let Some(tee) = tee.as_mut() {
+                    tee.write_all(kept)?;
+                    tee.flush()?;
+                }
+                body.extend_from_slice(kept);
+                if received > limit {
+                    return Err(TooLarge {
+                        limit,
+                        content_length: None,
+                        received,
+                        partial: body,
+                    }
+                    .into());
+                }
+            }
+            Ok(None) => return Ok(body),
+            Err(_) if ignore_length => return Ok(body),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Whether a newline is printed after the body. It is added with `--newline`, or
+/// on a terminal so that the prompt does not follow the body on the same line.
+pub fn needs_newline(body: &[u8], newline: bool) -> bool {
+    newline || (io::stdout().is_terminal() && !body.is_empty() && !body.ends_with(b"\n"))
+}
+
+/// The values of the headers of `--header-out`, one line for each NAME in order,
+/// and the names which are missing in the response.
+///
+/// The values of a repeated header are joined with ", ", or printed on separate
+/// lines with `lines`. A missing header is printed as an empty line.
+pub fn header_out<'a>(
+    headers: &HeaderMap,
+    names: &'a [String],
+    lines: bool,
+) -> (String, Vec<&'a str>) {
+    let mut out = String::new();
+    let mut missing = Vec::new();
+    for name in names {
+        let values = headers
+            .get_all(name.as_str())
+            .iter()
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+            .collect::<Vec<_>>();
+        if values.is_empty() {
+            missing.push(name.as_str());
+        }
+        let separator = if lines { "\n" } else { ", " };
+        out.push_str(&values.join(separator));
+        out.push('\n');
+    }
+    (out, missing)
+}
+
+/// The headers of the request id, which AWS support asks for.
+const REQUEST_ID_HEADERS: [&str; 3] = ["x-amzn-requestid", "x-amz-request-id", "x-amz-cf-id"];
+
+/// The request id of the response, which is the first one of REQUEST_ID_HEADERS.
+pub fn request_id(headers: &HeaderMap) -> Option<&str> {
+    REQUEST_ID_HEADERS
+        .iter()
+        .find_map(|name| headers.get(*name))
+        .and_then(|value| value.to_str().ok())
+}
+
+/// The line printed before the body with `--dump-body-on-error`,
+/// Ex. "HTTP 403 Forbidden (request id: 0123)".
+pub fn error_summary(status: StatusCode, headers: &HeaderMap) -> String {
+    match request_id(headers) {
+        Some(request_id) => format!("HTTP {} (request id: {})", status, request_id),
+        None => format!("HTTP {}", status),
+    }
+}
+
+/// The JSON document printed by `--output-format json`.
+///
+/// Bodies which are not valid UTF-8 are encoded with base64, and all of them
+/// are with BASE64 (`--output-base64`). Repeated response headers become arrays.
+pub fn envelope(
+    status: StatusCode,
+    headers: &HeaderMap,
+    url: &str,
+    body: &[u8],
+    timing: &Timing,
+    base64: bool,
+) -> Value {
+    let mut header_map = Map::new();
+    for name in headers.keys() {
+        let mut values = headers
+            .get_all(name)
+            .iter()
+            .map(|value| Value::String(String::from_utf8_lossy(value.as_bytes()).into_owned()))
+            .collect::<Vec<_>>();
+        let value = if values.len() == 1 {
+            values.remove(0)
+        } else {
+            Value::Array(values)
+        };
+        header_map.insert(name.to_string(), value);
+    }
+    let (body, encoding) = match std::str::from_utf8(body) {
+        Ok(text) if !base64 => (text.to_string(), "utf8"),
+        _ => (BASE64_STANDARD.encode(body), "base64"),
+    };
+    json!({
+        "status": status.as_u16(),
+        "headers": header_map,
+        "body": body,
+        "body_encoding": encoding,
+        "timing_ms": {
+            "headers": timing.headers.as_millis() as u64,
+            "total": timing.total.as_millis() as u64,
+        },
+        "url": url,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::pending, time::Duration};
+
+    use http::{HeaderMap, HeaderValue, StatusCode};
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{
+        check_template, envelope, error_summary, expand_template, header_out, read_body,
+        remote_name, Timing, TooLarge,
+    };
+    use crate::interrupt::Interrupted;
+
+    #[test]
+    fn remote_name_from_url() {
+        assert_eq!(
+            remote_name("https://bucket.s3.amazonaws.com/dir/my%20file.txt?versionId=1").unwrap(),
+            std::path::Path::new("my file.txt")
+        );
+        assert!(remote_name("https://bucket.s3.amazonaws.com/").is_err());
+    }
+
+    #[test]
+    fn output_template() {
+        let url = "https://bucket.s3.amazonaws.com/reports/2024/my%20file.json?versionId=1";
+        assert_eq!(
+            expand_template("out/{host}/{path_basename}", url, 2, StatusCode::OK).unwrap(),
+            std::path::Path::new("out/bucket.s3.amazonaws.com/my file.json")
+        );
+        assert_eq!(
+            expand_template("{index}-{status}/{path}", url, 2, StatusCode::NOT_FOUND).unwrap(),
+            std::path::Path::new("2-404/reports/2024/my file.json")
+        );
+        assert_eq!(
+            expand_template(
+                "out/{path}",
+                "https://example.com/a/../../b/%2E%2E/c/..%2F..%2Fd",
+                1,
+                StatusCode::OK
+            )
+            .unwrap(),
+            std::path::Path::new("out/c/.._.._d")
+        );
+        assert!(expand_template(
+            "{path_basename}",
+            "https://example.com/dir/",
+            1,
+            StatusCode::OK
+        )
+        .is_err());
+
+        assert!(check_template("out/{host}/{index}.json").is_ok());
+        assert_eq!(
+            check_template("out/{name}").unwrap_err().to_string(),
+            "Unknown placeholder {name} in --output-template. Use {host}, {path}, {path_basename}, {index}, {status}"
+        );
+        assert!(check_template("out/{host").is_err());
+    }
+
+    #[test]
+    fn envelope_headers_and_body() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("text/plain"));
+        headers.append("set-cookie", HeaderValue::from_static("a=1"));
+        headers.append("set-cookie", HeaderValue::from_static("b=2"));
+        let timing = Timing {
+            headers: Duration::from_millis(10),
+            total: Duration::from_millis(25),
+        };
+        assert_eq!(
+            envelope(
+                StatusCode::OK,
+                &headers,
+                "https://example.com/",
+                b"hello",
+                &timing,
+                false
+            ),
+            json!({
+                "status": 200,
+                "headers": {"content-type": "text/plain", "set-cookie": ["a=1", "b=2"]},
+                "body": "hello",
+                "body_encoding": "utf8",
+                "timing_ms": {"headers": 10, "total": 25},
+                "url": "https://example.com/",
+            })
+        );
+    }
+
+    #[test]
+    fn header_out_values() {
+        let mut headers = HeaderMap::new();
+        headers.insert("etag", HeaderValue::from_static("\"abc\""));
+        headers.append("x-amz-meta-tag", HeaderValue::from_static("a"));
+        headers.append("x-amz-meta-tag", HeaderValue::from_static("b"));
+        let names = ["X-Amz-Meta-Tag", "location", "ETag"].map(String::from);
+        assert_eq!(
+            header_out(&headers, &names, false),
+            ("a, b\n\n\"abc\"\n".to_string(), vec!["location"])
+        );
+        assert_eq!(header_out(&headers, &names, true).0, "a\nb\n\n\"abc\"\n");
+    }
+
+    #[test]
+    fn summary_with_request_id() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            error_summary(StatusCode::BAD_GATEWAY, &headers),
+            "HTTP 502 Bad Gateway"
+        );
+        headers.insert(
+            "x-amz-request-id",
+            HeaderValue::from_static("4442587FB7D0A2F9"),
+        );
+        assert_eq!(
+            error_summary(StatusCode::FORBIDDEN, &headers),
+            "HTTP 403 Forbidden (request id: 4442587FB7D0A2F9)"
+        );
+    }
+
+    #[test]
+    fn envelope_binary_body() {
+        let timing = Timing {
+            headers: Duration::ZERO,
+            total: Duration::ZERO,
+        };
+        let envelope = envelope(
+            StatusCode::OK,
+            &HeaderMap::new(),
+            "https://example.com/",
+            &[0xff, 0x00, 0x01],
+            &timing,
+            false,
+        );
+        assert_eq!(envelope["body"], "/wAB");
+        assert_eq!(envelope["body_encoding"], "base64");
+
+        let envelope = super::envelope(
+            StatusCode::OK,
+            &HeaderMap::new(),
+            "https://example.com/",
+            b"hello",
+            &timing,
+            true,
+        );
+        assert_eq!(envelope["body"], "aGVsbG8=");
+        assert_eq!(envelope["body_encoding"], "base64");
+    }
+
+    #[tokio::test]
+    async fn ignore_content_length() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let _ = stream.read(&mut buf).await.unwrap();
+                // The body is shorter than the content-length.
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nshort")
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let res = reqwest::get(&url).await.unwrap();
+        assert!(read_body(res, false, None, pending(), None).await.is_err());
+        let res = reqwest::get(&url).await.unwrap();
+        assert_eq!(
+            read_body(res, true, None, pending(), None).await.unwrap(),
+            b"short"
+        );
+    }
+
+    #[tokio::test]
+    async fn interrupt_body() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let _ = stream.read(&mut buf).await.unwrap();
+            // The rest of the body never comes.
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 100\r\n\r\npartial")
+                .await
+                .unwrap();
+            pending::<()>().await;
+            drop(stream);
+        });
+
+        let res = reqwest::get(&url).await.unwrap();
+        let interrupted = tokio::time::sleep(Duration::from_millis(200));
+        let err = read_body(res, false, None, interrupted, None)
+            .await
+            .unwrap_err();
+        let err = err.downcast::<Interrupted>().unwrap();
+        assert_eq!(err.partial, b"partial");
+        assert_eq!(err.to_string(), "Transfer interrupted after 7 bytes");
+    }
+
+    #[tokio::test]
+    async fn max_response_size() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for response in [
+                &b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\n0123456789"[..],
+                b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\na\r\n0123456789\r\n0\r\n\r\n",
+            ] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let _ = stream.read(&mut buf).await.unwrap();
+                stream.write_all(response).await.unwrap();
+            }
+        });
+
+        // Nothing is read beyond the Content-Length.
+        let res = reqwest::get(&url).await.unwrap();
+        let err = read_body(res, false, Some(5), pending(), None)
+            .await
+            .unwrap_err();
+        let err = err.downcast::<TooLarge>().unwrap();
+        assert_eq!(err.partial, b"");
+        assert_eq!(
+            err.to_string(),
+            "The response body of 10 bytes exceeds --max-response-size 5"
+        );
+
+        let res = reqwest::get(&url).await.unwrap();
+        let mut tee = Vec::new();
+        let err = read_body(res, false, Some(4), pending(), Some(&mut tee))
+            .await
+            .unwrap_err();
+        let err = err.downcast::<TooLarge>().unwrap();
+        assert_eq!(err.partial, b"0123");
+        assert_eq!(tee, b"0123");
+        assert_eq!(
+            err.to_string(),
+            "The response body exceeded --max-response-size 4 after 10 bytes were received"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Paging the output longer than the terminal (`--no-pager` to disable)
+//!
+//! Same as git and the AWS CLI, the output printed to a terminal is piped to
+//! $PAGER, or `less -FRX` without it, when it does not fit in the screen. An
+//! empty $PAGER, a missing pager and the output which is not a terminal print
+//! the output directly.
+
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    process::{Command, Stdio},
+};
+
+const DEFAULT_PAGER: &str = "less -FRX";
+
+/// Print OUTPUT through the pager if stdout is a terminal and OUTPUT is taller
+/// than it. Returns whether it was paged, otherwise nothing is printed.
+pub fn page(output: &[u8]) -> bool {
+    if !io::stdout().is_terminal() {
+        return false;
+    }
+    let Some(height) = height() else {
+        return false;
+    };
+    if !is_taller(output, height) {
+        return false;
+    }
+    let Some(command) = command(env::var("PAGER").ok().as_deref()) else {
+        return false;
+    };
+    let Ok(mut child) = Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .spawn()
+    else {
+        return false;
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // Quitting the pager before the end closes the pipe, which is not an
+        // error.
+        let _ = stdin.write_all(output);
+    }
+    let _ = child.wait();
+    true
+}
+
+/// The command line of the pager given by PAGER, which is None to disable it.
+fn command(pager: Option<&str>) -> Option<Vec<String>> {
+    let pager = pager.unwrap_or(DEFAULT_PAGER).trim();
+    if pager.is_empty() || pager == "cat" {
+        return None;
+    }
+    shlex::split(pager).filter(|command| !command.is_empty())
+}
+
+/// Whether OUTPUT does not fit in HEIGHT rows with the prompt after it.
+fn is_taller(output: &[u8], height: usize) -> bool {
+    let newlines = output.iter().filter(|b| **b == b'\n').count();
+    let lines = newlines + usize::from(!output.is_empty() && !output.ends_with(b"\n"));
+    lines >= height
+}
+
+/// The number of the rows of the terminal of stdout.
+#[cfg(unix)]
+fn height() -> Option<usize> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: TIOCGWINSZ only writes the winsize given.
+    let ret = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    match ret {
+        0 if size.ws_row > 0 => Some(size.ws_row as usize),
+        _ => lines(),
+    }
+}
+
+#[cfg(not(unix))]
+fn height() -> Option<usize> {
+    lines()
+}
+
+/// $LINES, which some shells export.
+fn lines() -> Option<usize> {
+    env::var("LINES")
+        .ok()?
+        .parse()
+        .ok()
+        .filter(|lines| *lines > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{command, is_taller};
+
+    #[test]
+    fn pager_command() {
+        assert_eq!(command(None).unwrap(), ["less", "-FRX"]);
+        assert_eq!(
+            command(Some("less -R --prompt='awscurl'")).unwrap(),
+            ["less", "-R", "--prompt=awscurl"]
+        );
+        assert_eq!(command(Some("")), None);
+        assert_eq!(command(Some("cat")), None);
+    }
+
+    #[test]
+    fn taller_output() {
+        assert!(!is_taller(b"a\nb\n", 3));
+        assert!(is_taller(b"a\nb\nc", 3));
+        assert!(is_taller(b"a\nb\n", 2));
+        assert!(!is_taller(b"", 1));
+    }
+}
@@ -0,0 +1,119 @@
+//! Polling until the response has a status (`--retry-until-status`)
+//!
+//! Unlike `--retry`, the other statuses are not failures to give up on: the
+//! request is signed and sent again every `--retry-until-interval` until
+//! `--retry-until-timeout`. Each attempt is still retried on its own failures
+//! with `--retry`.
+
+use std::{
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use serde_json::json;
+
+use crate::{
+    interrupt,
+    logging::{self, Level},
+    retry,
+    upload::Upload,
+    AwsCurlParam,
+};
+
+/// Send the request until the response has --retry-until-status, or send it
+/// once with the retries of --retry without it. The last response is returned.
+pub async fn send(
+    param: &AwsCurlParam,
+    client: &reqwest::Client,
+    mut req: reqwest::Request,
+) -> anyhow::Result<reqwest::Response> {
+    let args = &param.args;
+    let Some(wanted) = args.retry_until_status else {
+        return retry::send(param, client, req).await;
+    };
+    let interval = Duration::from_secs_f64(args.retry_until_interval);
+    let deadline = Instant::now() + Duration::from_secs_f64(args.retry_until_timeout);
+    let mut attempt = 1;
+    loop {
+        let res = retry::send(param, client, req).await?;
+        let status = res.status();
+        if status.as_u16() == wanted || Instant::now() + interval > deadline {
+            return Ok(res);
+        }
+        if args.verbose && logging::is_json() {
+            let fields = json!({
+                "status": status.as_u16(),
+                "wanted": wanted,
+                "attempt": attempt,
+                "delay_ms": interval.as_millis() as u64,
+            });
+            logging::event(Level::Debug, "poll", fields);
+        } else if args.verbose {
+            logging::note(format_args!(
+                "Got {}, sending again in {}s until {} (attempt {})",
+                status.as_u16(),
+                interval.as_secs_f64(),
+                wanted,
+                attempt
+            ));
+        }
+        // The connection is released before waiting.
+        drop(res);
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = interrupt::interrupted() => {
+                return Err(interrupt::Interrupted::with_partial(vec![]).into());
+            }
+        }
+        req = resign(param).await?;
+        attempt += 1;
+    }
+}
+
+/// The request of PARAM signed again, so that the date of the signature is
+/// not too old after waiting.
+pub async fn resign(param: &AwsCurlParam) -> anyhow::Result<reqwest::Request> {
+    let mut req = param.sendable(param.build_request().await?)?;
+    if let Some(Upload::Bytes(bytes)) = &param.upload {
+        *req.body_mut() = Some(bytes.clone().into());
+    }
+    Ok(req)
+}
+
+/// The exit code when the last response has STATUS, and its output exited
+/// with CODE. It is 0 only when STATUS is WANTED, even if it is an error.
+pub fn exit_code(wanted: u16, status: Option<u16>, code: ExitCode) -> ExitCode {
+    match status {
+        Some(status) if status == wanted => ExitCode::SUCCESS,
+        _ if code == ExitCode::SUCCESS => ExitCode::FAILURE,
+        _ => code,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::ExitCode;
+
+    use super::exit_code;
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(
+            exit_code(200, Some(200), ExitCode::SUCCESS),
+            ExitCode::SUCCESS
+        );
+        // Waiting for the deletion.
+        assert_eq!(
+            exit_code(404, Some(404), ExitCode::FAILURE),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(
+            exit_code(204, Some(200), ExitCode::SUCCESS),
+            ExitCode::FAILURE
+        );
+        assert_eq!(
+            exit_code(200, Some(404), ExitCode::from(4)),
+            ExitCode::from(4)
+        );
+    }
+}
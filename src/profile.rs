@@ -0,0 +1,303 @@
+//! The profiles of the shared config files (`--list-profiles`, the check of
+//! --profile, the defaults of awscurl)
+//!
+//! The SDK takes a missing profile as one without any settings, and the request
+//! fails later with a generic error of the credential provider. The names of the
+//! sections are read here instead, so that a typo of --profile is reported
+//! before the request with the closest name.
+//!
+//! The keys of awscurl in the selected profile of the config file
+//! (awscurl_service, awscurl_endpoint_url and awscurl_default_headers) are read
+//! here too, since the SDK does not expose the unknown keys. They are the
+//! defaults below the options on the command line and the environment
+//! variables.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+
+use crate::{endpoint, Args};
+
+/// The path of the shared file in the environment variable VAR, or FILE in ~/.aws.
+fn path(var: &str, file: &str) -> PathBuf {
+    if let Some(path) = env::var_os(var).filter(|path| !path.is_empty()) {
+        return PathBuf::from(path);
+    }
+    let home = env::var_os("HOME").unwrap_or_default();
+    Path::new(&home).join(".aws").join(file)
+}
+
+/// The file for the messages, which is ~/.aws/FILE unless VAR is set.
+fn display(var: &str, file: &str) -> String {
+    env::var(var)
+        .ok()
+        .filter(|path| !path.is_empty())
+        .unwrap_or_else(|| format!("~/.aws/{}", file))
+}
+
+/// The profile names in the sections of RAW. The sections of the config file
+/// are "[default]" and "[profile NAME]", and the others (Ex. "[sso-session
+/// NAME]") are not profiles. The ones of the credentials file are "[NAME]".
+fn parse(raw: &str, config: bool) -> Vec<String> {
+    raw.lines()
+        .filter_map(|line| {
+            let section = line.trim().strip_prefix('[')?.split(']').next()?.trim();
+            match (config, section.split_once(char::is_whitespace)) {
+                (true, Some(("profile", name))) => Some(name.trim()),
+                (true, None) if section == "default" => Some(section),
+                (false, _) => Some(section),
+                _ => None,
+            }
+        })
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The sorted names of the profiles in the config file and the credentials file.
+pub fn names() -> Vec<String> {
+    let mut names = Vec::new();
+    for (var, file, config) in [
+        ("AWS_CONFIG_FILE", "config", true),
+        ("AWS_SHARED_CREDENTIALS_FILE", "credentials", false),
+    ] {
+        if let Ok(raw) = fs::read_to_string(path(var, file)) {
+            names.extend(parse(&raw, config));
+        }
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// The keys of awscurl in a profile of the config file.
+#[derive(Debug, Default, PartialEq)]
+struct Defaults {
+    service: Option<String>,
+    endpoint_url: Option<String>,
+    /// The lines of awscurl_default_headers, which has a header on each of
+    /// the indented lines following it, or one on its own line
+    headers: Vec<String>,
+}
+
+/// The defaults in the section of PROFILE in RAW of the config file. The other
+/// keys, which are the ones of the SDK and the other tools, are ignored.
+fn parse_defaults(raw: &str, profile: &str) -> Defaults {
+    let mut defaults = Defaults::default();
+    let mut in_profile = false;
+    let mut key = String::new();
+    for line in raw.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(['#', ';']) {
+            continue;
+        }
+        if trimmed.starts_with('[') {
+            in_profile = parse(trimmed, true).iter().any(|name| name == profile);
+            key.clear();
+            continue;
+        }
+        if !in_profile {
+            continue;
+        }
+        if line.starts_with([' ', '\t']) {
+            // A sub-property of the key above
+            if key == "awscurl_default_headers" {
+                defaults.headers.push(trimmed.to_string());
+            }
+            continue;
+        }
+        let Some((name, value)) = trimmed.split_once('=') else {
+            key.clear();
+            continue;
+        };
+        key = name.trim().to_string();
+        let value = Some(value.trim().to_string()).filter(|value| !value.is_empty());
+        match key.as_str() {
+            "awscurl_service" => defaults.service = value,
+            "awscurl_endpoint_url" => defaults.endpoint_url = value,
+            "awscurl_default_headers" => defaults.headers.extend(value),
+            _ => {}
+        }
+    }
+    defaults
+}
+
+/// The name of HEADER in lowercase.
+fn header_name(header: &str) -> String {
+    let name = header.split_once(':').map_or(header, |(name, _)| name);
+    name.trim().to_ascii_lowercase()
+}
+
+/// Apply the defaults of the selected profile to ARGS, unless the options or
+/// the environment variables give them.
+pub fn install(args: &mut Args) {
+    let profile = args
+        .profile
+        .clone()
+        .or_else(|| env::var("AWS_PROFILE").ok().filter(|name| !name.is_empty()))
+        .unwrap_or_else(|| "default".to_string());
+    let Ok(raw) = fs::read_to_string(path("AWS_CONFIG_FILE", "config")) else {
+        return;
+    };
+    let defaults = parse_defaults(&raw, &profile);
+    if args.service.is_none() && !args.auto_service {
+        args.service = defaults.service;
+    }
+    if endpoint::resolve(args).is_none() {
+        args.endpoint_url = defaults.endpoint_url;
+    }
+    let given = args
+        .header
+        .iter()
+        .map(|header| header_name(header))
+        .collect::<Vec<_>>();
+    let headers = defaults
+        .headers
+        .into_iter()
+        .filter(|header| !given.contains(&header_name(header)));
+    args.header.splice(0..0, headers);
+}
+
+/// The Levenshtein distance between A and B.
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The name in NAMES closest to NAME, if it is close enough to be a typo.
+fn closest<'a>(name: &str, names: &'a [String]) -> Option<&'a str> {
+    names
+        .iter()
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2 && *distance < name.chars().count())
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+/// Fail if --profile is not in the shared config files.
+pub fn validate(args: &Args) -> anyhow::Result<()> {
+    let Some(profile) = &args.profile else {
+        return Ok(());
+    };
+    let names = names();
+    if names.contains(profile) {
+        return Ok(());
+    }
+    let files = format!(
+        "{} or {}",
+        display("AWS_CONFIG_FILE", "config"),
+        display("AWS_SHARED_CREDENTIALS_FILE", "credentials")
+    );
+    if names.is_empty() {
+        bail!(
+            "The profile {} is not found. No profiles in {}",
+            profile,
+            files
+        );
+    }
+    let mut message = format!("The profile {} is not found in {}", profile, files);
+    if let Some(name) = closest(profile, &names) {
+        message.push_str(&format!(" (Did you mean {}?)", name));
+    }
+    message.push_str(&format!("\nAvailable profiles: {}", names.join(", ")));
+    bail!(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{closest, distance, parse, parse_defaults, Defaults};
+
+    #[test]
+    fn parse_sections() {
+        let config = "\
+[default]
+region = us-east-1
+[profile dev]
+[ profile  prod ]
+# [profile commented]
+[sso-session company]
+[services local]
+";
+        assert_eq!(parse(config, true), ["default", "dev", "prod"]);
+        assert_eq!(
+            parse("[default]\naws_access_key_id = x\n[ci]\n", false),
+            ["default", "ci"]
+        );
+    }
+
+    #[test]
+    fn parse_awscurl_keys() {
+        let config = "\
+[default]
+awscurl_service = execute-api
+[profile search]
+region = eu-west-1
+awscurl_service = es
+awscurl_endpoint_url = https://search-dev.eu-west-1.es.amazonaws.com
+awscurl_default_headers =
+  Accept: application/json
+  X-Env: dev
+s3 =
+  max_concurrent_requests = 10
+some_other_tool = x
+[sso-session search]
+awscurl_service = sso
+[profile single]
+awscurl_default_headers = X-Env: ci
+awscurl_service =
+";
+        assert_eq!(
+            parse_defaults(config, "search"),
+            Defaults {
+                service: Some("es".to_string()),
+                endpoint_url: Some("https://search-dev.eu-west-1.es.amazonaws.com".to_string()),
+                headers: vec![
+                    "Accept: application/json".to_string(),
+                    "X-Env: dev".to_string()
+                ],
+            }
+        );
+        assert_eq!(
+            parse_defaults(config, "single"),
+            Defaults {
+                headers: vec!["X-Env: ci".to_string()],
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            parse_defaults(config, "default").service.as_deref(),
+            Some("execute-api")
+        );
+        assert_eq!(parse_defaults(config, "missing"), Defaults::default());
+    }
+
+    #[test]
+    fn distances() {
+        assert_eq!(distance("prod", "prod"), 0);
+        assert_eq!(distance("prd", "prod"), 1);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "dev"), 3);
+    }
+
+    #[test]
+    fn closest_names() {
+        let names = ["default", "dev", "prod"].map(String::from);
+        assert_eq!(closest("prdo", &names), Some("prod"));
+        assert_eq!(closest("deb", &names), Some("dev"));
+        assert_eq!(closest("staging", &names), None);
+        assert_eq!(closest("x", &names), None);
+    }
+}
@@ -0,0 +1,150 @@
+//! Editing the query string of the URL (`--override-query`, `--remove-query`)
+//!
+//! The parameters are edited in the URL before it is signed, so that the
+//! signature covers what is sent. The untouched parameters are kept as they are
+//! in their order, and the given values are percent-encoded like
+//! --data-urlencode.
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+
+use crate::{data, Args};
+
+/// Parse RAW of `--override-query`, which is NAME=VALUE.
+pub fn parse_override(raw: &str) -> Result<(String, String), String> {
+    match raw.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE: {}", raw)),
+    }
+}
+
+/// The decoded name of the raw parameter PARAM.
+fn name(param: &str) -> String {
+    let raw = param.split_once('=').map_or(param, |(name, _)| name);
+    percent_decode_str(&raw.replace('+', " "))
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+fn encode(text: &str) -> String {
+    utf8_percent_encode(text, data::URLENCODE).to_string()
+}
+
+/// URL with the parameters of REMOVE removed and the ones of OVERRIDE set.
+fn edit(url: &str, overrides: &[(String, String)], remove: &[String]) -> String {
+    let (url, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, Some(fragment)),
+        None => (url, None),
+    };
+    let (base, query) = url.split_once('?').unwrap_or((url, ""));
+    let mut params = query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .filter(|param| !remove.contains(&name(param)))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    for (key, value) in overrides {
+        let edited = format!("{}={}", encode(key), encode(value));
+        // The first one is replaced in place, and the others are removed.
+        let mut replaced = false;
+        params.retain_mut(|param| {
+            if name(param) != *key {
+                return true;
+            }
+            if replaced {
+                return false;
+            }
+            *param = edited.clone();
+            replaced = true;
+            true
+        });
+        if !replaced {
+            params.push(edited);
+        }
+    }
+    let mut edited = base.to_string();
+    if !params.is_empty() {
+        edited.push('?');
+        edited.push_str(&params.join("&"));
+    }
+    if let Some(fragment) = fragment {
+        edited.push('#');
+        edited.push_str(fragment);
+    }
+    edited
+}
+
+/// Apply --override-query and --remove-query to the URL of ARGS.
+pub fn install(args: &mut Args) {
+    if args.override_query.is_empty() && args.remove_query.is_empty() {
+        return;
+    }
+    if let Some(url) = &args.url {
+        args.url = Some(edit(url, &args.override_query, &args.remove_query));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edit, parse_override};
+
+    fn overrides(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn override_and_remove() {
+        let url = "https://example.com/items?b=1&nextToken=abc%3D&a=2#top";
+        assert_eq!(
+            edit(url, &overrides(&[("pageSize", "100")]), &[]),
+            "https://example.com/items?b=1&nextToken=abc%3D&a=2&pageSize=100#top"
+        );
+        assert_eq!(
+            edit(url, &overrides(&[("b", "3")]), &["nextToken".to_string()]),
+            "https://example.com/items?b=3&a=2#top"
+        );
+        assert_eq!(
+            edit(url, &[], &["b", "nextToken", "a"].map(String::from)),
+            "https://example.com/items#top"
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        let url = "https://example.com/?tag=a&x=1&tag=b";
+        assert_eq!(
+            edit(url, &overrides(&[("tag", "c")]), &[]),
+            "https://example.com/?tag=c&x=1"
+        );
+        assert_eq!(
+            edit(url, &[], &["tag".to_string()]),
+            "https://example.com/?x=1"
+        );
+    }
+
+    #[test]
+    fn encoding() {
+        let url = "https://example.com/search?my%20key=1&q=a+b";
+        assert_eq!(
+            edit(
+                url,
+                &overrides(&[("my key", "x & y"), ("filter", "名前=値")]),
+                &["q".to_string()]
+            ),
+            "https://example.com/search?my%20key=x%20%26%20y&filter=%E5%90%8D%E5%89%8D%3D%E5%80%A4"
+        );
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_override("q=a=b"),
+            Ok(("q".to_string(), "a=b".to_string()))
+        );
+        assert_eq!(parse_override("q="), Ok(("q".to_string(), String::new())));
+        assert!(parse_override("=1").is_err());
+        assert!(parse_override("q").is_err());
+    }
+}
@@ -0,0 +1,373 @@
+//! The hints of the rate limits in the responses (`--show-rate-limit`)
+//!
+//! Ex. "Rate limit: x-amzn-ratelimit-limit: 10.0, retry-after: 2 (throttled)"
+//!
+//! The headers are looked up in HEADERS, so that a service with another name
+//! of them needs only a row. The requests given with --next and the probes of
+//! --check-count are paced by a delay from the hints: Retry-After as it is, the
+//! interval of the advertised requests per second, or a delay doubled on each
+//! throttled response and halved on each other one. The number of the
+//! throttled responses follows them.
+
+use std::{sync::Mutex, time::Duration};
+
+use chrono::{DateTime, Utc};
+use http::{HeaderMap, StatusCode};
+use serde_json::json;
+
+use crate::{
+    interrupt,
+    logging::{self, Level},
+};
+
+/// What a header tells about the rate limit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    /// The requests per second allowed
+    RequestsPerSecond,
+    /// The requests allowed in the window
+    Limit,
+    /// The requests left in the window
+    Remaining,
+    /// Seconds until the window is reset
+    Reset,
+    /// Seconds or an HTTP date to wait before the next request
+    RetryAfter,
+    /// The error code, which tells throttling by its name
+    ErrorType,
+}
+
+/// The headers of the hints, in the order they are printed.
+const HEADERS: [(&str, Kind); 7] = [
+    // API Gateway and the Selling Partner API
+    ("x-amzn-ratelimit-limit", Kind::RequestsPerSecond),
+    // The gateways and the proxies in front of the services
+    ("x-ratelimit-limit", Kind::Limit),
+    ("x-ratelimit-remaining", Kind::Remaining),
+    ("x-ratelimit-reset", Kind::Reset),
+    ("retry-after", Kind::RetryAfter),
+    // API Gateway, Lambda and the services of the JSON protocols
+    ("x-amzn-errortype", Kind::ErrorType),
+    // The services of the query protocol
+    ("x-amzn-query-error", Kind::ErrorType),
+];
+
+/// The error codes of throttling, which some services return with 400.
+const THROTTLING_CODES: [&str; 9] = [
+    "Throttling",
+    "ThrottlingException",
+    "ThrottledException",
+    "RequestThrottled",
+    "RequestThrottledException",
+    "TooManyRequestsException",
+    "RequestLimitExceeded",
+    "ProvisionedThroughputExceededException",
+    "SlowDown",
+];
+
+/// The first delay after a throttled response without Retry-After.
+const INITIAL_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// The hints in a response.
+#[derive(Debug, Default, PartialEq)]
+pub struct Hints {
+    /// The headers found in HEADERS, with their values
+    headers: Vec<(&'static str, String)>,
+    requests_per_second: Option<f64>,
+    remaining: Option<u64>,
+    reset: Option<Duration>,
+    retry_after: Option<Duration>,
+    throttled: bool,
+}
+
+impl Hints {
+    /// The hints in the response of STATUS and HEADERS, received at NOW.
+    pub fn extract(status: StatusCode, headers: &HeaderMap, now: DateTime<Utc>) -> Self {
+        let mut hints = Hints {
+            throttled: status == StatusCode::TOO_MANY_REQUESTS,
+            ..Default::default()
+        };
+        for (name, kind) in HEADERS {
+            let Some(value) = headers.get(name).and_then(|value| value.to_str().ok()) else {
+                continue;
+            };
+            let value = value.trim();
+            hints.headers.push((name, value.to_string()));
+            match kind {
+                Kind::RequestsPerSecond => hints.requests_per_second = leading_number(value),
+                Kind::Limit => {}
+                Kind::Remaining => hints.remaining = value.parse().ok(),
+                Kind::Reset => hints.reset = seconds(value),
+                Kind::RetryAfter => hints.retry_after = retry_after(value, now),
+                Kind::ErrorType => {
+                    // Ex. "ThrottlingException:http://internal.amazon.com/coral/...",
+                    // "Throttling;Sender"
+                    let code = value.split([':', ';']).next().unwrap_or_default();
+                    hints.throttled |= THROTTLING_CODES.contains(&code);
+                }
+            }
+        }
+        hints
+    }
+
+    /// The delay before the next request, given the one before PREVIOUS.
+    fn delay(&self, previous: Duration) -> Duration {
+        if let Some(retry_after) = self.retry_after {
+            return retry_after.min(MAX_DELAY);
+        }
+        if self.throttled {
+            return (previous * 2).clamp(INITIAL_DELAY, MAX_DELAY);
+        }
+        if self.remaining == Some(0) {
+            if let Some(reset) = self.reset {
+                return reset.min(MAX_DELAY);
+            }
+        }
+        match self.requests_per_second.filter(|rps| *rps > 0.0) {
+            Some(rps) => Duration::from_secs_f64(1.0 / rps).min(MAX_DELAY),
+            None => previous / 2,
+        }
+    }
+}
+
+/// The number at the start of VALUE (Ex. "10.0" of "10.0:RPS").
+fn leading_number(value: &str) -> Option<f64> {
+    let end = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    value[..end].parse().ok()
+}
+
+fn seconds(value: &str) -> Option<Duration> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+/// The delay of the Retry-After header in HEADERS, received at NOW.
+pub fn retry_after_header(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let value = headers.get(http::header::RETRY_AFTER)?.to_str().ok()?;
+    retry_after(value.trim(), now)
+}
+
+/// The delay of Retry-After, which is seconds or an HTTP date.
+fn retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    if let Some(delay) = seconds(value) {
+        return Some(delay);
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// The pace of the requests and the count of the throttled responses.
+struct State {
+    delay: Duration,
+    responses: usize,
+    throttled: usize,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    delay: Duration::ZERO,
+    responses: 0,
+    throttled: 0,
+});
+
+/// Print the hints in the response and adjust the delay before the next request.
+pub fn observe(status: StatusCode, headers: &HeaderMap) {
+    let hints = Hints::extract(status, headers, Utc::now());
+    print(&hints);
+    let mut state = STATE.lock().unwrap();
+    state.delay = hints.delay(state.delay);
+    state.responses += 1;
+    state.throttled += usize::from(hints.throttled);
+}
+
+fn print(hints: &Hints) {
+    if logging::is_json() {
+        let fields = json!({
+            "headers": logging::headers(hints.headers.iter().map(|(name, value)| (*name, value))),
+            "throttled": hints.throttled,
+        });
+        logging::event(Level::Info, "rate-limit", fields);
+        return;
+    }
+    let mut line = match hints.headers.is_empty() {
+        true => "-".to_string(),
+        false => hints
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    if hints.throttled {
+        line.push_str(" (throttled)");
+    }
+    eprintln!("Rate limit: {}", line);
+}
+
+/// The delay before the next request.
+pub fn delay() -> Duration {
+    STATE.lock().unwrap().delay
+}
+
+/// Wait for the delay before the next request, unless interrupted.
+pub async fn pace(verbose: bool) {
+    let delay = delay();
+    if delay.is_zero() {
+        return;
+    }
+    if verbose {
+        logging::note(format_args!(
+            "Waiting {}ms for the rate limit",
+            delay.as_millis()
+        ));
+    }
+    tokio::select! {
+        _ = tokio::time::sleep(delay) => {}
+        _ = interrupt::interrupted() => {}
+    }
+}
+
+/// Print how many of the responses were throttled, if there were some of them.
+pub fn print_total() {
+    let state = STATE.lock().unwrap();
+    if state.responses < 2 {
+        return;
+    }
+    if logging::is_json() {
+        let fields = json!({ "responses": state.responses, "throttled": state.throttled });
+        logging::event(Level::Info, "rate-limit-total", fields);
+        return;
+    }
+    eprintln!(
+        "Rate limit: {} of {} responses were throttled",
+        state.throttled, state.responses
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{DateTime, Utc};
+    use http::{HeaderMap, StatusCode};
+
+    use super::{Hints, INITIAL_DELAY};
+
+    fn extract(status: u16, headers: &[(&'static str, &'static str)]) -> Hints {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+            .collect::<HeaderMap>();
+        let now = DateTime::<Utc>::from_timestamp(1_445_412_480, 0).unwrap();
+        Hints::extract(StatusCode::from_u16(status).unwrap(), &headers, now)
+    }
+
+    #[test]
+    fn api_gateway() {
+        let hints = extract(
+            429,
+            &[
+                ("x-amzn-RateLimit-Limit", "10.0"),
+                ("x-amzn-ErrorType", "TooManyRequestsException"),
+            ],
+        );
+        assert!(hints.throttled);
+        assert_eq!(hints.requests_per_second, Some(10.0));
+        assert_eq!(hints.delay(Duration::ZERO), INITIAL_DELAY);
+        assert_eq!(hints.delay(Duration::from_secs(1)), Duration::from_secs(2));
+
+        let hints = extract(200, &[("x-amzn-RateLimit-Limit", "10.0")]);
+        assert!(!hints.throttled);
+        assert_eq!(
+            hints.delay(Duration::from_secs(1)),
+            Duration::from_millis(100)
+        );
+    }
+
+    #[test]
+    fn selling_partner_api() {
+        let hints = extract(200, &[("x-amzn-RateLimit-Limit", "0.5")]);
+        assert_eq!(hints.delay(Duration::ZERO), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn lambda() {
+        let hints = extract(
+            429,
+            &[
+                ("Retry-After", "3"),
+                (
+                    "x-amzn-ErrorType",
+                    "TooManyRequestsException:http://internal.amazon.com/coral/com.amazonaws.lambda/",
+                ),
+            ],
+        );
+        assert!(hints.throttled);
+        assert_eq!(hints.delay(Duration::from_secs(10)), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn dynamodb() {
+        // Throttling is a 400 with the error code.
+        let hints = extract(
+            400,
+            &[(
+                "x-amzn-ErrorType",
+                "ProvisionedThroughputExceededException:",
+            )],
+        );
+        assert!(hints.throttled);
+        let hints = extract(400, &[("x-amzn-ErrorType", "ValidationException:")]);
+        assert!(!hints.throttled);
+        assert_eq!(
+            hints.delay(Duration::from_secs(1)),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn query_protocol() {
+        let hints = extract(400, &[("x-amzn-query-error", "Throttling;Sender")]);
+        assert!(hints.throttled);
+    }
+
+    #[test]
+    fn window() {
+        let hints = extract(
+            200,
+            &[
+                ("x-ratelimit-limit", "100"),
+                ("x-ratelimit-remaining", "0"),
+                ("x-ratelimit-reset", "30"),
+            ],
+        );
+        assert!(!hints.throttled);
+        assert_eq!(hints.delay(Duration::ZERO), Duration::from_secs(30));
+        assert_eq!(hints.headers.len(), 3);
+    }
+
+    #[test]
+    fn retry_after_date() {
+        // 30 seconds after the time of the response
+        let hints = extract(503, &[("retry-after", "Wed, 21 Oct 2015 07:28:30 GMT")]);
+        assert_eq!(hints.retry_after, Some(Duration::from_secs(30)));
+        let hints = extract(503, &[("retry-after", "Wed, 21 Oct 2015 07:00:00 GMT")]);
+        assert_eq!(hints.retry_after, Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn no_hints() {
+        let hints = extract(200, &[("content-type", "application/json")]);
+        assert_eq!(hints, Hints::default());
+        assert_eq!(hints.delay(Duration::ZERO), Duration::ZERO);
+    }
+}
@@ -0,0 +1,34 @@
+//! Writing the diagnostics to a file instead of stderr (`--stderr`, `--log-file`)
+//!
+//! The file descriptor of stderr is replaced, so that everything written to
+//! stderr goes to the file, including the errors. Each line is written as it
+//! is printed, so the file can be followed with `tail -f`.
+
+use std::{fs::OpenOptions, path::Path};
+
+use anyhow::Context;
+
+/// Redirect stderr to the file at PATH, which is truncated unless APPEND.
+/// "-" redirects it to stdout, which is same as curl.
+pub fn stderr_to(path: &Path, append: bool) -> anyhow::Result<()> {
+    if path == Path::new("-") {
+        return dup_to_stderr(libc::STDOUT_FILENO);
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .with_context(|| format!("Unable to open {}", path.display()))?;
+    // The descriptor is duplicated, so the file can be closed.
+    dup_to_stderr(std::os::fd::AsRawFd::as_raw_fd(&file))
+}
+
+fn dup_to_stderr(fd: libc::c_int) -> anyhow::Result<()> {
+    // SAFETY: both descriptors are open, and dup2 does not touch any memory.
+    if unsafe { libc::dup2(fd, libc::STDERR_FILENO) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("Unable to redirect stderr");
+    }
+    Ok(())
+}
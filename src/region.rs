@@ -0,0 +1,131 @@
+//! Diagnostics for the region: where it was looked up, and the obvious typos
+
+use std::env;
+
+/// Whether REGION has the format of the region names (Ex. us-east-1, us-gov-west-1).
+pub fn is_valid(region: &str) -> bool {
+    let parts = region.split('-').collect::<Vec<_>>();
+    let Some((number, names)) = parts.split_last() else {
+        return false;
+    };
+    names.len() >= 2
+        && names[0].len() == 2
+        && names
+            .iter()
+            .all(|name| !name.is_empty() && name.bytes().all(|b| b.is_ascii_lowercase()))
+        && !number.is_empty()
+        && number.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// The likely region for an invalid one, Ex. us-east-1 for "us-east1" or "US-EAST-1".
+pub fn correct(region: &str) -> Option<String> {
+    let region = region.trim().to_ascii_lowercase().replace('_', "-");
+    if is_valid(&region) {
+        return Some(region);
+    }
+    // The hyphen before the number is missing.
+    let digits = region.len() - region.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (name, number) = region.split_at(region.len() - digits);
+    let corrected = format!("{}-{}", name, number);
+    is_valid(&corrected).then_some(corrected)
+}
+
+/// The warning for --region which does not look like a region.
+pub fn warning(region: &str) -> Option<String> {
+    if is_valid(region) {
+        return None;
+    }
+    let mut warning = format!("Warning: --region {} does not look like a region", region);
+    if let Some(corrected) = correct(region) {
+        warning.push_str(&format!(" (Did you mean {}?)", corrected));
+    }
+    Some(warning)
+}
+
+/// The region which appears in the host name of URL (Ex. sqs.ap-northeast-1.amazonaws.com).
+pub fn find_in_host(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    host.split('.')
+        .flat_map(|label| {
+            // The region may be joined with other words by hyphens (Ex. s3-us-west-2).
+            let parts = label.split('-').collect::<Vec<_>>();
+            (0..parts.len()).flat_map(move |start| {
+                let parts = parts.clone();
+                (start + 3..=parts.len()).map(move |end| parts[start..end].join("-"))
+            })
+        })
+        .find(|candidate| is_valid(candidate))
+}
+
+/// The message when the region can not be decided, which lists where it was
+/// looked up and how to give it.
+pub fn missing_message(profile: Option<&str>, url: Option<&str>) -> String {
+    let profile = profile
+        .map(str::to_string)
+        .or_else(|| env::var("AWS_PROFILE").ok())
+        .unwrap_or_else(|| "default".to_string());
+    let config_file = env::var("AWS_CONFIG_FILE").unwrap_or_else(|_| "~/.aws/config".to_string());
+    let mut message = format!(
+        "Unable to decide region. It was looked up in:\n  \
+         - --region\n  \
+         - AWS_REGION, AWS_DEFAULT_REGION\n  \
+         - region of the profile \"{}\" in {}\n",
+        profile, config_file
+    );
+    match url.and_then(find_in_host) {
+        Some(region) => message.push_str(&format!(
+            "The URL contains the region {0}. Try --region {0}",
+            region
+        )),
+        None => message.push_str("Give --region (Ex. --region us-east-1) or set AWS_REGION"),
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{correct, find_in_host, is_valid, warning};
+
+    #[test]
+    fn valid_regions() {
+        for region in ["us-east-1", "ap-northeast-1", "us-gov-west-1", "cn-north-1"] {
+            assert!(is_valid(region), "{}", region);
+        }
+        for region in [
+            "us-east1",
+            "US-EAST-1",
+            "useast-1",
+            "us-east-",
+            "global",
+            "",
+        ] {
+            assert!(!is_valid(region), "{}", region);
+        }
+    }
+
+    #[test]
+    fn correct_typos() {
+        assert_eq!(correct("us-east1").as_deref(), Some("us-east-1"));
+        assert_eq!(correct("US_WEST_2").as_deref(), Some("us-west-2"));
+        assert_eq!(correct("tokyo"), None);
+        assert_eq!(
+            warning("us-east1").unwrap(),
+            "Warning: --region us-east1 does not look like a region (Did you mean us-east-1?)"
+        );
+        assert_eq!(warning("us-east-1"), None);
+    }
+
+    #[test]
+    fn region_in_host() {
+        assert_eq!(
+            find_in_host("https://sqs.ap-northeast-1.amazonaws.com/").as_deref(),
+            Some("ap-northeast-1")
+        );
+        assert_eq!(
+            find_in_host("https://bucket.s3-us-west-2.amazonaws.com/key").as_deref(),
+            Some("us-west-2")
+        );
+        assert_eq!(find_in_host("https://example.com/"), None);
+    }
+}
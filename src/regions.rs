@@ -0,0 +1,274 @@
+//! The same request to several regions (`--regions`)
+//!
+//! The region in the host of the URL (Ex. sqs.us-east-1.amazonaws.com) is
+//! replaced for each region, or "{region}" in --endpoint-url, and each request
+//! is signed for its region. They are sent in the order of --regions, and the
+//! output of each is preceded by a line of its region and followed by a table
+//! of the statuses and the times.
+
+use std::{process::ExitCode, sync::Mutex, time::Duration};
+
+use anyhow::bail;
+use http::StatusCode;
+
+use crate::{client, interrupt, load_config, region, report_error, run as run_one, service, Args};
+
+/// The placeholder of the region in --endpoint-url.
+const PLACEHOLDER: &str = "{region}";
+
+/// The status and the time of the last request, recorded while it is sent.
+static LAST: Mutex<Option<(Option<StatusCode>, Duration)>> = Mutex::new(None);
+
+/// Record the STATUS of the request which took ELAPSED.
+pub fn record(status: Option<StatusCode>, elapsed: Duration) {
+    *LAST.lock().unwrap() = Some((status, elapsed));
+}
+
+/// URL with the region in its host replaced with REGION, or None if the host is
+/// not the one of AWS with a region. The region nearest to the domain is the
+/// one replaced (Ex. the second one of logs-us-east-1.s3.us-east-1.amazonaws.com).
+fn replace_in_host(url: &str, region: &str) -> Option<String> {
+    let mut url = reqwest::Url::parse(url).ok()?;
+    let host = url.host_str()?.to_string();
+    let rest = service::DOMAINS
+        .iter()
+        .find_map(|domain| host.strip_suffix(domain))?;
+    let mut labels = rest.split('.').map(str::to_string).collect::<Vec<_>>();
+    let label = labels
+        .iter_mut()
+        .rev()
+        .find(|label| prefix(label).is_some())?;
+    // The region may be joined with the service by a hyphen (Ex. s3-us-west-2).
+    *label = format!("{}{}", prefix(label)?, region);
+    let replaced = format!("{}{}", labels.join("."), &host[rest.len()..]);
+    url.set_host(Some(&replaced)).ok()?;
+    Some(url.to_string())
+}
+
+/// The part of LABEL before the region which ends it, if any.
+fn prefix(label: &str) -> Option<&str> {
+    if region::is_valid(label) {
+        return Some("");
+    }
+    label
+        .match_indices('-')
+        .map(|(i, _)| i + 1)
+        .find(|i| region::is_valid(&label[*i..]))
+        .map(|i| &label[..i])
+}
+
+/// The requests of ARGS for each region of --regions.
+fn expand(args: &Args) -> anyhow::Result<Vec<Args>> {
+    let url = args.url.as_deref().unwrap_or_default();
+    let template = args
+        .endpoint_url
+        .as_deref()
+        .filter(|endpoint| endpoint.contains(PLACEHOLDER));
+    let mut requests = Vec::new();
+    for region in &args.regions {
+        if !region::is_valid(region) {
+            bail!(
+                "--regions has {}, which does not look like a region",
+                region
+            );
+        }
+        let mut request = args.clone();
+        request.region = Some(region.clone());
+        match template {
+            Some(template) => request.endpoint_url = Some(template.replace(PLACEHOLDER, region)),
+            None => match replace_in_host(url, region) {
+                Some(replaced) => request.url = Some(replaced),
+                None => bail!(
+                    "--regions needs a URL with a region in the host of AWS (Ex. https://sqs.us-east-1.amazonaws.com/), or --endpoint-url with {}: {}",
+                    PLACEHOLDER,
+                    url
+                ),
+            },
+        }
+        requests.push(request);
+    }
+    Ok(requests)
+}
+
+/// The result of the request to a region.
+struct Row {
+    region: String,
+    status: Option<StatusCode>,
+    elapsed: Option<Duration>,
+}
+
+/// The table of ROWS, with the columns aligned.
+fn table(rows: &[Row]) -> String {
+    let width = rows
+        .iter()
+        .map(|row| row.region.len())
+        .chain(["REGION".len()])
+        .max()
+        .unwrap_or_default();
+    let mut lines = vec![format!("{:width$}  STATUS  TIME", "REGION")];
+    for row in rows {
+        let status = match row.status {
+            Some(status) => status.as_u16().to_string(),
+            None => "error".to_string(),
+        };
+        let elapsed = match row.elapsed {
+            Some(elapsed) => format!("{}ms", elapsed.as_millis()),
+            None => "-".to_string(),
+        };
+        lines.push(format!("{:width$}  {:6}  {}", row.region, status, elapsed));
+    }
+    lines.join("\n")
+}
+
+/// Send the request of ARGS to each region of --regions. The exit code is the
+/// one of the first failed request, or 0 if any succeeded with --any-success.
+pub async fn run(args: Args) -> anyhow::Result<ExitCode> {
+    let requests = expand(&args)?;
+    let config = load_config(&args).await?;
+    let client = client::build(&args)?;
+    let mut rows = Vec::new();
+    let mut codes = Vec::new();
+    for request in requests {
+        let region = request.region.clone().unwrap_or_default();
+        let mode = request.exit_code_mode;
+        println!("== {} ==", region);
+        *LAST.lock().unwrap() = None;
+        let code = run_one(request, Some(&config), &client)
+            .await
+            .unwrap_or_else(|e| report_error(&e, mode));
+        let last = LAST.lock().unwrap().take();
+        rows.push(Row {
+            region,
+            status: last.and_then(|(status, _)| status),
+            elapsed: last.map(|(_, elapsed)| elapsed),
+        });
+        codes.push(code);
+        if interrupt::is_interrupted() {
+            break;
+        }
+    }
+    if !args.dry_run {
+        println!("{}", table(&rows));
+    }
+    if args.any_success && codes.contains(&ExitCode::SUCCESS) {
+        return Ok(ExitCode::SUCCESS);
+    }
+    Ok(codes
+        .into_iter()
+        .find(|code| *code != ExitCode::SUCCESS)
+        .unwrap_or(ExitCode::SUCCESS))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use clap::Parser;
+    use http::StatusCode;
+
+    use super::{expand, replace_in_host, table, Row};
+    use crate::Args;
+
+    #[test]
+    fn replace_region() {
+        let replace = |url| replace_in_host(url, "eu-west-1");
+        assert_eq!(
+            replace("https://sqs.us-east-1.amazonaws.com/123/queue").as_deref(),
+            Some("https://sqs.eu-west-1.amazonaws.com/123/queue")
+        );
+        assert_eq!(
+            replace("https://abc.execute-api.us-east-1.amazonaws.com/prod?q=1").as_deref(),
+            Some("https://abc.execute-api.eu-west-1.amazonaws.com/prod?q=1")
+        );
+        assert_eq!(
+            replace("https://search-d.us-east-1.es.amazonaws.com/_search").as_deref(),
+            Some("https://search-d.eu-west-1.es.amazonaws.com/_search")
+        );
+        assert_eq!(
+            replace("https://logs-us-east-1.s3.us-east-1.amazonaws.com/key").as_deref(),
+            Some("https://logs-us-east-1.s3.eu-west-1.amazonaws.com/key")
+        );
+        assert_eq!(
+            replace("https://bucket.s3-us-west-2.amazonaws.com/key").as_deref(),
+            Some("https://bucket.s3-eu-west-1.amazonaws.com/key")
+        );
+        assert_eq!(
+            replace("https://abc.lambda-url.us-east-1.on.aws/").as_deref(),
+            Some("https://abc.lambda-url.eu-west-1.on.aws/")
+        );
+        assert_eq!(replace("https://iam.amazonaws.com/"), None);
+        assert_eq!(replace("https://api.us-east-1.example.com/"), None);
+    }
+
+    #[test]
+    fn expand_requests() {
+        let args = Args::parse_from([
+            "awscurl",
+            "https://sqs.us-east-1.amazonaws.com/",
+            "--regions",
+            "us-west-2,ap-southeast-2",
+        ]);
+        let requests = expand(&args).unwrap();
+        assert_eq!(requests[0].region.as_deref(), Some("us-west-2"));
+        assert_eq!(
+            requests[1].url.as_deref(),
+            Some("https://sqs.ap-southeast-2.amazonaws.com/")
+        );
+
+        let args = Args::parse_from([
+            "awscurl",
+            "https://api.example.com/items",
+            "--regions",
+            "us-west-2",
+            "--endpoint-url",
+            "https://api-{region}.example.com",
+        ]);
+        let requests = expand(&args).unwrap();
+        assert_eq!(
+            requests[0].endpoint_url.as_deref(),
+            Some("https://api-us-west-2.example.com")
+        );
+        assert_eq!(
+            requests[0].url.as_deref(),
+            Some("https://api.example.com/items")
+        );
+
+        let args = Args::parse_from([
+            "awscurl",
+            "https://api.example.com/items",
+            "--regions",
+            "us-west-2",
+        ]);
+        assert!(expand(&args).is_err());
+        let args = Args::parse_from([
+            "awscurl",
+            "https://sqs.us-east-1.amazonaws.com/",
+            "--regions",
+            "us-west2",
+        ]);
+        assert!(expand(&args).is_err());
+    }
+
+    #[test]
+    fn results() {
+        let rows = [
+            Row {
+                region: "us-east-1".to_string(),
+                status: Some(StatusCode::OK),
+                elapsed: Some(Duration::from_millis(35)),
+            },
+            Row {
+                region: "ap-southeast-2".to_string(),
+                status: None,
+                elapsed: None,
+            },
+        ];
+        assert_eq!(
+            table(&rows),
+            "\
+REGION          STATUS  TIME
+us-east-1       200     35ms
+ap-southeast-2  error   -"
+        );
+    }
+}
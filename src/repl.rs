@@ -0,0 +1,291 @@
+//! Interactive mode (`--interactive`)
+//!
+//! The request is kept as an [`Args`] which is edited by the commands typed in
+//! the session. Credentials and the HTTP client are created once and reused for
+//! every request sent from the session.
+
+use std::{
+    fs,
+    process::ExitCode,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context};
+use aws_credential_types::Credentials;
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use crate::{
+    config::config_dir, confirm, parse_method, print_request_verbose, redact, send_request,
+    template, Args, AwsCurlParam,
+};
+
+const HELP: &str = "\
+show                  Show the current request
+url [URL]             Show or set the URL
+method [METHOD]       Show or set the HTTP method
+header [NAME: VALUE]  Show the headers or set a header
+unheader NAME         Remove a header
+body [DATA]           Show or set the request body
+service [NAME]        Show or set the AWS service name
+region [NAME]         Show or set the AWS region
+unset FIELD           Reset the method, body, service or region
+send                  Sign and send the request
+dry-run               Print the signed request without sending it
+presign [SECONDS]     Print a presigned URL (Default: 900 seconds)
+save NAME             Save the current request as a template
+help                  Show this help
+quit                  Exit";
+
+const DEFAULT_PRESIGN_EXPIRES_IN: u64 = 900;
+
+// Credentials which expire within this margin are refreshed before signing.
+const CREDENTIALS_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+enum Flow {
+    Continue,
+    Quit,
+}
+
+pub async fn run(mut param: AwsCurlParam, client: &reqwest::Client) -> anyhow::Result<ExitCode> {
+    let mut credentials = CredentialsCache::default();
+    let mut editor = DefaultEditor::new()?;
+    let history_path = config_dir().map(|dir| dir.join("repl_history"));
+    if let Some(path) = &history_path {
+        // The history file does not exist on the first run.
+        let _ = editor.load_history(path);
+    }
+
+    loop {
+        let line = match editor.readline("awscurl> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line)?;
+        match execute(&mut param, client, &mut credentials, line).await {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Quit) => break,
+            Err(e) => eprintln!("{}", redact::scrub(&format!("{:?}", e))),
+        }
+    }
+
+    if let Some(path) = &history_path {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        editor.save_history(path)?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn execute(
+    param: &mut AwsCurlParam,
+    client: &reqwest::Client,
+    credentials: &mut CredentialsCache,
+    line: &str,
+) -> anyhow::Result<Flow> {
+    let (command, value) = match line.split_once(char::is_whitespace) {
+        Some((command, value)) => (command, Some(value.trim()).filter(|v| !v.is_empty())),
+        None => (line, None),
+    };
+    match command {
+        "send" => {
+            let credentials = credentials.get(param).await?;
+            let req = param.sendable(param.build_signed_request(&credentials)?)?;
+            if param.args.verbose {
+                print_request_verbose(&req, &param.args);
+            }
+            if confirm::required(&param.args, param.method()) {
+                confirm::ask(
+                    param.method(),
+                    req.url().as_str(),
+                    None,
+                    Some(&confirm::principal(&credentials, &param.args)),
+                )?;
+            }
+            send_request(param, client, req, None).await?;
+        }
+        "dry-run" => {
+            let credentials = credentials.get(param).await?;
+            let req = param.sendable(param.build_signed_request(&credentials)?)?;
+            print_request_verbose(&req, &param.args);
+        }
+        "presign" => {
+            let expires_in = match value {
+                Some(raw) => raw.parse().context("Invalid expiration seconds")?,
+                None => DEFAULT_PRESIGN_EXPIRES_IN,
+            };
+            let credentials = credentials.get(param).await?;
+            let url = param.presigned_url(&credentials, Duration::from_secs(expires_in))?;
+            println!("{}", url);
+        }
+        "save" => {
+            let name = value.context("Template name is required")?;
+            let path = template::save(name, &template::Template::from_args(&param.args))?;
+            println!("Saved to {}", path.display());
+        }
+        "help" => println!("{}", HELP),
+        "quit" | "exit" => return Ok(Flow::Quit),
+        _ => {
+            if let Some(output) = apply(&mut param.args, command, value)? {
+                println!("{}", output);
+            }
+        }
+    }
+    Ok(Flow::Continue)
+}
+
+/// Apply a command which shows or edits the request.
+///
+/// Returns the text to print, if any.
+fn apply(args: &mut Args, command: &str, value: Option<&str>) -> anyhow::Result<Option<String>> {
+    let field = match command {
+        "show" => return Ok(Some(show(args))),
+        "url" => &mut args.url,
+        "method" => {
+            match value {
+                Some(value) => args.method = Some(parse_method(value).map_err(anyhow::Error::msg)?),
+                None => return Ok(Some(args.method.clone().unwrap_or_default())),
+            }
+            return Ok(None);
+        }
+        "body" => {
+            match value {
+                Some(value) => args.data = vec![value.to_string()],
+                None => return Ok(Some(args.body().unwrap_or_default())),
+            }
+            return Ok(None);
+        }
+        "service" => &mut args.service,
+        "region" => &mut args.region,
+        "header" => {
+            let Some(header) = value else {
+                return Ok(Some(args.header.join("\n")));
+            };
+            let Some((name, _)) = header.split_once(':') else {
+                bail!("Invalid header: {}", header);
+            };
+            remove_header(args, name.trim());
+            args.header.push(header.to_string());
+            return Ok(None);
+        }
+        "unheader" => {
+            remove_header(args, value.context("Header name is required")?);
+            return Ok(None);
+        }
+        "unset" => {
+            match value {
+                Some("method") => args.method = None,
+                Some("body") => args.data.clear(),
+                Some("service") => args.service = None,
+                Some("region") => args.region = None,
+                Some(other) => bail!("Unable to unset {}", other),
+                None => bail!("Field name is required"),
+            }
+            return Ok(None);
+        }
+        _ => bail!(
+            "Unknown command: {} (type \"help\" to list commands)",
+            command
+        ),
+    };
+    match value {
+        Some(value) => {
+            *field = Some(value.to_string());
+            Ok(None)
+        }
+        None => Ok(Some(field.clone().unwrap_or_default())),
+    }
+}
+
+fn remove_header(args: &mut Args, name: &str) {
+    args.header.retain(|header| {
+        let key = header
+            .split_once(':')
+            .map_or(header.as_str(), |(key, _)| key);
+        !key.trim().eq_ignore_ascii_case(name)
+    });
+}
+
+fn show(args: &Args) -> String {
+    let fields = [
+        ("url", &args.url),
+        ("method", &args.method),
+        ("service", &args.service),
+        ("region", &args.region),
+        ("body", &args.body()),
+    ];
+    let mut lines = fields
+        .iter()
+        .map(|(name, value)| format!("{}: {}", name, value.as_deref().unwrap_or("")))
+        .collect::<Vec<_>>();
+    lines.extend(
+        args.header
+            .iter()
+            .map(|header| format!("header: {}", header)),
+    );
+    lines.join("\n")
+}
+
+/// Keeps the resolved credentials until they are about to expire.
+#[derive(Default)]
+struct CredentialsCache {
+    credentials: Option<Credentials>,
+}
+
+impl CredentialsCache {
+    async fn get(&mut self, param: &AwsCurlParam) -> anyhow::Result<Credentials> {
+        if let Some(credentials) = &self.credentials {
+            let expiring = credentials
+                .expiry()
+                .is_some_and(|expiry| expiry <= SystemTime::now() + CREDENTIALS_REFRESH_MARGIN);
+            if !expiring {
+                return Ok(credentials.clone());
+            }
+        }
+        let credentials = param.credentials().await?;
+        self.credentials = Some(credentials.clone());
+        Ok(credentials)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::apply;
+    use crate::Args;
+
+    #[test]
+    fn edit_request() {
+        let mut args = Args::parse_from(["awscurl", "--interactive"]);
+        apply(&mut args, "url", Some("https://example.com")).unwrap();
+        apply(&mut args, "method", Some("put")).unwrap();
+        assert_eq!(args.method.as_deref(), Some("PUT"));
+        assert!(apply(&mut args, "method", Some("GET /")).is_err());
+        apply(&mut args, "header", Some("content-type: text/plain")).unwrap();
+        apply(&mut args, "header", Some("Content-Type: application/json")).unwrap();
+        apply(&mut args, "body", Some("{}")).unwrap();
+        apply(&mut args, "unset", Some("method")).unwrap();
+        assert_eq!(
+            apply(&mut args, "show", None).unwrap().unwrap(),
+            "url: https://example.com\n\
+             method: \n\
+             service: \n\
+             region: \n\
+             body: {}\n\
+             header: Content-Type: application/json"
+        );
+    }
+
+    #[test]
+    fn unknown_command() {
+        let mut args = Args::parse_from(["awscurl", "--interactive"]);
+        assert!(apply(&mut args, "sned", None).is_err());
+    }
+}
@@ -0,0 +1,369 @@
+//! Requests written in HTTP message files (`--request-file`)
+//!
+//! The format is the one of the .http files of the REST clients: the request
+//! line ("POST /path HTTP/1.1", where the version is optional), the headers, a
+//! blank line and the body. "###" separates the requests of a file, and the
+//! text after it, or "# @name NAME", names the request. The lines starting with
+//! "#" or "//" before the request line are comments. The errors are reported
+//! with the line numbers, as "FILE:LINE: message".
+
+use anyhow::{anyhow, bail};
+
+use crate::{data, variable::Variables, Args};
+
+/// A request in the file, with the line numbers of its parts.
+#[derive(Debug, PartialEq)]
+struct Request {
+    name: Option<String>,
+    line: usize,
+    method: String,
+    target: String,
+    headers: Vec<(usize, String)>,
+    body: Vec<(usize, String)>,
+}
+
+/// Parse RAW read from PATH into its requests.
+fn parse(raw: &str, path: &str) -> anyhow::Result<Vec<Request>> {
+    let mut requests = Vec::new();
+    let mut lines = raw.lines().enumerate().map(|(i, line)| (i + 1, line));
+    let mut name = None;
+    let mut request: Option<Request> = None;
+    let mut in_body = false;
+    for (number, line) in &mut lines {
+        if let Some(rest) = line.strip_prefix("###") {
+            requests.extend(request.take());
+            in_body = false;
+            name = Some(rest.trim().to_string()).filter(|name| !name.is_empty());
+            continue;
+        }
+        let Some(current) = &mut request else {
+            let trimmed = line.trim();
+            if let Some(comment) = trimmed.strip_prefix('#').or(trimmed.strip_prefix("//")) {
+                if let Some(value) = comment.trim().strip_prefix("@name") {
+                    name = Some(value.trim().to_string()).filter(|name| !name.is_empty());
+                }
+                continue;
+            }
+            if trimmed.is_empty() {
+                continue;
+            }
+            let (method, target) = parse_request_line(trimmed)
+                .ok_or_else(|| anyhow!("{}:{}: Invalid request line: {}", path, number, line))?;
+            request = Some(Request {
+                name: name.take(),
+                line: number,
+                method,
+                target,
+                headers: Vec::new(),
+                body: Vec::new(),
+            });
+            continue;
+        };
+        if in_body {
+            current.body.push((number, line.to_string()));
+        } else if line.trim().is_empty() {
+            in_body = true;
+        } else if line.contains(':') {
+            current.headers.push((number, line.to_string()));
+        } else {
+            bail!(
+                "{}:{}: Expected a header NAME: VALUE: {}",
+                path,
+                number,
+                line
+            );
+        }
+    }
+    requests.extend(request);
+    for request in &mut requests {
+        while request
+            .body
+            .last()
+            .is_some_and(|(_, line)| line.trim().is_empty())
+        {
+            request.body.pop();
+        }
+    }
+    Ok(requests)
+}
+
+/// The method and the target of LINE, which is "METHOD TARGET [HTTP/x]" or only
+/// the target of GET.
+fn parse_request_line(line: &str) -> Option<(String, String)> {
+    let parts = line.split_whitespace().collect::<Vec<_>>();
+    match parts[..] {
+        [target] => Some(("GET".to_string(), target.to_string())),
+        [method, target] | [method, target, _] => {
+            if parts
+                .get(2)
+                .is_some_and(|version| !version.starts_with("HTTP/"))
+            {
+                return None;
+            }
+            if !method.chars().all(|c| c.is_ascii_alphabetic()) {
+                return None;
+            }
+            Some((method.to_ascii_uppercase(), target.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// The URL of TARGET on BASE, which can be a host only. The path of an absolute
+/// TARGET is appended to BASE, so that another host can be given on the command
+/// line.
+fn join(base: &str, target: &str) -> String {
+    let base = match base.contains("://") {
+        true => base.to_string(),
+        false => format!("https://{}", base),
+    };
+    let path = match target.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |i| &rest[i..]),
+        None => target,
+    };
+    format!(
+        "{}/{}",
+        base.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+/// Replace ARGS with the request of --request-file and --request-name. -X, -H
+/// and -d on the command line override the ones in the file.
+pub fn install(args: &mut Args, variables: &Variables) -> anyhow::Result<()> {
+    let Some(path) = &args.request_file else {
+        return Ok(());
+    };
+    let mut requests = parse(&data::read_file(path)?, path)?;
+    let names = || {
+        requests
+            .iter()
+            .map(|request| request.name.as_deref().unwrap_or("(unnamed)"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let index = match (&args.request_name, requests.len()) {
+        (_, 0) => bail!("No request in {}", path),
+        (Some(name), _) => requests
+            .iter()
+            .position(|request| request.name.as_ref() == Some(name))
+            .ok_or_else(|| anyhow!("No request named {} in {} ({})", name, path, names()))?,
+        (None, 1) => 0,
+        (None, n) => bail!(
+            "{} has {} requests. Select one with --request-name ({})",
+            path,
+            n,
+            names()
+        ),
+    };
+    let request = requests.swap_remove(index);
+
+    let expand = |line: usize, text: &str| {
+        variables
+            .expand(text)
+            .map_err(|e| anyhow!("{}:{}: {}", path, line, e))
+    };
+    let target = expand(request.line, &request.target)?;
+    let mut host = None;
+    let mut headers = Vec::new();
+    for (line, header) in &request.headers {
+        let header = expand(*line, header)?;
+        // The Host is the one of the URL.
+        match header.split_once(':') {
+            Some((name, value)) if name.trim().eq_ignore_ascii_case("host") => {
+                host = Some(value.trim().to_string());
+            }
+            _ => headers.push(header),
+        }
+    }
+    let url = match (args.url.as_deref(), host) {
+        (Some(base), _) => join(base, &target),
+        (None, _) if target.contains("://") => target,
+        (None, Some(host)) => join(&host, &target),
+        (None, None) => bail!(
+            "{}:{}: The target has no host. Give the base URL as `awscurl --request-file {} URL`",
+            path,
+            request.line,
+            path
+        ),
+    };
+    args.url = Some(url);
+    args.method = args.method.take().or(Some(request.method));
+    args.header.splice(0..0, headers);
+    if args.data.is_empty() && !request.body.is_empty() {
+        let body = request
+            .body
+            .iter()
+            .map(|(line, text)| expand(*line, text))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        args.data.push(body.join("\n"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use clap::Parser;
+
+    use super::{install, join, parse};
+    use crate::{variable::Variables, Args};
+
+    const FILE: &str = "\
+# Requests of the API
+### list
+GET /items?limit=10 HTTP/1.1
+Accept: application/json
+
+###
+// @name create
+post https://api.example.com/items
+Host: api.example.com
+Content-Type: application/json
+
+{
+  \"name\": \"{{name}}\"
+}
+
+";
+
+    #[test]
+    fn parse_file() {
+        let requests = parse(FILE, "req.http").unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].name.as_deref(), Some("list"));
+        assert_eq!(requests[0].line, 3);
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].target, "/items?limit=10");
+        assert_eq!(
+            requests[0].headers,
+            [(4, "Accept: application/json".to_string())]
+        );
+        assert!(requests[0].body.is_empty());
+        assert_eq!(requests[1].name.as_deref(), Some("create"));
+        assert_eq!(requests[1].method, "POST");
+        assert_eq!(requests[1].headers.len(), 2);
+        assert_eq!(
+            requests[1]
+                .body
+                .iter()
+                .map(|(line, _)| *line)
+                .collect::<Vec<_>>(),
+            [12, 13, 14]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            parse("GET /\nAccept application/json\n", "req.http")
+                .unwrap_err()
+                .to_string(),
+            "req.http:2: Expected a header NAME: VALUE: Accept application/json"
+        );
+        assert_eq!(
+            parse("# comment\n\nGET / HTTP/1.1 extra\n", "req.http")
+                .unwrap_err()
+                .to_string(),
+            "req.http:3: Invalid request line: GET / HTTP/1.1 extra"
+        );
+        assert!(parse("GET / SPDY/3\n", "req.http").is_err());
+        assert!(parse("# only comments\n", "req.http").unwrap().is_empty());
+    }
+
+    #[test]
+    fn join_base() {
+        assert_eq!(
+            join("api.example.com", "/items"),
+            "https://api.example.com/items"
+        );
+        assert_eq!(
+            join("http://localhost:8080/stage/", "/items?a=1"),
+            "http://localhost:8080/stage/items?a=1"
+        );
+        assert_eq!(
+            join("http://localhost", "https://api.example.com/items"),
+            "http://localhost/items"
+        );
+        assert_eq!(
+            join("http://localhost", "https://api.example.com"),
+            "http://localhost/"
+        );
+    }
+
+    fn installed(options: &[&str], variables: &Variables) -> anyhow::Result<Args> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(FILE.as_bytes()).unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        let mut args = Args::parse_from(["awscurl", "--request-file", &path].iter().chain(options));
+        install(&mut args, variables)
+            .map_err(|e| anyhow::anyhow!(e.to_string().replace(&path, "req.http")))?;
+        Ok(args)
+    }
+
+    #[test]
+    fn select_and_override() {
+        let mut variables = Variables::default();
+        variables.define("name=alice").unwrap();
+        let args = installed(&["--request-name", "create"], &variables).unwrap();
+        assert_eq!(args.url.as_deref(), Some("https://api.example.com/items"));
+        assert_eq!(args.method.as_deref(), Some("POST"));
+        assert_eq!(args.header, ["Content-Type: application/json"]);
+        assert_eq!(args.data, ["{\n  \"name\": \"alice\"\n}"]);
+
+        let args = installed(
+            &[
+                "http://localhost:8080",
+                "--request-name",
+                "list",
+                "-X",
+                "HEAD",
+                "-H",
+                "Accept: text/plain",
+                "-d",
+                "x",
+            ],
+            &variables,
+        )
+        .unwrap();
+        assert_eq!(
+            args.url.as_deref(),
+            Some("http://localhost:8080/items?limit=10")
+        );
+        assert_eq!(args.method.as_deref(), Some("HEAD"));
+        assert_eq!(
+            args.header,
+            ["Accept: application/json", "Accept: text/plain"]
+        );
+        assert_eq!(args.data, ["x"]);
+    }
+
+    #[test]
+    fn install_errors() {
+        let variables = Variables::default();
+        assert_eq!(
+            installed(&[], &variables).err().unwrap().to_string(),
+            "req.http has 2 requests. Select one with --request-name (list, create)"
+        );
+        assert_eq!(
+            installed(&["--request-name", "delete"], &variables)
+                .err()
+                .unwrap()
+                .to_string(),
+            "No request named delete in req.http (list, create)"
+        );
+        assert_eq!(
+            installed(&["--request-name", "create"], &variables)
+                .err()
+                .unwrap()
+                .to_string(),
+            "req.http:13: Undefined variable: name"
+        );
+        assert!(installed(&["--request-name", "list"], &variables)
+            .err()
+            .unwrap()
+            .to_string()
+            .starts_with("req.http:3: The target has no host."));
+    }
+}
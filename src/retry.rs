@@ -0,0 +1,483 @@
+//! Retrying the failed requests (`--retry`)
+//!
+//! By default, only the transient failures are retried: the timeouts, the reset
+//! connections and the 408, 429, 500, 502, 503 and 504 responses.
+//! `--retry-connrefused` adds the refused connections, `--retry-dns` the hosts
+//! which can not be resolved yet, and `--retry-all-errors` retries any error and
+//! any 4xx or 5xx response.
+//!
+//! Each retry is signed again, so that the signature is not too old after long
+//! delays. The delay after 429 and 503 is the one of Retry-After if given.
+//! `--max-time` bounds each attempt, and `--total-deadline` bounds all of them
+//! with the delays between them.
+
+use std::{
+    fmt, io,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use http::{HeaderMap, StatusCode};
+use serde_json::json;
+
+use crate::{
+    dns, execute, interrupt,
+    logging::{self, Level},
+    poll, rate_limit, refresh, AwsCurlParam,
+};
+
+/// The delay before the first retry, which is doubled for each retry like curl.
+const INITIAL_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(600);
+
+/// The statuses retried by default, which are same as curl.
+const TRANSIENT_STATUSES: [u16; 6] = [408, 429, 500, 502, 503, 504];
+
+/// The error when `--total-deadline` is exceeded, or would be by the next delay.
+#[derive(Debug)]
+pub struct DeadlineExceeded {
+    attempts: u32,
+    deadline: Duration,
+    /// The delay before the next retry, if it is the one which would exceed it
+    delay: Option<Duration>,
+}
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let attempts = match self.attempts {
+            1 => "1 attempt".to_string(),
+            n => format!("{} attempts", n),
+        };
+        match self.delay {
+            Some(delay) => write!(
+                f,
+                "Gave up after {}: the retry in {}s would exceed --total-deadline {}s",
+                attempts,
+                delay.as_secs_f64(),
+                self.deadline.as_secs_f64()
+            ),
+            None => write!(
+                f,
+                "Gave up after {}: --total-deadline {}s was exceeded",
+                attempts,
+                self.deadline.as_secs_f64()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+/// The error of the response headers which do not arrive within
+/// `--max-time-to-first-byte`.
+#[derive(Debug)]
+pub struct FirstByteTimeout {
+    pub limit: Duration,
+}
+
+impl fmt::Display for FirstByteTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Timed out waiting for the response headers: --max-time-to-first-byte {}s was exceeded",
+            self.limit.as_secs_f64()
+        )
+    }
+}
+
+impl std::error::Error for FirstByteTimeout {}
+
+/// Why the request failed.
+#[derive(Debug, PartialEq)]
+pub enum Failure {
+    Status(StatusCode),
+    ConnectionRefused,
+    ConnectionReset,
+    Timeout,
+    Dns,
+    Tls,
+    Interrupted,
+    Other,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Status(status) => write!(f, "HTTP {}", status),
+            Failure::ConnectionRefused => write!(f, "connection refused"),
+            Failure::ConnectionReset => write!(f, "connection reset"),
+            Failure::Timeout => write!(f, "timeout"),
+            Failure::Dns => write!(f, "DNS error"),
+            Failure::Tls => write!(f, "TLS error"),
+            Failure::Interrupted => write!(f, "interrupted"),
+            Failure::Other => write!(f, "other error"),
+        }
+    }
+}
+
+impl Failure {
+    /// Classify the error of sending the request by its causes.
+    pub fn classify(e: &anyhow::Error) -> Self {
+        for cause in e.chain() {
+            if cause.is::<interrupt::Interrupted>() {
+                return Failure::Interrupted;
+            }
+            if cause.is::<DeadlineExceeded>() || cause.is::<FirstByteTimeout>() {
+                return Failure::Timeout;
+            }
+            if cause.is::<dns::ResolveError>() || cause.to_string().starts_with("dns error") {
+                return Failure::Dns;
+            }
+            if cause.is::<rustls::Error>() {
+                return Failure::Tls;
+            }
+            if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+                if e.is_timeout() {
+                    return Failure::Timeout;
+                }
+            }
+            if let Some(e) = cause.downcast_ref::<io::Error>() {
+                match e.kind() {
+                    io::ErrorKind::ConnectionRefused => return Failure::ConnectionRefused,
+                    io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof => return Failure::ConnectionReset,
+                    io::ErrorKind::TimedOut => return Failure::Timeout,
+                    _ => {}
+                }
+                // rustls reports its errors wrapped in io::Error.
+                if e.get_ref().is_some_and(|inner| inner.is::<rustls::Error>()) {
+                    return Failure::Tls;
+                }
+            }
+            // hyper reports the connection closed before the response without io::Error.
+            if let Some(e) = cause.downcast_ref::<hyper::Error>() {
+                if e.is_incomplete_message() || e.is_canceled() {
+                    return Failure::ConnectionReset;
+                }
+            }
+        }
+        Failure::Other
+    }
+
+    /// Whether the request is retried for this failure.
+    pub fn is_retryable(&self, connrefused: bool, dns: bool, all_errors: bool) -> bool {
+        match self {
+            Failure::Interrupted => false,
+            Failure::Status(status) if all_errors => {
+                status.is_client_error() || status.is_server_error()
+            }
+            Failure::Status(status) => TRANSIENT_STATUSES.contains(&status.as_u16()),
+            _ if all_errors => true,
+            Failure::ConnectionRefused => connrefused,
+            Failure::Dns => dns,
+            Failure::ConnectionReset | Failure::Timeout => true,
+            Failure::Tls | Failure::Other => false,
+        }
+    }
+}
+
+/// The delay before the retry of ATTEMPT (starting at 1).
+pub fn delay(fixed: Option<u64>, attempt: u32) -> Duration {
+    match fixed {
+        Some(seconds) => Duration::from_secs(seconds),
+        None => INITIAL_DELAY
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(MAX_DELAY),
+    }
+}
+
+/// The statuses whose Retry-After is the delay before the retry.
+const RETRY_AFTER_STATUSES: [u16; 2] = [429, 503];
+
+/// The delay before the retry of ATTEMPT after FAILURE, which is the one of
+/// Retry-After in HEADERS of 429 and 503, or the one of `delay`.
+fn delay_after(
+    failure: &Failure,
+    headers: Option<&HeaderMap>,
+    fixed: Option<u64>,
+    attempt: u32,
+) -> (Duration, bool) {
+    let retry_after = match (failure, headers) {
+        (Failure::Status(status), Some(headers))
+            if RETRY_AFTER_STATUSES.contains(&status.as_u16()) =>
+        {
+            rate_limit::retry_after_header(headers, Utc::now())
+        }
+        _ => None,
+    };
+    match retry_after {
+        Some(retry_after) => (retry_after.min(MAX_DELAY), true),
+        None => (delay(fixed, attempt), false),
+    }
+}
+
+/// Send the request, and send it again up to --retry times while it fails with
+/// a retryable failure. The result of the last attempt is returned.
+///
+/// The request whose body is streamed is not retried, and the others are signed
+/// again for each retry. Each attempt, including
+/// the one sent again with refreshed credentials, is cut at --max-time or at
+/// --total-deadline, whichever comes first.
+pub async fn send(
+    param: &AwsCurlParam,
+    client: &reqwest::Client,
+    mut req: reqwest::Request,
+) -> anyhow::Result<reqwest::Response> {
+    let args = &param.args;
+    let total = args.total_deadline.map(Duration::from_secs_f64);
+    let deadline = total.map(|total| Instant::now() + total);
+    let exceeded = |attempts, delay| DeadlineExceeded {
+        attempts,
+        deadline: total.unwrap_or_default(),
+        delay,
+    };
+    let mut attempt = 0;
+    loop {
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        let max_time = args.max_time.map(Duration::from_secs_f64);
+        *req.timeout_mut() = match (max_time, remaining) {
+            (Some(max_time), Some(remaining)) => Some(max_time.min(remaining)),
+            (max_time, remaining) => max_time.or(remaining),
+        };
+        let sent = req.try_clone();
+        // The streamed body can not be sent again.
+        let resendable = attempt < args.retry && sent.is_some();
+        let host = req.url().host_str().unwrap_or("the host").to_string();
+        // Only in JSON, where the attempts are told apart by the events.
+        if args.verbose && logging::is_json() {
+            let fields = json!({
+                "attempt": attempt + 1,
+                "method": req.method().as_str(),
+                "path": req.url().path(),
+            });
+            logging::event(Level::Debug, "attempt-started", fields);
+        }
+        let res = match execute(param, client, req).await {
+            Ok(res) => refresh::retry_expired(param, client, res, sent).await,
+            Err(e) => Err(e),
+        };
+        let failure = match &res {
+            Ok(res) if res.status().is_success() => None,
+            Ok(res) => Some(Failure::Status(res.status())),
+            Err(e) => Some(Failure::classify(e)),
+        };
+        let Some(failure) = failure else {
+            return res;
+        };
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(exceeded(attempt + 1, None).into());
+        }
+        let retryable = failure.is_retryable(
+            args.retry_connrefused,
+            args.retry_dns,
+            args.retry_all_errors,
+        );
+        if !(retryable && resendable) {
+            if args.verbose && args.retry > 0 {
+                let reason = match failure {
+                    _ if retryable && attempt < args.retry => {
+                        "the streamed body can not be sent again"
+                    }
+                    _ if retryable => "no retries left",
+                    Failure::ConnectionRefused => "not retryable without --retry-connrefused",
+                    Failure::Dns => "not retryable without --retry-dns",
+                    _ => "not retryable without --retry-all-errors",
+                };
+                if logging::is_json() {
+                    let fields = json!({
+                        "failure": failure.to_string(),
+                        "reason": reason,
+                    });
+                    logging::event(Level::Debug, "retry-skipped", fields);
+                } else {
+                    eprintln!("* Not retrying after {} ({})", failure, reason);
+                }
+            }
+            return res;
+        }
+        attempt += 1;
+        let headers = res.as_ref().ok().map(|res| res.headers());
+        let (delay, retry_after) = delay_after(&failure, headers, args.retry_delay, attempt);
+        if deadline.is_some_and(|deadline| Instant::now() + delay >= deadline) {
+            return Err(exceeded(attempt, Some(delay)).into());
+        }
+        if args.verbose && logging::is_json() {
+            let fields = json!({
+                "failure": failure.to_string(),
+                "delay_ms": delay.as_millis() as u64,
+                "retry_after": retry_after,
+                "retry": attempt,
+                "retries": args.retry,
+            });
+            logging::event(Level::Warn, "retry", fields);
+        } else if failure == Failure::Dns {
+            // Printed without --verbose, since the wait can be long.
+            logging::message(
+                Level::Info,
+                format_args!(
+                    "Waiting for {} to resolve, retrying in {}s ({} of {})",
+                    host,
+                    delay.as_secs(),
+                    attempt,
+                    args.retry
+                ),
+            );
+        } else if args.verbose {
+            eprintln!(
+                "* Retrying after {} in {}s{} ({} of {})",
+                failure,
+                delay.as_secs(),
+                if retry_after { " of Retry-After" } else { "" },
+                attempt,
+                args.retry
+            );
+        }
+        // The connection is released before waiting.
+        drop(res);
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = interrupt::interrupted() => {
+                return Err(interrupt::Interrupted::with_partial(vec![]).into());
+            }
+        }
+        if failure == Failure::Dns {
+            // The negative answer would be cached for the TTL of its SOA.
+            dns::clear_cache();
+        }
+        req = poll::resign(param).await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use http::{HeaderMap, StatusCode};
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    use super::{delay, delay_after, DeadlineExceeded, Failure, FirstByteTimeout};
+
+    async fn send(url: &str) -> anyhow::Error {
+        reqwest::get(url).await.unwrap_err().into()
+    }
+
+    #[tokio::test]
+    async fn classify_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        assert_eq!(
+            Failure::classify(&send(&url).await),
+            Failure::ConnectionRefused
+        );
+    }
+
+    #[tokio::test]
+    async fn classify_reset() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            // Closed after reading the request without any response
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let _ = stream.read(&mut [0; 4096]).await.unwrap();
+        });
+        assert_eq!(
+            Failure::classify(&send(&url).await),
+            Failure::ConnectionReset
+        );
+    }
+
+    #[test]
+    fn retryable_failures() {
+        let transient = Failure::Status(StatusCode::SERVICE_UNAVAILABLE);
+        assert!(transient.is_retryable(false, false, false));
+        let forbidden = Failure::Status(StatusCode::FORBIDDEN);
+        assert!(!forbidden.is_retryable(false, false, false));
+        assert!(forbidden.is_retryable(false, false, true));
+        assert!(!Failure::ConnectionRefused.is_retryable(false, false, false));
+        assert!(Failure::ConnectionRefused.is_retryable(true, false, false));
+        assert!(!Failure::Dns.is_retryable(true, false, false));
+        assert!(Failure::Dns.is_retryable(false, true, false));
+        assert!(Failure::Dns.is_retryable(false, false, true));
+        assert!(Failure::ConnectionReset.is_retryable(false, false, false));
+        assert!(!Failure::Tls.is_retryable(false, false, false));
+        assert!(Failure::Tls.is_retryable(false, false, true));
+        assert!(!Failure::Interrupted.is_retryable(true, true, true));
+    }
+
+    #[test]
+    fn deadline_message() {
+        let exceeded = DeadlineExceeded {
+            attempts: 2,
+            deadline: Duration::from_millis(1500),
+            delay: Some(Duration::from_secs(1)),
+        };
+        assert_eq!(
+            exceeded.to_string(),
+            "Gave up after 2 attempts: the retry in 1s would exceed --total-deadline 1.5s"
+        );
+        let exceeded = DeadlineExceeded {
+            attempts: 1,
+            deadline: Duration::from_secs(2),
+            delay: None,
+        };
+        assert_eq!(
+            exceeded.to_string(),
+            "Gave up after 1 attempt: --total-deadline 2s was exceeded"
+        );
+        assert_eq!(Failure::classify(&exceeded.into()), Failure::Timeout);
+    }
+
+    #[test]
+    fn first_byte_timeout() {
+        let timeout = FirstByteTimeout {
+            limit: Duration::from_millis(500),
+        };
+        assert_eq!(
+            timeout.to_string(),
+            "Timed out waiting for the response headers: --max-time-to-first-byte 0.5s was exceeded"
+        );
+        assert_eq!(Failure::classify(&timeout.into()), Failure::Timeout);
+    }
+
+    #[test]
+    fn double_delay() {
+        assert_eq!(delay(None, 1), Duration::from_secs(1));
+        assert_eq!(delay(None, 4), Duration::from_secs(8));
+        assert_eq!(delay(None, 20), Duration::from_secs(600));
+        assert_eq!(delay(Some(0), 3), Duration::ZERO);
+    }
+
+    #[test]
+    fn retry_after_delay() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "7".parse().unwrap());
+        let throttled = Failure::Status(StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            delay_after(&throttled, Some(&headers), None, 1),
+            (Duration::from_secs(7), true)
+        );
+        let unavailable = Failure::Status(StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            delay_after(&unavailable, Some(&headers), Some(0), 3),
+            (Duration::from_secs(7), true)
+        );
+        // Retry-After of the other statuses is not the delay.
+        let bad_gateway = Failure::Status(StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            delay_after(&bad_gateway, Some(&headers), None, 2),
+            (Duration::from_secs(2), false)
+        );
+        assert_eq!(
+            delay_after(&throttled, None, None, 1),
+            (Duration::from_secs(1), false)
+        );
+        headers.insert("retry-after", "86400".parse().unwrap());
+        assert_eq!(
+            delay_after(&throttled, Some(&headers), None, 1),
+            (Duration::from_secs(600), true)
+        );
+    }
+}
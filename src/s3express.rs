@@ -0,0 +1,290 @@
+//! Session authentication of S3 Express One Zone (directory buckets, `--s3express`)
+//!
+//! The requests to a directory bucket are signed for the "s3express" service with
+//! the credentials of a session created by CreateSession, and the token of the
+//! session is sent as x-amz-s3session-token. The sessions are cached per bucket
+//! in memory, and in `--s3express-session-dir` if given, and are created again
+//! shortly before they expire.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context};
+use aws_credential_types::{
+    provider::{error::CredentialsError, future, ProvideCredentials, SharedCredentialsProvider},
+    Credentials,
+};
+use aws_sigv4::{
+    http_request::{sign, SignableBody, SignableRequest, SigningSettings},
+    sign::v4,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache, calc_sha256_hex_digest, logging, multipart::xml_text, redact::Secret, AwsCurlParam,
+    CONTENT_SHA256_HEADER,
+};
+
+/// The header which carries the session token instead of x-amz-security-token.
+pub const SESSION_TOKEN_HEADER: &str = "x-amz-s3session-token";
+/// The service name which the requests to the directory buckets are signed for.
+pub const SERVICE: &str = "s3express";
+/// The session is created again when it expires within this.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// The sessions created in this process, keyed by the bucket.
+static SESSIONS: Mutex<BTreeMap<String, Session>> = Mutex::new(BTreeMap::new());
+
+/// Whether URL is of a directory bucket
+/// (Ex. bucket--usw2-az1--x-s3.s3express-usw2-az1.us-west-2.amazonaws.com).
+pub fn is_express_url(url: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    url.host_str()
+        .is_some_and(|host| host.contains(".s3express-") && host.ends_with(".amazonaws.com"))
+}
+
+/// The credentials of a session, which are same as the ones in the response of
+/// CreateSession.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+struct Session {
+    access_key_id: String,
+    secret_access_key: Secret,
+    session_token: Secret,
+    /// ISO 8601 (Ex. 2013-05-24T00:05:00Z)
+    expiration: String,
+}
+
+impl Session {
+    fn parse(xml: &str) -> anyhow::Result<Self> {
+        let text = |tag| {
+            xml_text(xml, tag)
+                .map(str::to_string)
+                .with_context(|| format!("{} is not found in the response of CreateSession", tag))
+        };
+        let session = Self {
+            access_key_id: text("AccessKeyId")?,
+            secret_access_key: Secret::new(text("SecretAccessKey")?),
+            session_token: Secret::new(text("SessionToken")?),
+            expiration: text("Expiration")?,
+        };
+        session
+            .expires_at()
+            .with_context(|| format!("Invalid expiration: {}", session.expiration))?;
+        Ok(session)
+    }
+
+    fn expires_at(&self) -> Option<SystemTime> {
+        let expiration = self.expiration.parse::<DateTime<Utc>>().ok()?;
+        Some(expiration.into())
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.expires_at()
+            .is_some_and(|expiration| expiration > SystemTime::now() + EXPIRY_MARGIN)
+    }
+
+    fn credentials(&self) -> Credentials {
+        Credentials::new(
+            &self.access_key_id,
+            self.secret_access_key.expose(),
+            Some(self.session_token.expose().to_string()),
+            self.expires_at(),
+            "s3express",
+        )
+    }
+}
+
+/// The credentials provider which gives the session of the bucket, created
+/// with the credentials of `base`.
+#[derive(Debug)]
+struct SessionProvider {
+    base: SharedCredentialsProvider,
+    client: reqwest::Client,
+    bucket: String,
+    session_url: String,
+    region: String,
+    dir: Option<PathBuf>,
+    time: Option<SystemTime>,
+    verbose: bool,
+}
+
+impl SessionProvider {
+    async fn session(&self) -> anyhow::Result<Credentials> {
+        if let Some(session) = SESSIONS.lock().unwrap().get(&self.bucket) {
+            if session.is_fresh() {
+                return Ok(session.credentials());
+            }
+        }
+        let path = self.dir.as_deref().map(|dir| self.session_path(dir));
+        let saved = match &path {
+            Some(path) if path.exists() => {
+                let raw = std::fs::read_to_string(path)
+                    .with_context(|| format!("Unable to read {}", path.display()))?;
+                serde_json::from_str::<Session>(&raw)
+                    .ok()
+                    .filter(Session::is_fresh)
+            }
+            _ => None,
+        };
+        let session = match saved {
+            Some(session) => session,
+            None => {
+                let session = self.create().await?;
+                if let Some(path) = &path {
+                    // The temporary file is only readable by the user.
+                    cache::write_atomically(path, serde_json::to_string(&session)?.as_bytes())?;
+                }
+                session
+            }
+        };
+        let credentials = session.credentials();
+        SESSIONS
+            .lock()
+            .unwrap()
+            .insert(self.bucket.clone(), session);
+        Ok(credentials)
+    }
+
+    fn session_path(&self, dir: &Path) -> PathBuf {
+        dir.join(format!("{}.json", self.bucket))
+    }
+
+    /// Call CreateSession, which is signed with the base credentials.
+    async fn create(&self) -> anyhow::Result<Session> {
+        let credentials = self.base.provide_credentials().await?;
+        let mut req = http::Request::builder()
+            .uri(&self.session_url)
+            .header("x-amz-create-session-mode", "ReadWrite")
+            .header(CONTENT_SHA256_HEADER, calc_sha256_hex_digest(""))
+            .body(String::new())?;
+        let identity = credentials.into();
+        let signing_params = v4::SigningParams::builder()
+            .identity(&identity)
+            .time(self.time.unwrap_or_else(SystemTime::now))
+            .settings(SigningSettings::default())
+            .region(&self.region)
+            .name(SERVICE)
+            .build()?
+            .into();
+        let signable_request = SignableRequest::new(
+            "GET",
+            self.session_url.clone(),
+            req.headers()
+                .iter()
+                .map(|(k, v)| (k.as_str(), std::str::from_utf8(v.as_bytes()).unwrap())),
+            SignableBody::Bytes(b""),
+        )?;
+        let (instruction, _signature) = sign(signable_request, &signing_params)?.into_parts();
+        instruction.apply_to_request_http1x(&mut req);
+
+        if self.verbose {
+            logging::note(format_args!(
+                "Creating an S3 Express session for {}",
+                self.bucket
+            ));
+        }
+        let res = self.client.execute(req.try_into()?).await?;
+        let status = res.status();
+        let body = res.text().await?;
+        if !status.is_success() {
+            bail!("CreateSession failed with {}: {}", status, body);
+        }
+        let session = Session::parse(&body)?;
+        if self.verbose {
+            logging::note(format_args!(
+                "The session expires at {}",
+                session.expiration
+            ));
+        }
+        Ok(session)
+    }
+}
+
+impl ProvideCredentials for SessionProvider {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::new(async move {
+            self.session()
+                .await
+                .map_err(CredentialsError::provider_error)
+        })
+    }
+}
+
+/// Sign the requests of PARAM with the session of the bucket in its URL. The
+/// default service becomes "s3express".
+pub fn install(param: &mut AwsCurlParam, client: &reqwest::Client) -> anyhow::Result<()> {
+    let url = reqwest::Url::parse(param.url()?)?;
+    let host = url.host_str().context("The URL has no host")?;
+    let bucket = host
+        .split_once(".s3express-")
+        .map_or(host, |(bucket, _)| bucket);
+    let mut session_url = url.clone();
+    session_url.set_path("/");
+    session_url.set_query(Some("session"));
+    let provider = SessionProvider {
+        base: param
+            .config
+            .credentials_provider()
+            .context("Unable to find credentials")?,
+        client: client.clone(),
+        bucket: bucket.to_string(),
+        session_url: session_url.to_string(),
+        region: param.region()?.to_string(),
+        dir: param.args.s3express_session_dir.clone(),
+        time: param.args.datetime.map(SystemTime::from),
+        verbose: param.args.verbose,
+    };
+    param.config = param
+        .config
+        .to_builder()
+        .credentials_provider(SharedCredentialsProvider::new(provider))
+        .build();
+    param
+        .args
+        .service
+        .get_or_insert_with(|| SERVICE.to_string());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_express_url, Session};
+
+    #[test]
+    fn express_url() {
+        assert!(is_express_url(
+            "https://bucket--usw2-az1--x-s3.s3express-usw2-az1.us-west-2.amazonaws.com/key"
+        ));
+        assert!(!is_express_url(
+            "https://s3express-control.us-west-2.amazonaws.com/bucket"
+        ));
+        assert!(!is_express_url("https://bucket.s3.amazonaws.com/key"));
+    }
+
+    #[test]
+    fn parse_session() {
+        let xml = "<CreateSessionResult><Credentials>\
+            <SessionToken>token</SessionToken>\
+            <SecretAccessKey>secret</SecretAccessKey>\
+            <AccessKeyId>ASIAEXAMPLE</AccessKeyId>\
+            <Expiration>2013-05-24T00:05:00Z</Expiration>\
+            </Credentials></CreateSessionResult>";
+        let session = Session::parse(xml).unwrap();
+        assert_eq!(session.access_key_id, "ASIAEXAMPLE");
+        assert_eq!(session.session_token.expose(), "token");
+        assert_eq!(session.expiration, "2013-05-24T00:05:00Z");
+        assert!(!session.is_fresh());
+        assert!(Session::parse("<Error/>").is_err());
+    }
+}
@@ -0,0 +1,255 @@
+//! Checking a large object by sampled ranges (`--sample-ranges`)
+//!
+//! The size is taken from HEAD, and N ranges of SIZE bytes at the start, at the
+//! end and evenly spaced between them are requested by signed GETs with If-Match
+//! of the ETag, --parallel-max of them at once. Each range has to respond 206 with its Content-Range
+//! and all of its bytes. A line for each range with its latency is printed to
+//! stdout, followed by a summary, and any failed range fails the command.
+
+use std::{
+    io::{self, Write},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use futures_util::{stream, StreamExt};
+use http::{
+    header::{CONTENT_RANGE, ETAG, IF_MATCH, RANGE},
+    Method, StatusCode,
+};
+
+use crate::{chunks, multipart, AwsCurlParam};
+
+/// The bytes of each range without SIZE.
+pub const DEFAULT_SIZE: u64 = 64 * 1024;
+
+/// The ranges of --sample-ranges.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub count: u64,
+    pub size: u64,
+}
+
+/// Parse RAW of --sample-ranges (N[:SIZE]).
+pub fn parse(raw: &str) -> Result<Sample, String> {
+    let (count, size) = match raw.split_once(':') {
+        Some((count, size)) => (count, multipart::parse_size(size)?),
+        None => (raw, DEFAULT_SIZE),
+    };
+    match count.parse::<u64>() {
+        Ok(count) if count > 0 => Ok(Sample { count, size }),
+        _ => Err(format!(
+            "expected the number of ranges like 5 or 5:1M: {}",
+            raw
+        )),
+    }
+}
+
+/// The inclusive byte ranges of SAMPLE in the object of SIZE: the first one at
+/// the start, the last one at the end, and the others evenly between them.
+fn offsets(size: u64, sample: Sample) -> Vec<(u64, u64)> {
+    let len = sample.size.min(size);
+    let last = (size - len) as u128;
+    let mut ranges = (0..sample.count)
+        .map(|i| match sample.count {
+            1 => 0,
+            n => (last * i as u128 / (n - 1) as u128) as u64,
+        })
+        .map(|start| (start, start + len - 1))
+        .collect::<Vec<_>>();
+    ranges.dedup();
+    ranges
+}
+
+/// The result of a range.
+struct Checked {
+    range: (u64, u64),
+    elapsed: Duration,
+    /// Why the range failed
+    error: Option<String>,
+}
+
+impl Checked {
+    fn line(&self) -> String {
+        let (start, end) = self.range;
+        match &self.error {
+            None => format!("OK bytes {}-{} {}ms", start, end, self.elapsed.as_millis()),
+            Some(error) => format!(
+                "FAIL bytes {}-{} {}ms: {}",
+                start,
+                end,
+                self.elapsed.as_millis(),
+                error
+            ),
+        }
+    }
+}
+
+/// Request the ranges of SAMPLE of the object, and print the results.
+pub async fn run(
+    param: &AwsCurlParam,
+    client: &reqwest::Client,
+    sample: Sample,
+) -> anyhow::Result<ExitCode> {
+    if param.method() != "GET" {
+        bail!(
+            "--sample-ranges requests the ranges with GET, not {}",
+            param.method()
+        );
+    }
+    let credentials = param.credentials().await?;
+    let head = chunks::send(param, client, &credentials, Method::HEAD, &[]).await?;
+    let size = chunks::ranged_size(head.status(), head.headers())
+        .map_err(anyhow::Error::msg)
+        .context("Unable to sample the ranges")?;
+    let etag = head
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let ranges = offsets(size, sample);
+    let (etag, credentials) = (&etag, &credentials);
+    let checked = stream::iter(ranges.iter().map(|&(start, end)| async move {
+        let range = format!("bytes={}-{}", start, end);
+        let mut headers = vec![(RANGE.as_str(), range.as_str())];
+        if let Some(etag) = etag {
+            headers.push((IF_MATCH.as_str(), etag));
+        }
+        let started = Instant::now();
+        let error = match chunks::send(param, client, credentials, Method::GET, &headers).await {
+            Ok(res) => check(res, (start, end), size).await.err(),
+            Err(e) => Some(format!("{:#}", e)),
+        };
+        Checked {
+            range: (start, end),
+            elapsed: started.elapsed(),
+            error,
+        }
+    }))
+    .buffered(param.args.parallel_max.max(1))
+    .collect::<Vec<_>>()
+    .await;
+
+    let mut stdout = io::stdout();
+    for checked in &checked {
+        writeln!(stdout, "{}", checked.line())?;
+    }
+    writeln!(stdout, "{}", summary(&checked, size))?;
+    let failed = checked.iter().filter(|c| c.error.is_some()).count();
+    if failed > 0 {
+        bail!("{} of {} ranges failed", failed, checked.len());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Whether RES is the whole of the range from START to END of the object of
+/// SIZE, or why not.
+async fn check(res: reqwest::Response, (start, end): (u64, u64), size: u64) -> Result<(), String> {
+    match res.status() {
+        StatusCode::PARTIAL_CONTENT => {}
+        StatusCode::PRECONDITION_FAILED => {
+            return Err("the object was changed during the check (412)".to_string())
+        }
+        status => return Err(format!("responded {}, not 206", status)),
+    }
+    let expected = format!("bytes {}-{}/{}", start, end, size);
+    let content_range = res
+        .headers()
+        .get(CONTENT_RANGE)
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned());
+    if content_range.as_deref() != Some(expected.as_str()) {
+        return Err(format!(
+            "Content-Range is {}, not {}",
+            content_range.as_deref().unwrap_or("missing"),
+            expected
+        ));
+    }
+    let body = res.bytes().await.map_err(|e| e.to_string())?;
+    let len = end - start + 1;
+    if body.len() as u64 != len {
+        return Err(format!("received {} of {} bytes", body.len(), len));
+    }
+    Ok(())
+}
+
+/// The ranges which passed out of CHECKED, and their latencies.
+fn summary(checked: &[Checked], size: u64) -> String {
+    let ok = checked.iter().filter(|c| c.error.is_none()).count();
+    let elapsed = checked
+        .iter()
+        .map(|c| c.elapsed.as_millis())
+        .collect::<Vec<_>>();
+    let mut summary = format!("{}/{} ranges OK of {} bytes", ok, checked.len(), size);
+    if let (Some(min), Some(max)) = (elapsed.iter().min(), elapsed.iter().max()) {
+        let avg = elapsed.iter().sum::<u128>() / elapsed.len() as u128;
+        summary.push_str(&format!(", min/avg/max {}/{}/{}ms", min, avg, max));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{offsets, parse, summary, Checked, Sample};
+
+    #[test]
+    fn parse_samples() {
+        assert_eq!(
+            parse("5").unwrap(),
+            Sample {
+                count: 5,
+                size: 64 * 1024
+            }
+        );
+        assert_eq!(
+            parse("3:1M").unwrap(),
+            Sample {
+                count: 3,
+                size: 1 << 20
+            }
+        );
+        assert!(parse("0").is_err());
+        assert!(parse("3:1X").is_err());
+        assert!(parse("x:1M").is_err());
+    }
+
+    #[test]
+    fn sample_offsets() {
+        let sample = |count, size| Sample { count, size };
+        assert_eq!(offsets(100, sample(1, 10)), [(0, 9)]);
+        assert_eq!(offsets(100, sample(2, 10)), [(0, 9), (90, 99)]);
+        assert_eq!(
+            offsets(100, sample(4, 10)),
+            [(0, 9), (30, 39), (60, 69), (90, 99)]
+        );
+        // The object smaller than a range is checked once.
+        assert_eq!(offsets(5, sample(3, 10)), [(0, 4)]);
+    }
+
+    #[test]
+    fn lines() {
+        let checked = [
+            Checked {
+                range: (0, 9),
+                elapsed: Duration::from_millis(12),
+                error: None,
+            },
+            Checked {
+                range: (90, 99),
+                elapsed: Duration::from_millis(20),
+                error: Some("responded 200 OK, not 206".to_string()),
+            },
+        ];
+        assert_eq!(checked[0].line(), "OK bytes 0-9 12ms");
+        assert_eq!(
+            checked[1].line(),
+            "FAIL bytes 90-99 20ms: responded 200 OK, not 206"
+        );
+        assert_eq!(
+            summary(&checked, 100),
+            "1/2 ranges OK of 100 bytes, min/avg/max 12/16/20ms"
+        );
+    }
+}
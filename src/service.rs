@@ -0,0 +1,124 @@
+//! The service which the host of the URL belongs to, for the warning of the
+//! likely wrong --service and for `--auto-service`
+//!
+//! Only the hosts of AWS (Ex. sqs.us-east-1.amazonaws.com) are inferred, by the
+//! label of the service before the region. The others, such as the custom
+//! domains of API Gateway, have no inferred service.
+
+use crate::region;
+
+/// The domains of the AWS endpoints.
+pub const DOMAINS: [&str; 3] = [".amazonaws.com", ".amazonaws.com.cn", ".on.aws"];
+
+/// The endpoint prefixes in the host, the services which they are signed for
+/// and their names in the warning.
+const SERVICES: [(&str, &str, &str); 21] = [
+    ("s3", "s3", "S3"),
+    ("s3express", "s3express", "S3 Express"),
+    ("execute-api", "execute-api", "API Gateway"),
+    ("es", "es", "OpenSearch"),
+    ("aoss", "aoss", "OpenSearch Serverless"),
+    ("lambda", "lambda", "Lambda"),
+    ("lambda-url", "lambda", "a Lambda function URL"),
+    ("sqs", "sqs", "SQS"),
+    ("sns", "sns", "SNS"),
+    ("dynamodb", "dynamodb", "DynamoDB"),
+    ("sts", "sts", "STS"),
+    ("iam", "iam", "IAM"),
+    ("logs", "logs", "CloudWatch Logs"),
+    ("monitoring", "monitoring", "CloudWatch"),
+    ("states", "states", "Step Functions"),
+    ("kinesis", "kinesis", "Kinesis"),
+    ("ssm", "ssm", "Systems Manager"),
+    ("secretsmanager", "secretsmanager", "Secrets Manager"),
+    ("kms", "kms", "KMS"),
+    ("events", "events", "EventBridge"),
+    ("bedrock-runtime", "bedrock", "Bedrock"),
+];
+
+/// The service which the host of URL is signed for, and its name.
+pub fn infer(url: &str) -> Option<(&'static str, &'static str)> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    let rest = DOMAINS
+        .iter()
+        .find_map(|domain| host.strip_suffix(domain))?;
+    // The label of the service is the last one except the region and the
+    // variants of the endpoint (Ex. bucket.s3.dualstack.us-east-1).
+    let label = rest
+        .rsplit('.')
+        .find(|label| !region::is_valid(label) && !["dualstack", "fips"].contains(label))?;
+    let prefix = match label {
+        _ if label.starts_with("s3express") => "s3express",
+        // Ex. s3-us-west-2, s3-accesspoint and s3-external-1
+        _ if label.starts_with("s3-") => "s3",
+        label => label,
+    };
+    SERVICES
+        .iter()
+        .find(|(known, _, _)| *known == prefix)
+        .map(|(_, service, name)| (*service, *name))
+}
+
+/// The warning when the request to URL is signed for SERVICE, which is not the
+/// inferred one.
+pub fn mismatch(url: &str, service: &str) -> Option<String> {
+    let (inferred, name) = infer(url)?;
+    (inferred != service).then(|| {
+        format!(
+            "URL looks like {} but signing with service '{}'; pass --service {} or --auto-service",
+            name, service, inferred
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{infer, mismatch};
+
+    #[test]
+    fn infer_from_host() {
+        let service = |url| infer(url).map(|(service, _)| service);
+        assert_eq!(service("https://bucket.s3.amazonaws.com/key"), Some("s3"));
+        assert_eq!(
+            service("https://bucket.s3.dualstack.us-east-1.amazonaws.com/"),
+            Some("s3")
+        );
+        assert_eq!(service("https://s3-us-west-2.amazonaws.com/b"), Some("s3"));
+        assert_eq!(
+            service("https://b--usw2-az1--x-s3.s3express-usw2-az1.us-west-2.amazonaws.com/"),
+            Some("s3express")
+        );
+        assert_eq!(
+            service("https://search-d.us-east-1.es.amazonaws.com/_search"),
+            Some("es")
+        );
+        assert_eq!(
+            service("https://abc.execute-api.ap-northeast-1.amazonaws.com/prod"),
+            Some("execute-api")
+        );
+        assert_eq!(
+            service("https://abc.lambda-url.eu-west-1.on.aws/"),
+            Some("lambda")
+        );
+        assert_eq!(service("https://iam.amazonaws.com/"), Some("iam"));
+        assert_eq!(
+            service("https://bedrock-runtime.us-east-1.amazonaws.com/"),
+            Some("bedrock")
+        );
+        // No opinion on the custom domains and the unknown services.
+        assert_eq!(service("https://api.example.com/prod"), None);
+        assert_eq!(service("http://localhost:4566/bucket"), None);
+        assert_eq!(service("https://unknown.us-east-1.amazonaws.com/"), None);
+    }
+
+    #[test]
+    fn mismatch_warning() {
+        assert_eq!(
+            mismatch("https://bucket.s3.amazonaws.com/key", "execute-api").unwrap(),
+            "URL looks like S3 but signing with service 'execute-api'; pass --service s3 or --auto-service"
+        );
+        assert_eq!(mismatch("https://bucket.s3.amazonaws.com/key", "s3"), None);
+        assert_eq!(mismatch("https://api.example.com/", "s3"), None);
+    }
+}
@@ -0,0 +1,500 @@
+//! A background process which keeps the connections and the credentials warm
+//! for repeated invocations (`--session`)
+//!
+//! `--session start` runs awscurl in the background, listening on a Unix socket
+//! in the runtime directory. The invocations with `--session` still build and
+//! sign each request with the current time, but get the credentials cached by
+//! the session and send the signed request through it, which reuses the
+//! connections of its client. The session stops with `--session stop` or after
+//! `--session-timeout` without requests.
+
+use clap::ValueEnum;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// Start the session in the background
+    Start,
+    /// Stop the running session
+    Stop,
+    /// Print the state of the running session
+    Status,
+    /// Send the request through the running session
+    Use,
+    /// Run the session in the foreground, which `start` spawns
+    #[value(hide = true)]
+    Serve,
+}
+
+#[cfg(unix)]
+pub use imp::*;
+
+#[cfg(unix)]
+mod imp {
+    use std::{
+        convert::Infallible,
+        env, fs,
+        os::unix::{
+            fs::{FileTypeExt, MetadataExt, PermissionsExt},
+            process::CommandExt,
+        },
+        path::{Path, PathBuf},
+        process::{Command, ExitCode, Stdio},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use anyhow::{bail, Context};
+    use aws_config::SdkConfig;
+    use aws_credential_types::{
+        provider::{
+            error::CredentialsError, future, ProvideCredentials, SharedCredentialsProvider,
+        },
+        Credentials,
+    };
+    use chrono::{DateTime, Utc};
+    use http::{Method, StatusCode};
+    use http_body_util::BodyExt;
+    use hyper::{body::Incoming, service::service_fn};
+    use hyper_util::rt::TokioIo;
+    use serde::{Deserialize, Serialize};
+    use tokio::{
+        net::{UnixListener, UnixStream},
+        sync::Notify,
+    };
+
+    use super::Action;
+    use crate::{
+        client, load_config,
+        logging::{self, Level},
+        redact::Secret,
+        unix, Args,
+    };
+
+    /// The header of the error which the session failed to send the request
+    /// with, instead of the response.
+    const ERROR_HEADER: &str = "x-awscurl-session-error";
+
+    /// The host of the requests to the session itself.
+    const SESSION_URL: &str = "http://awscurl-session";
+
+    /// How long `start` and `stop` wait for the session.
+    const WAIT: Duration = Duration::from_secs(5);
+
+    /// The cached credentials are refreshed this long before they expire.
+    const EXPIRY_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+    /// The credentials given to the invocations, in the format of the container
+    /// credentials endpoint.
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct SessionCredentials {
+        access_key_id: String,
+        secret_access_key: Secret,
+        token: Option<Secret>,
+        /// RFC 3339 (Ex. 2013-05-24T00:05:00Z)
+        expiration: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Status {
+        pid: u32,
+        socket: PathBuf,
+        requests: usize,
+        idle_seconds: u64,
+        timeout_seconds: u64,
+    }
+
+    struct State {
+        config: SdkConfig,
+        client: reqwest::Client,
+        credentials: tokio::sync::Mutex<Option<Credentials>>,
+        requests: AtomicUsize,
+        last_used: Mutex<Instant>,
+        timeout: Duration,
+        socket: PathBuf,
+        shutdown: Notify,
+    }
+
+    /// The socket of the session: awscurl/session.sock in $XDG_RUNTIME_DIR, or in
+    /// the temporary directory of the user without it.
+    pub fn socket_path() -> PathBuf {
+        let dir = match env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir).join("awscurl"),
+            // SAFETY: getuid never fails.
+            None => env::temp_dir().join(format!("awscurl-{}", unsafe { libc::getuid() })),
+        };
+        dir.join("session.sock")
+    }
+
+    /// Fail unless PATH is owned by the user and not accessible by the others,
+    /// which could otherwise listen on the socket in place of the session and
+    /// receive the signed requests or hand out forged credentials.
+    fn check_private(path: &Path, metadata: &fs::Metadata) -> anyhow::Result<()> {
+        // SAFETY: getuid never fails.
+        let uid = unsafe { libc::getuid() };
+        if metadata.uid() != uid {
+            bail!(
+                "{} is owned by another user, so the session is not used",
+                path.display()
+            );
+        }
+        if metadata.mode() & 0o077 != 0 {
+            bail!(
+                "{} is accessible by the other users, so the session is not used",
+                path.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Fail unless the session is running at SOCKET in a directory which only
+    /// the user can access.
+    async fn check_running(socket: &Path) -> anyhow::Result<()> {
+        let not_running = || {
+            anyhow::anyhow!(
+                "No session is running at {}. Start one with --session start",
+                socket.display()
+            )
+        };
+        let dir = socket.parent().context("Invalid socket path")?;
+        let Ok(metadata) = fs::symlink_metadata(socket) else {
+            return Err(not_running());
+        };
+        if !metadata.file_type().is_socket() {
+            bail!("{} is not a socket", socket.display());
+        }
+        let dir_metadata = fs::symlink_metadata(dir)?;
+        if !dir_metadata.is_dir() {
+            bail!("{} is not a directory", dir.display());
+        }
+        check_private(dir, &dir_metadata)?;
+        check_private(socket, &metadata)?;
+        if UnixStream::connect(socket).await.is_err() {
+            return Err(not_running());
+        }
+        Ok(())
+    }
+
+    /// Run ACTION of --session other than `use`.
+    pub async fn run(action: Action, args: &Args) -> anyhow::Result<ExitCode> {
+        let socket = socket_path();
+        match action {
+            Action::Start => start(&socket),
+            Action::Stop => stop(&socket).await,
+            Action::Status => {
+                let res = request(&socket, Method::GET, "/status").await?;
+                let status = serde_json::from_slice::<Status>(&res)?;
+                println!(
+                    "Session {} at {}: {} requests, idle for {}s of {}s",
+                    status.pid,
+                    status.socket.display(),
+                    status.requests,
+                    status.idle_seconds,
+                    status.timeout_seconds
+                );
+                Ok(ExitCode::SUCCESS)
+            }
+            Action::Serve => serve(&socket, args).await,
+            Action::Use => bail!("--session alone sends a request through the session"),
+        }
+    }
+
+    /// Spawn `awscurl --session serve` with the other arguments of this
+    /// invocation, and wait until it listens on SOCKET.
+    fn start(socket: &Path) -> anyhow::Result<ExitCode> {
+        if std::os::unix::net::UnixStream::connect(socket).is_ok() {
+            bail!("A session is already running at {}", socket.display());
+        }
+        let mut args = env::args_os().skip(1).collect::<Vec<_>>();
+        let mut replaced = false;
+        for i in 0..args.len() {
+            if args[i] == "--session=start" {
+                args[i] = "--session=serve".into();
+                replaced = true;
+            } else if args[i] == "--session" && args.get(i + 1).is_some_and(|a| a == "start") {
+                args[i + 1] = "serve".into();
+                replaced = true;
+            }
+        }
+        if !replaced {
+            bail!("Unable to find --session start in the arguments");
+        }
+        let mut child = Command::new(env::current_exe()?)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            // Not stopped by Ctrl-C in the shell which started it.
+            .process_group(0)
+            .spawn()
+            .context("Unable to start the session")?;
+        let started = Instant::now();
+        while started.elapsed() < WAIT {
+            if let Some(status) = child.try_wait()? {
+                bail!("The session exited with {}", status);
+            }
+            if std::os::unix::net::UnixStream::connect(socket).is_ok() {
+                logging::message(
+                    Level::Info,
+                    format_args!("Session {} started at {}", child.id(), socket.display()),
+                );
+                return Ok(ExitCode::SUCCESS);
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let _ = child.kill();
+        bail!("The session did not start in {}s", WAIT.as_secs())
+    }
+
+    async fn stop(socket: &Path) -> anyhow::Result<ExitCode> {
+        request(socket, Method::POST, "/shutdown").await?;
+        let started = Instant::now();
+        while socket.exists() && started.elapsed() < WAIT {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        logging::message(Level::Info, "Session stopped");
+        Ok(ExitCode::SUCCESS)
+    }
+
+    /// Send a request for the session itself, and return the body of the response.
+    async fn request(socket: &Path, method: Method, path: &str) -> anyhow::Result<Vec<u8>> {
+        check_running(socket).await?;
+        let req = reqwest::Request::new(method, format!("{}{}", SESSION_URL, path).parse()?);
+        let res = unix::execute(socket, req).await?;
+        let status = res.status();
+        let body = res.bytes().await?;
+        if !status.is_success() {
+            bail!("The session failed: {}", String::from_utf8_lossy(&body));
+        }
+        Ok(body.to_vec())
+    }
+
+    /// Listen on SOCKET until `stop` or the timeout.
+    async fn serve(socket: &Path, args: &Args) -> anyhow::Result<ExitCode> {
+        let dir = socket.parent().context("Invalid socket path")?;
+        fs::create_dir_all(dir)?;
+        let metadata = fs::symlink_metadata(dir)?;
+        if !metadata.is_dir() {
+            bail!("{} is not a directory", dir.display());
+        }
+        // SAFETY: getuid never fails.
+        if metadata.uid() != unsafe { libc::getuid() } {
+            bail!(
+                "{} is owned by another user, so the session is not started",
+                dir.display()
+            );
+        }
+        // Only the user can connect, since the session hands out the credentials.
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+        if UnixStream::connect(socket).await.is_ok() {
+            bail!("A session is already running at {}", socket.display());
+        }
+        let _ = fs::remove_file(socket);
+        let listener = UnixListener::bind(socket)
+            .with_context(|| format!("Unable to listen on {}", socket.display()))?;
+        fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+        let state = Arc::new(State {
+            config: load_config(args).await?,
+            client: client::build(args)?,
+            credentials: tokio::sync::Mutex::new(None),
+            requests: AtomicUsize::new(0),
+            last_used: Mutex::new(Instant::now()),
+            timeout: Duration::from_secs(args.session_timeout),
+            socket: socket.to_path_buf(),
+            shutdown: Notify::new(),
+        });
+        loop {
+            let idle = state.last_used.lock().unwrap().elapsed();
+            let Some(remaining) = state.timeout.checked_sub(idle) else {
+                break;
+            };
+            let stream = tokio::select! {
+                accepted = tokio::time::timeout(remaining, listener.accept()) => match accepted {
+                    Ok(accepted) => accepted?.0,
+                    // The timeout is checked again with the last request.
+                    Err(_) => continue,
+                },
+                _ = state.shutdown.notified() => break,
+            };
+            let state = state.clone();
+            tokio::spawn(async move {
+                let service = service_fn(|req| handle(state.clone(), req));
+                let _ = hyper::server::conn::http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+        let _ = fs::remove_file(socket);
+        Ok(ExitCode::SUCCESS)
+    }
+
+    async fn handle(
+        state: Arc<State>,
+        req: http::Request<Incoming>,
+    ) -> Result<http::Response<reqwest::Body>, Infallible> {
+        let res = match (req.method(), req.uri().path()) {
+            _ if req.uri().scheme().is_some() => {
+                *state.last_used.lock().unwrap() = Instant::now();
+                state.requests.fetch_add(1, Ordering::Relaxed);
+                send(&state, req).await
+            }
+            (&Method::GET, "/credentials") => {
+                *state.last_used.lock().unwrap() = Instant::now();
+                credentials(&state).await
+            }
+            (&Method::GET, "/status") => status(&state),
+            (&Method::POST, "/shutdown") => {
+                state.shutdown.notify_one();
+                Ok(http::Response::new(reqwest::Body::from("")))
+            }
+            _ => Ok(http::Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(reqwest::Body::from(""))
+                .unwrap()),
+        };
+        Ok(res.unwrap_or_else(|e| {
+            http::Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .header(ERROR_HEADER, "1")
+                .body(reqwest::Body::from(format!("{:#}", e)))
+                .unwrap()
+        }))
+    }
+
+    /// Send the signed request with the client of the session.
+    async fn send(
+        state: &State,
+        req: http::Request<Incoming>,
+    ) -> anyhow::Result<http::Response<reqwest::Body>> {
+        let (parts, body) = req.into_parts();
+        let body = body.collect().await?.to_bytes();
+        let mut builder = state.client.request(parts.method, parts.uri.to_string());
+        for (key, value) in parts.headers.iter() {
+            // The ones of the connection to the session.
+            if key == http::header::CONNECTION
+                || key == http::header::TRANSFER_ENCODING
+                || key == http::header::CONTENT_LENGTH
+            {
+                continue;
+            }
+            builder = builder.header(key, value);
+        }
+        let res = builder.body(body).send().await?;
+        Ok(res.into())
+    }
+
+    /// The credentials of the session, which are resolved again only when they
+    /// are about to expire.
+    async fn credentials(state: &State) -> anyhow::Result<http::Response<reqwest::Body>> {
+        let mut cached = state.credentials.lock().await;
+        let fresh = cached.as_ref().is_some_and(|credentials| {
+            credentials
+                .expiry()
+                .is_none_or(|expiry| expiry > SystemTime::now() + EXPIRY_MARGIN)
+        });
+        if !fresh {
+            let provider = state
+                .config
+                .credentials_provider()
+                .context("Unable to find credentials")?;
+            *cached = Some(provider.provide_credentials().await?);
+        }
+        let credentials = cached.as_ref().context("No credentials")?;
+        let body = SessionCredentials {
+            access_key_id: credentials.access_key_id().to_string(),
+            secret_access_key: Secret::new(credentials.secret_access_key().to_string()),
+            token: credentials
+                .session_token()
+                .map(|token| Secret::new(token.to_string())),
+            expiration: credentials
+                .expiry()
+                .map(|expiry| DateTime::<Utc>::from(expiry).to_rfc3339()),
+        };
+        Ok(http::Response::new(serde_json::to_string(&body)?.into()))
+    }
+
+    fn status(state: &State) -> anyhow::Result<http::Response<reqwest::Body>> {
+        let status = Status {
+            pid: std::process::id(),
+            socket: state.socket.clone(),
+            requests: state.requests.load(Ordering::Relaxed),
+            idle_seconds: state.last_used.lock().unwrap().elapsed().as_secs(),
+            timeout_seconds: state.timeout.as_secs(),
+        };
+        Ok(http::Response::new(serde_json::to_string(&status)?.into()))
+    }
+
+    /// The credentials provider which asks the session.
+    #[derive(Debug)]
+    struct SessionProvider {
+        socket: PathBuf,
+    }
+
+    impl SessionProvider {
+        async fn fetch(&self) -> anyhow::Result<Credentials> {
+            let body = request(&self.socket, Method::GET, "/credentials").await?;
+            let credentials = serde_json::from_slice::<SessionCredentials>(&body)?;
+            Ok(Credentials::new(
+                credentials.access_key_id,
+                credentials.secret_access_key.expose(),
+                credentials.token.map(|token| token.expose().to_string()),
+                credentials
+                    .expiration
+                    .and_then(|expiration| expiration.parse::<DateTime<Utc>>().ok())
+                    .map(SystemTime::from),
+                "session",
+            ))
+        }
+    }
+
+    impl ProvideCredentials for SessionProvider {
+        fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+        where
+            Self: 'a,
+        {
+            future::ProvideCredentials::new(async move {
+                self.fetch().await.map_err(CredentialsError::provider_error)
+            })
+        }
+    }
+
+    /// CONFIG with the credentials of the session.
+    pub fn config(config: SdkConfig) -> SdkConfig {
+        let provider = SessionProvider {
+            socket: socket_path(),
+        };
+        config
+            .to_builder()
+            .credentials_provider(SharedCredentialsProvider::new(provider))
+            .build()
+    }
+
+    /// Send REQ through the session.
+    pub async fn execute(req: reqwest::Request) -> anyhow::Result<reqwest::Response> {
+        let socket = socket_path();
+        check_running(&socket).await?;
+        let res = unix::forward(&socket, req).await?;
+        if res.headers().contains_key(ERROR_HEADER) {
+            bail!("{}", res.text().await?);
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(not(unix))]
+pub fn config(config: aws_config::SdkConfig) -> aws_config::SdkConfig {
+    config
+}
+
+#[cfg(not(unix))]
+pub async fn run(_action: Action, _args: &crate::Args) -> anyhow::Result<std::process::ExitCode> {
+    anyhow::bail!("--session is not supported on this platform")
+}
+
+#[cfg(not(unix))]
+pub async fn execute(_req: reqwest::Request) -> anyhow::Result<reqwest::Response> {
+    anyhow::bail!("--session is not supported on this platform")
+}
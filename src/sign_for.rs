@@ -0,0 +1,102 @@
+//! Signing for another host, service and region than the URL (`--sign-for`)
+//!
+//! The origin behind a proxy (Ex. an S3 bucket behind CloudFront with Origin
+//! Access Control) checks the signature for its own host, service and region,
+//! while the request is sent to the URL of the proxy. The canonical request has
+//! the Host of the origin, and the request is sent with the Host of the URL.
+
+use http::{header::HOST, HeaderValue};
+
+use crate::endpoint;
+
+/// The target of the signature given by --sign-for.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SignFor {
+    pub host: Option<String>,
+    pub service: Option<String>,
+    pub region: Option<String>,
+}
+
+/// Parse RAW of `--sign-for` (host=HOST,service=SERVICE,region=REGION), each
+/// of which is optional.
+pub fn parse(raw: &str) -> Result<SignFor, String> {
+    let mut target = SignFor::default();
+    for entry in raw.split(',') {
+        let (key, value) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=VALUE (Ex. service=s3): {}", entry))?;
+        let field = match key.trim() {
+            "host" => &mut target.host,
+            "service" => &mut target.service,
+            "region" => &mut target.region,
+            _ => {
+                return Err(format!(
+                    "unknown key {} (only host, service and region)",
+                    key
+                ))
+            }
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(format!("empty value of {}", key));
+        }
+        if field.replace(value.to_string()).is_some() {
+            return Err(format!("{} is given twice", key));
+        }
+    }
+    if let Some(host) = &target.host {
+        if host.contains(['/', ' ']) {
+            return Err(format!(
+                "expected a host (Ex. bucket.s3.amazonaws.com): {}",
+                host
+            ));
+        }
+    }
+    Ok(target)
+}
+
+/// Send the request signed for another host with the Host of its URL.
+pub fn restore_host(req: &mut reqwest::Request) -> anyhow::Result<()> {
+    let host = endpoint::host(req.url());
+    req.headers_mut()
+        .insert(HOST, HeaderValue::from_str(&host)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, SignFor};
+
+    #[test]
+    fn parse_targets() {
+        assert_eq!(
+            parse("host=bucket.s3.us-west-2.amazonaws.com,service=s3,region=us-west-2").unwrap(),
+            SignFor {
+                host: Some("bucket.s3.us-west-2.amazonaws.com".to_string()),
+                service: Some("s3".to_string()),
+                region: Some("us-west-2".to_string()),
+            }
+        );
+        assert_eq!(
+            parse("service=s3").unwrap(),
+            SignFor {
+                service: Some("s3".to_string()),
+                ..SignFor::default()
+            }
+        );
+        assert_eq!(
+            parse("service=s3,zone=a").unwrap_err(),
+            "unknown key zone (only host, service and region)"
+        );
+        assert_eq!(
+            parse("s3").unwrap_err(),
+            "expected KEY=VALUE (Ex. service=s3): s3"
+        );
+        assert_eq!(parse("region=").unwrap_err(), "empty value of region");
+        assert_eq!(
+            parse("service=s3,service=es").unwrap_err(),
+            "service is given twice"
+        );
+        assert!(parse("host=https://bucket.s3.amazonaws.com/").is_err());
+    }
+}
@@ -0,0 +1,269 @@
+//! The metadata of an object without its body (`--stat`)
+//!
+//! The object is requested with a signed HEAD, and the headers which describe
+//! it are printed as a table, or as JSON with `--output-format json`. The names
+//! are the ones of S3, where the missing x-amz-storage-class means STANDARD.
+//! The user metadata (x-amz-meta-*) and the other x-amz-* headers are grouped
+//! after them.
+
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    process::ExitCode,
+};
+
+use http::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, LAST_MODIFIED},
+    HeaderMap, StatusCode,
+};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    exit::{self, ExitCodeMode},
+    logging::{self, Level},
+    metadata,
+    output::{self, OutputFormat},
+};
+
+const STORAGE_CLASS_HEADER: &str = "x-amz-storage-class";
+const VERSION_ID_HEADER: &str = "x-amz-version-id";
+/// The x-amz-* headers which identify the request, and not the object.
+const REQUEST_HEADERS: [&str; 3] = ["x-amz-request-id", "x-amz-id-2", "x-amz-cf-id"];
+
+/// The metadata in the response to HEAD.
+#[derive(Debug, PartialEq)]
+pub struct Stat {
+    size: Option<u64>,
+    content_type: Option<String>,
+    last_modified: Option<String>,
+    etag: Option<String>,
+    storage_class: Option<String>,
+    version_id: Option<String>,
+    /// x-amz-meta-* without the prefix
+    metadata: Vec<(String, String)>,
+    /// The other x-amz-* headers
+    amz: Vec<(String, String)>,
+}
+
+impl Stat {
+    /// The metadata in HEADERS, which are of S3 if S3.
+    pub fn new(headers: &HeaderMap, s3: bool) -> Self {
+        let value = |name: &str| {
+            headers
+                .get(name)
+                .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+        };
+        let storage_class = value(STORAGE_CLASS_HEADER).or(s3.then(|| "STANDARD".to_string()));
+        let mut metadata = Vec::new();
+        let mut amz = Vec::new();
+        for (name, value) in headers {
+            let name = name.as_str();
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            if let Some(key) = name.strip_prefix(metadata::HEADER_PREFIX) {
+                metadata.push((key.to_string(), value));
+            } else if name.starts_with("x-amz-")
+                && ![STORAGE_CLASS_HEADER, VERSION_ID_HEADER].contains(&name)
+                && !REQUEST_HEADERS.contains(&name)
+            {
+                amz.push((name.to_string(), value));
+            }
+        }
+        metadata.sort();
+        amz.sort();
+        Stat {
+            size: value(CONTENT_LENGTH.as_str()).and_then(|size| size.parse().ok()),
+            content_type: value(CONTENT_TYPE.as_str()),
+            last_modified: value(LAST_MODIFIED.as_str()),
+            etag: value(ETAG.as_str()),
+            storage_class,
+            version_id: value(VERSION_ID_HEADER),
+            metadata,
+            amz,
+        }
+    }
+
+    /// The table for a terminal, where the missing fields are "-".
+    pub fn table(&self) -> String {
+        let size = self
+            .size
+            .map(|size| format!("{} ({})", size, human_size(size)));
+        let fields = [
+            ("Size", size.as_deref()),
+            ("Content type", self.content_type.as_deref()),
+            ("Last modified", self.last_modified.as_deref()),
+            ("ETag", self.etag.as_deref()),
+            ("Storage class", self.storage_class.as_deref()),
+            ("Version id", self.version_id.as_deref()),
+        ];
+        let width = fields
+            .iter()
+            .map(|(label, _)| label.len())
+            .chain(self.metadata.iter().map(|(key, _)| key.len() + 2))
+            .chain(self.amz.iter().map(|(name, _)| name.len() + 2))
+            .max()
+            .unwrap_or(0);
+        let mut table = String::new();
+        for (label, value) in fields {
+            let _ = writeln!(table, "{:width$}  {}", label, value.unwrap_or("-"));
+        }
+        for (title, group) in [("Metadata", &self.metadata), ("Headers", &self.amz)] {
+            if group.is_empty() {
+                continue;
+            }
+            let _ = writeln!(table, "{}", title);
+            for (name, value) in group {
+                let _ = writeln!(table, "  {:width$}  {}", name, value, width = width - 2);
+            }
+        }
+        table
+    }
+
+    /// The JSON document of `--output-format json` for the object at URL.
+    pub fn json(&self, url: &str) -> Value {
+        json!({
+            "url": url,
+            "size": self.size,
+            "content_type": self.content_type,
+            "last_modified": self.last_modified,
+            "etag": self.etag,
+            "storage_class": self.storage_class,
+            "version_id": self.version_id,
+            "metadata": group(&self.metadata),
+            "headers": group(&self.amz),
+        })
+    }
+
+    /// The user metadata as a JSON object (`--print-metadata`).
+    pub fn metadata_json(&self) -> Value {
+        Value::Object(group(&self.metadata))
+    }
+}
+
+fn group(group: &[(String, String)]) -> Map<String, Value> {
+    group
+        .iter()
+        .map(|(name, value)| (name.clone(), Value::from(value.as_str())))
+        .collect()
+}
+
+/// SIZE in the binary units (Ex. 1.5 MiB).
+fn human_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Print the metadata of the response of STATUS and HEADERS for URL to stdout,
+/// only the user metadata if METADATA_ONLY, or the reason of the failure to
+/// stderr.
+pub fn report(
+    status: StatusCode,
+    headers: &HeaderMap,
+    url: &str,
+    s3: bool,
+    format: OutputFormat,
+    metadata_only: bool,
+    mode: ExitCodeMode,
+) -> anyhow::Result<ExitCode> {
+    if !status.is_success() {
+        let message = match status {
+            StatusCode::NOT_FOUND => format!("Not found: {}", url),
+            _ => output::error_summary(status, headers),
+        };
+        logging::message(Level::Error, message);
+        return Ok(exit::for_status(status, mode));
+    }
+    let stat = Stat::new(headers, s3);
+    let output = match format {
+        _ if metadata_only => format!("{}\n", stat.metadata_json()),
+        OutputFormat::Body => stat.table(),
+        OutputFormat::Json => format!("{}\n", stat.json(url)),
+    };
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(output.as_bytes())?;
+    stdout.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderMap;
+    use serde_json::json;
+
+    use super::{human_size, Stat};
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+            .collect()
+    }
+
+    fn s3_headers() -> HeaderMap {
+        headers(&[
+            ("content-length", "1572864"),
+            ("content-type", "image/png"),
+            ("last-modified", "Fri, 24 May 2013 00:00:00 GMT"),
+            ("etag", "\"d41d8cd98f00b204e9800998ecf8427e\""),
+            ("x-amz-version-id", "3HL4kqtJlcpXroDTDmjVBH40Nrjfkd"),
+            ("x-amz-meta-author", "alice"),
+            ("x-amz-server-side-encryption", "AES256"),
+            ("x-amz-request-id", "318BC8BC148832E5"),
+            ("accept-ranges", "bytes"),
+        ])
+    }
+
+    #[test]
+    fn s3_table() {
+        insta::assert_snapshot!(Stat::new(&s3_headers(), true).table(), @r#"
+        Size                            1572864 (1.5 MiB)
+        Content type                    image/png
+        Last modified                   Fri, 24 May 2013 00:00:00 GMT
+        ETag                            "d41d8cd98f00b204e9800998ecf8427e"
+        Storage class                   STANDARD
+        Version id                      3HL4kqtJlcpXroDTDmjVBH40Nrjfkd
+        Metadata
+          author                        alice
+        Headers
+          x-amz-server-side-encryption  AES256
+        "#);
+    }
+
+    #[test]
+    fn json() {
+        let stat = Stat::new(
+            &headers(&[("content-length", "0"), ("x-amz-meta-a", "1")]),
+            false,
+        );
+        assert_eq!(
+            stat.json("http://localhost/k"),
+            json!({
+                "url": "http://localhost/k",
+                "size": 0,
+                "content_type": null,
+                "last_modified": null,
+                "etag": null,
+                "storage_class": null,
+                "version_id": null,
+                "metadata": {"a": "1"},
+                "headers": {},
+            })
+        );
+    }
+
+    #[test]
+    fn human_sizes() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+}
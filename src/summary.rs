@@ -0,0 +1,173 @@
+//! One line on stderr for each request (`--summary`)
+//!
+//! Ex. "PUT /test.txt -> 200 in 312ms (21 B up, 0 B down) req-id: ABC123"
+//!
+//! The fields are always printed in this order, and a missing one is "-", so
+//! that the lines can be grepped. The requests given with --next are followed
+//! by a line of their total.
+
+use std::{fmt, sync::Mutex, time::Duration};
+
+use http::{HeaderMap, StatusCode};
+use serde_json::json;
+
+use crate::{
+    logging::{self, Level},
+    output,
+};
+
+/// The summary of a request.
+pub struct Line {
+    pub method: String,
+    pub path: String,
+    /// None if no response was received
+    pub status: Option<StatusCode>,
+    pub elapsed: Duration,
+    /// None if the body is streamed (Ex. -T - from stdin)
+    pub up: Option<u64>,
+    pub down: u64,
+    pub request_id: Option<String>,
+}
+
+impl Line {
+    /// The summary of a request whose response is not received yet.
+    pub fn new(req: &reqwest::Request) -> Self {
+        Self {
+            method: req.method().to_string(),
+            path: req.url().path().to_string(),
+            status: None,
+            elapsed: Duration::ZERO,
+            up: match req.body() {
+                Some(body) => body.as_bytes().map(|bytes| bytes.len() as u64),
+                None => Some(0),
+            },
+            down: 0,
+            request_id: None,
+        }
+    }
+
+    pub fn set_response(&mut self, status: StatusCode, headers: &HeaderMap) {
+        self.status = Some(status);
+        self.request_id = output::request_id(headers).map(str::to_string);
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            Some(status) => status.as_u16().to_string(),
+            None => "-".to_string(),
+        };
+        let up = match self.up {
+            Some(up) => up.to_string(),
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "{} {} -> {} in {}ms ({} B up, {} B down) req-id: {}",
+            self.method,
+            self.path,
+            status,
+            self.elapsed.as_millis(),
+            up,
+            self.down,
+            self.request_id.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+/// The total of the summarized requests.
+struct Total {
+    requests: usize,
+    failed: usize,
+    up: u64,
+    down: u64,
+}
+
+static TOTAL: Mutex<Total> = Mutex::new(Total {
+    requests: 0,
+    failed: 0,
+    up: 0,
+    down: 0,
+});
+
+/// Print LINE to stderr and add it to the total.
+pub fn print(line: &Line) {
+    if logging::is_json() {
+        let fields = json!({
+            "method": line.method,
+            "path": line.path,
+            "status": line.status.map(|status| status.as_u16()),
+            "elapsed_ms": line.elapsed.as_millis() as u64,
+            "bytes_up": line.up,
+            "bytes_down": line.down,
+            "request_id": line.request_id,
+        });
+        logging::event(Level::Info, "summary", fields);
+    } else {
+        eprintln!("{}", line);
+    }
+    let mut total = TOTAL.lock().unwrap();
+    total.requests += 1;
+    total.failed += usize::from(!line.status.is_some_and(|status| status.is_success()));
+    total.up += line.up.unwrap_or(0);
+    total.down += line.down;
+}
+
+/// Print the total of the printed requests, which took ELAPSED in all.
+pub fn print_total(elapsed: Duration) {
+    let total = TOTAL.lock().unwrap();
+    if logging::is_json() {
+        let fields = json!({
+            "requests": total.requests,
+            "failed": total.failed,
+            "elapsed_ms": elapsed.as_millis() as u64,
+            "bytes_up": total.up,
+            "bytes_down": total.down,
+        });
+        logging::event(Level::Info, "summary-total", fields);
+        return;
+    }
+    eprintln!(
+        "Total: {} requests ({} failed) in {}ms ({} B up, {} B down)",
+        total.requests,
+        total.failed,
+        elapsed.as_millis(),
+        total.up,
+        total.down
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use http::{HeaderMap, HeaderValue, StatusCode};
+
+    use super::Line;
+
+    #[test]
+    fn line() {
+        let mut line = Line {
+            method: "PUT".to_string(),
+            path: "/test.txt".to_string(),
+            status: None,
+            elapsed: Duration::from_millis(312),
+            up: Some(21),
+            down: 0,
+            request_id: None,
+        };
+        assert_eq!(
+            line.to_string(),
+            "PUT /test.txt -> - in 312ms (21 B up, 0 B down) req-id: -"
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert("x-amz-request-id", HeaderValue::from_static("ABC123"));
+        line.set_response(StatusCode::OK, &headers);
+        line.up = None;
+        assert_eq!(
+            line.to_string(),
+            "PUT /test.txt -> 200 in 312ms (- B up, 0 B down) req-id: ABC123"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! The response body printed to stdout and written to a file (`--tee`)
+//!
+//! The body is written to stdout and to a temporary file next to FILE as each
+//! part of it is received, and the temporary file replaces FILE when the body
+//! is complete. When writing the file fails, the body is still printed to the
+//! end, and the error is reported after it.
+
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use http::HeaderMap;
+use tempfile::NamedTempFile;
+
+use crate::{cache, output, Args};
+
+/// The file of --tee, which is its value or the one of --output.
+pub fn path(args: &Args) -> anyhow::Result<Option<PathBuf>> {
+    match (&args.tee, &args.output) {
+        (None, _) => Ok(None),
+        (Some(Some(_)), Some(_)) => {
+            bail!("Both --tee and --output have FILE. Give it to one of them")
+        }
+        (Some(Some(path)), None) => Ok(Some(path.clone())),
+        (Some(None), Some(path)) => Ok(Some(path.clone())),
+        (Some(None), None) => bail!("--tee needs FILE, or --output with it"),
+    }
+}
+
+/// Whether the body is written as it is received, which is not when it is
+/// formatted with --pretty. -N prefers the streaming to the formatting.
+pub fn streams(args: &Args) -> bool {
+    !args.pretty || args.no_buffer
+}
+
+/// The temporary file in the directory of PATH.
+pub fn create(path: &Path) -> anyhow::Result<NamedTempFile> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(dir)?;
+    NamedTempFile::new_in(dir).with_context(|| format!("Unable to write {}", path.display()))
+}
+
+/// A writer to OUT and FILE, which keeps writing OUT after FILE fails.
+pub struct Tee<O, F> {
+    out: O,
+    file: Option<F>,
+    error: Option<io::Error>,
+}
+
+impl<O: Write, F: Write> Tee<O, F> {
+    pub fn new(out: O, file: F) -> Self {
+        Self {
+            out,
+            file: Some(file),
+            error: None,
+        }
+    }
+
+    /// The file with the whole body, or the error which stopped writing it.
+    pub fn finish(self) -> io::Result<F> {
+        match (self.file, self.error) {
+            (Some(file), _) => Ok(file),
+            (None, error) => Err(error.unwrap_or_else(|| io::ErrorKind::Other.into())),
+        }
+    }
+
+    fn write_file(&mut self, write: impl FnOnce(&mut F) -> io::Result<()>) {
+        if let Some(file) = &mut self.file {
+            if let Err(e) = write(file) {
+                self.file = None;
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+impl<O: Write, F: Write> Write for Tee<O, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.out.write(buf)?;
+        self.write_file(|file| file.write_all(&buf[..written]));
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()?;
+        self.write_file(|file| file.flush());
+        Ok(())
+    }
+}
+
+/// Write the file of --tee at PATH: FILE streamed by `Tee`, or BODY if the body
+/// was not streamed. The time of the file is the Last-Modified of HEADERS with
+/// `remote_time`.
+pub fn save(
+    path: &Path,
+    file: Option<io::Result<NamedTempFile>>,
+    body: &[u8],
+    headers: &HeaderMap,
+    remote_time: bool,
+) -> anyhow::Result<()> {
+    match file {
+        Some(Ok(file)) => {
+            file.persist(path)
+                .with_context(|| format!("Unable to write {}", path.display()))?;
+        }
+        Some(Err(e)) => {
+            return Err(e).with_context(|| {
+                format!(
+                    "Unable to write {}. The body was printed only to stdout",
+                    path.display()
+                )
+            })
+        }
+        None => cache::write_atomically(path, body)?,
+    }
+    if remote_time {
+        output::apply_remote_time(path, headers)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use clap::Parser;
+
+    use super::{path, Tee};
+    use crate::Args;
+
+    /// A file which fails after LIMIT bytes.
+    struct Full {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Write for Full {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.written.len() + buf.len() > self.limit {
+                return Err(io::Error::other("No space left on device"));
+            }
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_both() {
+        let mut tee = Tee::new(Vec::new(), Vec::new());
+        tee.write_all(b"first\n").unwrap();
+        tee.write_all(b"second\n").unwrap();
+        tee.flush().unwrap();
+        assert_eq!(tee.out, b"first\nsecond\n");
+        assert_eq!(tee.finish().unwrap(), b"first\nsecond\n");
+    }
+
+    #[test]
+    fn file_error() {
+        let full = Full {
+            written: Vec::new(),
+            limit: 8,
+        };
+        let mut tee = Tee::new(Vec::new(), full);
+        tee.write_all(b"first\n").unwrap();
+        tee.write_all(b"second\n").unwrap();
+        tee.write_all(b"third\n").unwrap();
+        // stdout has the whole body.
+        assert_eq!(tee.out, b"first\nsecond\nthird\n");
+        assert_eq!(
+            tee.finish().err().unwrap().to_string(),
+            "No space left on device"
+        );
+    }
+
+    #[test]
+    fn paths() {
+        let tee = |args: &[&str]| {
+            let args = Args::parse_from([&["awscurl", "https://example.com"], args].concat());
+            path(&args).map_err(|e| e.to_string())
+        };
+        assert_eq!(tee(&[]), Ok(None));
+        assert_eq!(tee(&["--tee", "out.json"]), Ok(Some("out.json".into())));
+        assert_eq!(
+            tee(&["-o", "out.json", "--tee"]),
+            Ok(Some("out.json".into()))
+        );
+        assert_eq!(
+            tee(&["--tee"]),
+            Err("--tee needs FILE, or --output with it".to_string())
+        );
+        assert_eq!(
+            tee(&["-o", "a.json", "--tee", "b.json"]),
+            Err("Both --tee and --output have FILE. Give it to one of them".to_string())
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! Named request templates stored as TOML files under the config directory.
+
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::config_dir, Args};
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Template {
+    pub url: Option<String>,
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<String>,
+    pub body: Option<String>,
+    pub service: Option<String>,
+    pub region: Option<String>,
+}
+
+impl Template {
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            url: args.url.clone(),
+            method: args.method.clone(),
+            headers: args.header.clone(),
+            body: args.body(),
+            service: args.service.clone(),
+            region: args.region.clone(),
+        }
+    }
+
+    /// Fill the fields which are not given on the command line, then substitute
+    /// the `{{name}}` and `{{env.NAME}}` placeholders.
+    pub fn apply(self, args: &mut Args, vars: &HashMap<String, String>) -> anyhow::Result<()> {
+        args.url = self.url;
+        args.method = args.method.take().or(self.method);
+        args.header.splice(0..0, self.headers);
+        if args.data.is_empty() {
+            args.data.extend(self.body);
+        }
+        args.service = args.service.take().or(self.service);
+        args.region = args.region.take().or(self.region);
+
+        let mut undefined = Vec::new();
+        let fields = args
+            .url
+            .iter_mut()
+            .chain(args.data.iter_mut())
+            .chain(args.header.iter_mut());
+        for field in fields {
+            *field = substitute(field, vars, &mut undefined);
+        }
+        if !undefined.is_empty() {
+            undefined.dedup();
+            bail!("Undefined template variables: {}", undefined.join(", "));
+        }
+        Ok(())
+    }
+}
+
+/// Replace the placeholders in `text`. The names which can not be resolved are
+/// pushed to `undefined` and left as they are.
+fn substitute(text: &str, vars: &HashMap<String, String>, undefined: &mut Vec<String>) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        ret.push_str(&rest[..start]);
+        let placeholder = &rest[start..start + len + 4];
+        let name = placeholder[2..placeholder.len() - 2].trim();
+        let value = match name.strip_prefix("env.") {
+            Some(env_name) => env::var(env_name).ok(),
+            None => vars.get(name).cloned(),
+        };
+        match value {
+            Some(value) => ret.push_str(&value),
+            None => {
+                undefined.push(name.to_string());
+                ret.push_str(placeholder);
+            }
+        }
+        rest = &rest[start + len + 4..];
+    }
+    ret.push_str(rest);
+    ret
+}
+
+fn template_path(name: &str) -> anyhow::Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        bail!("Invalid template name: {}", name);
+    }
+    let dir = config_dir().context("Unable to decide the config directory")?;
+    Ok(dir.join("templates").join(format!("{}.toml", name)))
+}
+
+pub fn load(name: &str) -> anyhow::Result<Template> {
+    let path = template_path(name)?;
+    let raw = fs::read_to_string(&path)
+        .with_context(|| format!("Unable to read the template {}", path.display()))?;
+    toml::from_str(&raw).with_context(|| format!("Invalid template {}", path.display()))
+}
+
+pub fn save(name: &str, template: &Template) -> anyhow::Result<PathBuf> {
+    let path = template_path(name)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, toml::to_string(template)?)
+        .with_context(|| format!("Unable to write {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use clap::Parser;
+
+    use super::{substitute, Template};
+    use crate::Args;
+
+    #[test]
+    fn toml_round_trip() {
+        let template = Template {
+            url: Some("https://example.com/{{stage}}".to_string()),
+            method: Some("POST".to_string()),
+            headers: vec!["content-type: application/json".to_string()],
+            body: Some("{}".to_string()),
+            service: Some("execute-api".to_string()),
+            region: None,
+        };
+        let raw = toml::to_string(&template).unwrap();
+        assert_eq!(toml::from_str::<Template>(&raw).unwrap(), template);
+    }
+
+    #[test]
+    fn substitute_placeholders() {
+        let vars = HashMap::from([("stage".to_string(), "prod".to_string())]);
+        let mut undefined = vec![];
+        std::env::set_var("AWSCURL_TEMPLATE_TEST", "42");
+        assert_eq!(
+            substitute(
+                "https://example.com/{{stage}}/{{ env.AWSCURL_TEMPLATE_TEST }}/{{id}}",
+                &vars,
+                &mut undefined
+            ),
+            "https://example.com/prod/42/{{id}}"
+        );
+        assert_eq!(undefined, ["id"]);
+    }
+
+    #[test]
+    fn command_line_wins() {
+        let template = Template {
+            url: Some("https://example.com/{{stage}}".to_string()),
+            method: Some("POST".to_string()),
+            headers: vec!["x-stage: {{stage}}".to_string()],
+            body: Some(r#"{"id": {{id}}}"#.to_string()),
+            service: Some("lambda".to_string()),
+            region: Some("us-east-1".to_string()),
+        };
+        let mut args = Args::parse_from(["awscurl", "@t", "-X", "PUT", "-H", "x-extra: 1"]);
+        let vars = HashMap::from([
+            ("stage".to_string(), "prod".to_string()),
+            ("id".to_string(), "42".to_string()),
+        ]);
+        template.apply(&mut args, &vars).unwrap();
+        assert_eq!(args.url.as_deref(), Some("https://example.com/prod"));
+        assert_eq!(args.method.as_deref(), Some("PUT"));
+        assert_eq!(args.header, ["x-stage: prod", "x-extra: 1"]);
+        assert_eq!(args.data, [r#"{"id": 42}"#]);
+        assert_eq!(args.service.as_deref(), Some("lambda"));
+    }
+
+    #[test]
+    fn undefined_variables() {
+        let template = Template {
+            url: Some("https://example.com/{{stage}}/{{id}}".to_string()),
+            ..Default::default()
+        };
+        let mut args = Args::parse_from(["awscurl", "@t"]);
+        let err = template.apply(&mut args, &HashMap::new()).unwrap_err();
+        assert_eq!(err.to_string(), "Undefined template variables: stage, id");
+    }
+}
@@ -0,0 +1,246 @@
+//! TLS versions and cipher suites (`--tlsv1.2`, `--tls-max`, `--ciphers`)
+//!
+//! rustls supports only TLS 1.2 and 1.3, so the default native TLS backend is
+//! kept when TLS 1.0 or 1.1 is allowed. The cipher suites can be chosen only
+//! with rustls.
+
+use std::sync::Arc;
+
+use crate::{client, Args};
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use rustls::{
+    crypto::ring, pki_types::ServerName, ClientConfig, ClientConnection, RootCertStore,
+    SupportedProtocolVersion,
+};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum TlsVersion {
+    #[value(name = "1.0")]
+    V1_0,
+    #[value(name = "1.1")]
+    V1_1,
+    #[value(name = "1.2")]
+    V1_2,
+    #[value(name = "1.3")]
+    V1_3,
+}
+
+impl TlsVersion {
+    fn to_reqwest(self) -> reqwest::tls::Version {
+        match self {
+            TlsVersion::V1_0 => reqwest::tls::Version::TLS_1_0,
+            TlsVersion::V1_1 => reqwest::tls::Version::TLS_1_1,
+            TlsVersion::V1_2 => reqwest::tls::Version::TLS_1_2,
+            TlsVersion::V1_3 => reqwest::tls::Version::TLS_1_3,
+        }
+    }
+}
+
+impl Args {
+    /// The minimum TLS version given by --tlsv1.0 ... --tlsv1.3.
+    pub fn tls_min(&self) -> Option<TlsVersion> {
+        [
+            (self.tlsv1_3, TlsVersion::V1_3),
+            (self.tlsv1_2, TlsVersion::V1_2),
+            (self.tlsv1_1, TlsVersion::V1_1),
+            (self.tlsv1_0, TlsVersion::V1_0),
+        ]
+        .into_iter()
+        .find_map(|(given, version)| given.then_some(version))
+    }
+}
+
+/// Whether TLS 1.0 or 1.1 has to be offered, which is possible only with native TLS.
+fn needs_native(args: &Args) -> bool {
+    let legacy = |version: Option<TlsVersion>| version.is_some_and(|v| v <= TlsVersion::V1_1);
+    args.ciphers.is_empty() && (legacy(args.tls_min()) || legacy(args.tls_max))
+}
+
+pub fn configure(
+    mut builder: reqwest::ClientBuilder,
+    args: &Args,
+) -> anyhow::Result<reqwest::ClientBuilder> {
+    if needs_native(args) {
+        if let Some(min) = args.tls_min() {
+            builder = builder.min_tls_version(min.to_reqwest());
+        }
+        // TLS 1.3 is the default maximum, which native TLS can not be set to.
+        if let Some(max) = args.tls_max.filter(|max| *max < TlsVersion::V1_3) {
+            builder = builder.max_tls_version(max.to_reqwest());
+        }
+        return Ok(builder);
+    }
+    match rustls_config(args)? {
+        Some(config) => Ok(builder.use_preconfigured_tls(config)),
+        None => Ok(builder),
+    }
+}
+
+/// The rustls configuration for the TLS options, or None if no option is given.
+fn rustls_config(args: &Args) -> anyhow::Result<Option<ClientConfig>> {
+    if args.tls_min().is_none() && args.tls_max.is_none() && args.ciphers.is_empty() {
+        return Ok(None);
+    }
+    let mut provider = ring::default_provider();
+    if !args.ciphers.is_empty() {
+        let mut suites = Vec::new();
+        for name in &args.ciphers {
+            let suite = provider
+                .cipher_suites
+                .iter()
+                .find(|suite| format!("{:?}", suite.suite()).eq_ignore_ascii_case(name))
+                .with_context(|| format!("Unknown cipher suite: {}", name))?;
+            suites.push(*suite);
+        }
+        provider.cipher_suites = suites;
+    }
+
+    let min = args.tls_min().unwrap_or(TlsVersion::V1_2);
+    let max = args.tls_max.unwrap_or(TlsVersion::V1_3);
+    let versions: Vec<&'static SupportedProtocolVersion> = [
+        (TlsVersion::V1_2, &rustls::version::TLS12),
+        (TlsVersion::V1_3, &rustls::version::TLS13),
+    ]
+    .into_iter()
+    .filter(|(version, _)| (min..=max).contains(version))
+    .map(|(_, version)| version)
+    .collect();
+    if versions.is_empty() {
+        bail!("--ciphers supports only TLS 1.2 and 1.3");
+    }
+
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = ClientConfig::builder_with_provider(Arc::new(provider))
+        .with_protocol_versions(&versions)
+        .context("Invalid combination of the TLS versions and the cipher suites")?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Some(config))
+}
+
+/// The rustls configuration of the connections which awscurl makes itself
+/// (`--preserve-header-case`), which offer only HTTP/1.1.
+pub fn http1_config(args: &Args) -> anyhow::Result<ClientConfig> {
+    let mut config = match rustls_config(args)? {
+        Some(config) => config,
+        None => ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            })
+            .with_no_client_auth(),
+    };
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// Describe the TLS parameters for --verbose.
+///
+/// The connection of the request is hidden in the HTTP client, so the
+/// negotiated parameters are taken from a separate handshake with the same
+/// configuration, over a connection made as the client makes it.
+pub async fn describe(args: &Args, url: &reqwest::Url) -> Option<String> {
+    if url.scheme() != "https" || args.unix_socket.is_some() {
+        return None;
+    }
+    if needs_native(args) {
+        let range = |version: Option<TlsVersion>, default: &str| {
+            version.map_or(default.to_string(), |v| {
+                v.to_possible_value().unwrap().get_name().to_string()
+            })
+        };
+        return Some(format!(
+            "TLS {} - {} with native TLS (the negotiated parameters are not available)",
+            range(args.tls_min(), "1.0"),
+            range(args.tls_max, "1.3")
+        ));
+    }
+    let config = rustls_config(args).ok()??;
+    Some(match handshake(args, config, url).await {
+        Ok(description) => description,
+        Err(e) => format!("TLS handshake failed: {:#}", e),
+    })
+}
+
+async fn handshake(
+    args: &Args,
+    config: ClientConfig,
+    url: &reqwest::Url,
+) -> anyhow::Result<String> {
+    let host = url.host_str().context("URL has no host")?;
+    let port = url.port_or_known_default().unwrap_or(443);
+    let stream = client::connect(args, host, port).await?;
+    let server_name = ServerName::try_from(
+        host.trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string(),
+    )?;
+    let stream = tokio_rustls::TlsConnector::from(Arc::new(config))
+        .connect(server_name, stream)
+        .await?;
+    let (_, connection) = stream.get_ref();
+    Ok(negotiated(connection))
+}
+
+/// The negotiated version and cipher suite of CONNECTION (Ex. "TLS TLSv1_3
+/// TLS13_AES_256_GCM_SHA384").
+pub fn negotiated(connection: &ClientConnection) -> String {
+    let version = connection
+        .protocol_version()
+        .map_or("unknown".to_string(), |version| format!("{:?}", version));
+    let suite = connection
+        .negotiated_cipher_suite()
+        .map_or("unknown".to_string(), |suite| {
+            format!("{:?}", suite.suite())
+        });
+    format!("TLS {} {}", version, suite)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::{needs_native, rustls_config, TlsVersion};
+    use crate::Args;
+
+    fn args(options: &[&str]) -> Args {
+        Args::parse_from(["awscurl", "https://example.com"].iter().chain(options))
+    }
+
+    #[test]
+    fn choose_backend() {
+        assert!(needs_native(&args(&["--tls-max", "1.1"])));
+        assert!(needs_native(&args(&["--tlsv1.0"])));
+        assert!(!needs_native(&args(&["--tlsv1.2"])));
+        assert_eq!(args(&["--tlsv1.2"]).tls_min(), Some(TlsVersion::V1_2));
+        assert!(rustls_config(&args(&[])).unwrap().is_none());
+    }
+
+    #[test]
+    fn select_ciphers() {
+        let config = rustls_config(&args(&["--ciphers", "tls13_aes_128_gcm_sha256"]))
+            .unwrap()
+            .unwrap();
+        let suites = config
+            .crypto_provider()
+            .cipher_suites
+            .iter()
+            .map(|suite| format!("{:?}", suite.suite()))
+            .collect::<Vec<_>>();
+        assert_eq!(suites, ["TLS13_AES_128_GCM_SHA256"]);
+
+        let err = rustls_config(&args(&["--ciphers", "RC4-MD5"])).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown cipher suite: RC4-MD5");
+        assert!(rustls_config(&args(&[
+            "--ciphers",
+            "TLS13_AES_128_GCM_SHA256",
+            "--tlsv1.2",
+            "--tls-max",
+            "1.2"
+        ]))
+        .is_err());
+    }
+}
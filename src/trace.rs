@@ -0,0 +1,196 @@
+//! Tracing headers which correlate the request with backend traces
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::bail;
+
+pub const XRAY_HEADER: &str = "X-Amzn-Trace-Id";
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// W3C trace context of the request (<https://www.w3.org/TR/trace-context/>)
+pub struct TraceParent {
+    pub trace_id: String,
+    /// The span of this request, which is sent as the parent-id.
+    pub span_id: String,
+    /// The span given by the user to continue the trace.
+    #[cfg_attr(not(feature = "otel"), allow(dead_code))]
+    pub parent_span_id: Option<String>,
+    pub flags: String,
+}
+
+impl TraceParent {
+    pub fn generate() -> Self {
+        Self {
+            trace_id: uuid::Uuid::new_v4().simple().to_string(),
+            span_id: generate_span_id(),
+            parent_span_id: None,
+            flags: "01".to_string(),
+        }
+    }
+
+    /// Continue the trace in the given traceparent header value with a new span.
+    pub fn parse(raw: &str) -> anyhow::Result<Self> {
+        let raw = raw.trim();
+        let [version, trace_id, parent_id, flags] = raw.split('-').collect::<Vec<_>>()[..] else {
+            bail!("Invalid traceparent: {}", raw);
+        };
+        let valid = is_hex(version, 2)
+            && version != "ff"
+            && is_hex(trace_id, 32)
+            && !is_zero(trace_id)
+            && is_hex(parent_id, 16)
+            && !is_zero(parent_id)
+            && is_hex(flags, 2);
+        if !valid {
+            bail!("Invalid traceparent: {}", raw);
+        }
+        Ok(Self {
+            trace_id: trace_id.to_ascii_lowercase(),
+            span_id: generate_span_id(),
+            parent_span_id: Some(parent_id.to_ascii_lowercase()),
+            flags: flags.to_ascii_lowercase(),
+        })
+    }
+
+    pub fn header_value(&self) -> String {
+        format!("00-{}-{}-{}", self.trace_id, self.span_id, self.flags)
+    }
+}
+
+fn generate_span_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..16].to_string()
+}
+
+fn is_zero(raw: &str) -> bool {
+    raw.bytes().all(|b| b == b'0')
+}
+
+/// Generate a new X-Ray trace header value (`Root=1-<epoch>-<random>;Sampled=1`).
+pub fn generate_xray(time: SystemTime) -> String {
+    let epoch = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let random = uuid::Uuid::new_v4().simple().to_string();
+    format!("Root=1-{:08x}-{};Sampled=1", epoch, &random[..24])
+}
+
+/// Validate the X-Ray trace header value given by the user.
+///
+/// A bare root trace id (Ex. `1-5759e988-bd862e3fe1be46a994272793`) is also accepted.
+pub fn parse_xray(raw: &str) -> anyhow::Result<String> {
+    let raw = raw.trim();
+    let value = if raw.starts_with("1-") {
+        format!("Root={};Sampled=1", raw)
+    } else {
+        raw.to_string()
+    };
+    let mut root = None;
+    for field in value.split(';') {
+        let Some((key, val)) = field.split_once('=') else {
+            bail!("Invalid X-Ray trace id: {}", raw);
+        };
+        match key {
+            "Root" => root = Some(val),
+            "Parent" if is_hex(val, 16) => {}
+            "Sampled" if matches!(val, "0" | "1" | "?") => {}
+            "Parent" | "Sampled" => bail!("Invalid {} in X-Ray trace id: {}", key, raw),
+            _ => {}
+        }
+    }
+    let Some(root) = root else {
+        bail!("X-Ray trace id must contain Root: {}", raw);
+    };
+    let valid_root = match root.split('-').collect::<Vec<_>>()[..] {
+        ["1", epoch, id] => is_hex(epoch, 8) && is_hex(id, 24),
+        _ => false,
+    };
+    if !valid_root {
+        bail!("Invalid Root in X-Ray trace id: {}", raw);
+    }
+    Ok(value)
+}
+
+/// The root trace id, which is what the X-Ray console searches for.
+pub fn xray_root(value: &str) -> &str {
+    value
+        .split(';')
+        .find_map(|field| field.strip_prefix("Root="))
+        .unwrap_or(value)
+}
+
+fn is_hex(raw: &str, len: usize) -> bool {
+    raw.len() == len && raw.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{generate_xray, parse_xray, xray_root, TraceParent};
+
+    #[test]
+    fn generated_traceparent_is_valid() {
+        let header = TraceParent::generate().header_value();
+        let parsed = TraceParent::parse(&header).unwrap();
+        assert_eq!(parsed.header_value().len(), header.len());
+        assert_eq!(&parsed.header_value()[..36], &header[..36]);
+    }
+
+    #[test]
+    fn continue_given_traceparent() {
+        let parsed =
+            TraceParent::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        assert_eq!(parsed.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(parsed.parent_span_id.as_deref(), Some("00f067aa0ba902b7"));
+        assert_ne!(parsed.span_id, "00f067aa0ba902b7");
+        assert!(parsed
+            .header_value()
+            .starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+    }
+
+    #[test]
+    fn reject_invalid_traceparent() {
+        for raw in [
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4bf92f3577b34da6-00f067aa0ba902b7-01",
+        ] {
+            assert!(TraceParent::parse(raw).is_err(), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn generated_xray_is_valid() {
+        let value = generate_xray(UNIX_EPOCH + Duration::from_secs(1369353600));
+        assert!(value.starts_with("Root=1-519ead80-"));
+        assert!(value.ends_with(";Sampled=1"));
+        assert_eq!(parse_xray(&value).unwrap(), value);
+    }
+
+    #[test]
+    fn parse_given_xray() {
+        assert_eq!(
+            parse_xray("1-5759e988-bd862e3fe1be46a994272793").unwrap(),
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Sampled=1"
+        );
+        let value = "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1";
+        assert_eq!(parse_xray(value).unwrap(), value);
+        assert_eq!(xray_root(value), "1-5759e988-bd862e3fe1be46a994272793");
+    }
+
+    #[test]
+    fn reject_invalid_xray() {
+        for raw in [
+            "1-5759e988-bd862e3f",
+            "Root=2-5759e988-bd862e3fe1be46a994272793",
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=xyz",
+            "Parent=53995c3f42cd8ad8",
+            "hello",
+        ] {
+            assert!(parse_xray(raw).is_err(), "{}", raw);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! HTTP over a Unix domain socket (`--unix-socket`)
+//!
+//! reqwest can not connect to a Unix socket, so the signed request is sent with
+//! a hyper HTTP/1.1 connection on the socket. Only the connection is different:
+//! the host in the URL is still sent as the Host header and used in the signature.
+
+use std::path::Path;
+
+use anyhow::Context;
+use bytes::Bytes;
+use http::header::HOST;
+use hyper_util::rt::TokioIo;
+use tokio::net::UnixStream;
+
+use crate::logging::{self, Level};
+
+pub async fn execute(socket: &Path, req: reqwest::Request) -> anyhow::Result<reqwest::Response> {
+    send(socket, req, false).await
+}
+
+/// Send REQ to the proxy on the socket, with the whole URL in the request line
+/// (Ex. the session of `--session`).
+pub async fn forward(socket: &Path, req: reqwest::Request) -> anyhow::Result<reqwest::Response> {
+    send(socket, req, true).await
+}
+
+async fn send(
+    socket: &Path,
+    mut req: reqwest::Request,
+    absolute: bool,
+) -> anyhow::Result<reqwest::Response> {
+    let url = req.url().clone();
+    let host = url.host_str().context("URL has no host")?;
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    };
+    let target = match url.query() {
+        _ if absolute => url.to_string(),
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let mut builder = http::Request::builder()
+        .method(req.method())
+        .uri(target)
+        .header(HOST, host);
+    for (key, value) in req.headers() {
+        builder = builder.header(key, value);
+    }
+    // A streamed body (--upload-file -) is sent with chunked transfer encoding.
+    let body = req.body_mut().take().unwrap_or_else(|| Bytes::new().into());
+    let req = builder.body(body)?;
+
+    let stream = UnixStream::connect(socket)
+        .await
+        .with_context(|| format!("Unable to connect to {}", socket.display()))?;
+    let (mut sender, connection) =
+        hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            logging::message(Level::Warn, format_args!("Connection error: {}", e));
+        }
+    });
+    let res = sender.send_request(req).await?;
+    Ok(res.map(reqwest::Body::wrap).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::UnixListener,
+    };
+
+    use super::execute;
+
+    #[tokio::test]
+    async fn echo_authorization() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let len = stream.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..len]).to_string();
+            let authorization = request
+                .lines()
+                .find_map(|line| line.strip_prefix("authorization: "))
+                .unwrap_or_default()
+                .to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
+                authorization.len(),
+                authorization
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            request
+        });
+
+        let req = reqwest::Client::new()
+            .get("http://example.com/path?a=1")
+            .header("authorization", "AWS4-HMAC-SHA256 dummy")
+            .build()
+            .unwrap();
+        let res = execute(&socket, req).await.unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.text().await.unwrap(), "AWS4-HMAC-SHA256 dummy");
+        let request = server.await.unwrap();
+        assert!(request.starts_with("GET /path?a=1 HTTP/1.1\r\n"));
+        assert!(request
+            .to_ascii_lowercase()
+            .contains("host: example.com\r\n"));
+    }
+}
@@ -0,0 +1,140 @@
+//! Request body given by `--upload-file` (`-T`)
+//!
+//! Unlike `--data`, the contents are sent as they are. The payload hash is a part
+//! of the signature, so the body is read up front, except with
+//! `--unsigned-payload`: a file is streamed with its Content-Length, and stdin
+//! with "transfer-encoding: chunked".
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use futures_util::{stream, TryStreamExt};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncRead;
+use tokio_util::io::ReaderStream;
+
+use crate::body_size;
+
+/// The value of x-amz-content-sha256 when the payload is not signed.
+pub const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+pub enum Upload {
+    Bytes(Vec<u8>),
+    /// Opened again for each request, which is sent with the length of the file
+    File {
+        path: PathBuf,
+        len: u64,
+    },
+    /// Sent with chunked transfer encoding because the length is unknown
+    Stream(Box<dyn AsyncRead + Send + Unpin>),
+}
+
+impl Upload {
+    /// Open FILE ("-" for stdin), which is streamed only if the payload is unsigned.
+    /// stdin read up front fails as soon as it exceeds LIMIT.
+    pub fn open(path: &Path, unsigned_payload: bool, limit: Option<u64>) -> anyhow::Result<Self> {
+        if path != Path::new("-") && unsigned_payload {
+            let metadata =
+                fs::metadata(path).with_context(|| format!("Unable to read {}", path.display()))?;
+            return Ok(Upload::File {
+                path: path.to_path_buf(),
+                len: metadata.len(),
+            });
+        }
+        if path != Path::new("-") {
+            let bytes =
+                fs::read(path).with_context(|| format!("Unable to read {}", path.display()))?;
+            return Ok(Upload::Bytes(bytes));
+        }
+        if unsigned_payload {
+            return Ok(Upload::Stream(Box::new(tokio::io::stdin())));
+        }
+        Ok(Upload::Bytes(body_size::read_stdin(limit)?))
+    }
+
+    /// The hex encoded SHA256 of the body, which is unknown for a stream.
+    pub fn sha256(&self) -> Option<String> {
+        match self {
+            Upload::Bytes(bytes) => Some(hex::encode(Sha256::digest(bytes))),
+            Upload::File { .. } | Upload::Stream(_) => None,
+        }
+    }
+
+    pub fn into_body(self) -> reqwest::Body {
+        match self {
+            Upload::Bytes(bytes) => bytes.into(),
+            Upload::File { path, .. } => file_body(path),
+            Upload::Stream(reader) => reqwest::Body::wrap_stream(ReaderStream::new(reader)),
+        }
+    }
+}
+
+/// The body streamed from the file at PATH, which is opened when the body is
+/// sent, so that the failure to open it fails the request.
+pub fn file_body(path: PathBuf) -> reqwest::Body {
+    reqwest::Body::wrap_stream(
+        stream::once(tokio::fs::File::open(path))
+            .map_ok(ReaderStream::new)
+            .try_flatten(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::Upload;
+
+    #[test]
+    fn hash_bytes() {
+        let upload = Upload::Bytes(b"hello".to_vec());
+        assert_eq!(
+            upload.sha256().unwrap(),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert!(Upload::Stream(Box::new(&b""[..])).sha256().is_none());
+    }
+
+    #[tokio::test]
+    async fn send_chunked_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = vec![0; 4096];
+            // Read until the last chunk
+            while !request.ends_with(b"0\r\n\r\n") {
+                let len = stream.read(&mut buf).await.unwrap();
+                assert!(len > 0, "connection closed before the last chunk");
+                request.extend_from_slice(&buf[..len]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let (mut writer, reader) = tokio::io::duplex(64);
+        tokio::spawn(async move {
+            writer.write_all(b"streamed body").await.unwrap();
+        });
+        let res = reqwest::Client::new()
+            .put(&url)
+            .body(Upload::Stream(Box::new(reader)).into_body())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        let request = server.await.unwrap();
+        assert!(request.contains("transfer-encoding: chunked\r\n"));
+        assert!(request.ends_with("\r\n\r\nD\r\nstreamed body\r\n0\r\n\r\n"));
+    }
+}
@@ -0,0 +1,164 @@
+//! Variables of `--variable` and their expansion in `--expand-url`,
+//! `--expand-data` and `--expand-header`, which are same as curl 8.4
+//!
+//! "{{name}}" is replaced with the value of the variable, and the functions
+//! after the name are applied in order (Ex. "{{name:trim:url}}"). "\{{" is left
+//! as "{{". The expansion is done before signing, so that the signature covers
+//! the expanded values.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use percent_encoding::utf8_percent_encode;
+
+use crate::data;
+
+/// The variables defined by `--variable`, which are shared by the requests
+/// after the definition.
+#[derive(Debug, Default)]
+pub struct Variables(HashMap<String, String>);
+
+impl Variables {
+    /// Define a variable with `--variable` RAW, which is one of "name=content",
+    /// "name@FILE" ("-" for stdin), and "%NAME" for the environment variable,
+    /// optionally followed by "=default" or "@FILE" used when it is not set.
+    pub fn define(&mut self, raw: &str) -> anyhow::Result<()> {
+        let (env, raw) = match raw.strip_prefix('%') {
+            Some(raw) => (true, raw),
+            None => (false, raw),
+        };
+        let (name, default) = match raw.find(['=', '@']) {
+            Some(i) => (&raw[..i], Some(&raw[i..])),
+            None => (raw, None),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            bail!("Invalid variable name in --variable {}", raw);
+        }
+        let value = match std::env::var(name).ok().filter(|_| env) {
+            Some(value) => value,
+            None => match default {
+                Some(content) if content.starts_with('=') => content[1..].to_string(),
+                Some(path) => data::read_file(&path[1..])?,
+                None if env => bail!("The environment variable {} is not set", name),
+                None => bail!("--variable {} has no value. Use {}=VALUE", name, name),
+            },
+        };
+        self.0.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Replace the "{{name}}" placeholders in TEXT. An undefined variable or an
+    /// unknown function is an error.
+    pub fn expand(&self, text: &str) -> anyhow::Result<String> {
+        let mut ret = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            if rest[..start].ends_with('\\') {
+                ret.push_str(&rest[..start - 1]);
+                ret.push_str("{{");
+                rest = &rest[start + 2..];
+                continue;
+            }
+            let len = rest[start + 2..]
+                .find("}}")
+                .with_context(|| format!("Unterminated {{{{ in {}", text))?;
+            ret.push_str(&rest[..start]);
+            let mut functions = rest[start + 2..start + 2 + len].split(':');
+            let name = functions.next().unwrap_or_default();
+            let mut value = self
+                .0
+                .get(name)
+                .with_context(|| format!("Undefined variable: {}", name))?
+                .clone();
+            for function in functions {
+                value = apply(function, &value)?;
+            }
+            ret.push_str(&value);
+            rest = &rest[start + len + 4..];
+        }
+        ret.push_str(rest);
+        Ok(ret)
+    }
+}
+
+/// Apply the expansion FUNCTION to VALUE.
+fn apply(function: &str, value: &str) -> anyhow::Result<String> {
+    Ok(match function {
+        "trim" => value.trim().to_string(),
+        // Without the quotes, so that it can be put in a JSON string.
+        "json" => {
+            let quoted = serde_json::to_string(value)?;
+            quoted[1..quoted.len() - 1].to_string()
+        }
+        "url" => utf8_percent_encode(value, data::URLENCODE).to_string(),
+        "b64" => BASE64_STANDARD.encode(value),
+        _ => bail!(
+            "Unknown function {:?} in {{{{...}}}}. Use trim, json, url or b64",
+            function
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Variables;
+
+    #[test]
+    fn define() {
+        let mut variables = Variables::default();
+        variables.define("key=reports/today.json").unwrap();
+        variables.define("empty=").unwrap();
+        std::env::set_var("AWSCURL_VARIABLE_TEST", "bucket");
+        variables.define("%AWSCURL_VARIABLE_TEST").unwrap();
+        variables
+            .define("%AWSCURL_VARIABLE_UNSET=fallback")
+            .unwrap();
+        assert_eq!(variables.0["key"], "reports/today.json");
+        assert_eq!(variables.0["empty"], "");
+        assert_eq!(variables.0["AWSCURL_VARIABLE_TEST"], "bucket");
+        assert_eq!(variables.0["AWSCURL_VARIABLE_UNSET"], "fallback");
+
+        assert_eq!(
+            variables
+                .define("%AWSCURL_VARIABLE_UNSET")
+                .unwrap_err()
+                .to_string(),
+            "The environment variable AWSCURL_VARIABLE_UNSET is not set"
+        );
+        assert!(variables.define("key").is_err());
+        assert!(variables.define("a-b=c").is_err());
+        assert!(variables.define("name@/nonexistent").is_err());
+    }
+
+    #[test]
+    fn expand() {
+        let mut variables = Variables::default();
+        variables.define("key=reports/today.json").unwrap();
+        variables.define("text= \"a b\"\n").unwrap();
+        assert_eq!(
+            variables
+                .expand("https://bucket.s3.amazonaws.com/{{key}}")
+                .unwrap(),
+            "https://bucket.s3.amazonaws.com/reports/today.json"
+        );
+        assert_eq!(
+            variables.expand("{{key:url}} {{text:trim:json}}").unwrap(),
+            r#"reports%2Ftoday.json \"a b\""#
+        );
+        assert_eq!(
+            variables.expand("{{key:b64}}").unwrap(),
+            "cmVwb3J0cy90b2RheS5qc29u"
+        );
+        assert_eq!(variables.expand(r"\{{key}}").unwrap(), "{{key}}");
+        assert_eq!(
+            variables.expand("{{bucket}}").unwrap_err().to_string(),
+            "Undefined variable: bucket"
+        );
+        assert_eq!(
+            variables.expand("{{key:upper}}").unwrap_err().to_string(),
+            "Unknown function \"upper\" in {{...}}. Use trim, json, url or b64"
+        );
+        assert!(variables.expand("{{key").is_err());
+    }
+}
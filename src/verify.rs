@@ -0,0 +1,99 @@
+//! Checking the uploaded object with a HEAD request (`--upload-verify`)
+//!
+//! The HEAD request is signed again with the current date, and its
+//! Content-Length and checksums are compared with the body which was sent. The
+//! file streamed with --unsigned-payload is read again to compare.
+
+use std::{
+    fs,
+    io::{self, Read},
+};
+
+use anyhow::{bail, Context};
+use http::header::CONTENT_LENGTH;
+
+use crate::{
+    checksum, logging, print_request_verbose, print_response_verbose, retry, upload::Upload,
+    AwsCurlParam,
+};
+
+/// The headers of the upload which are needed to read the object again (SSE-C).
+const KEPT_HEADER_PREFIX: &str = "x-amz-server-side-encryption-customer-";
+
+/// Send HEAD to the URL of the uploaded PARAM and compare the object with the
+/// body which was sent.
+pub async fn run(mut param: AwsCurlParam, client: &reqwest::Client) -> anyhow::Result<()> {
+    let (len, body): (u64, Box<dyn Read>) = match param.upload.take() {
+        Some(Upload::Bytes(bytes)) => (bytes.len() as u64, Box::new(io::Cursor::new(bytes))),
+        Some(Upload::File { path, len }) => {
+            let file = fs::File::open(&path)
+                .with_context(|| format!("Unable to read {}", path.display()))?;
+            (len, Box::new(file))
+        }
+        Some(Upload::Stream(_)) => {
+            logging::warning("--upload-verify is skipped for the body streamed from stdin");
+            return Ok(());
+        }
+        None => {
+            let bytes = param.args.body().unwrap_or_default().into_bytes();
+            (bytes.len() as u64, Box::new(io::Cursor::new(bytes)))
+        }
+    };
+    let s3 = param.service() == "s3";
+    let args = &mut param.args;
+    args.method = Some("HEAD".to_string());
+    args.data.clear();
+    args.data_hex = None;
+    args.data_base64 = None;
+    args.upload_file = None;
+    args.unsigned_payload = false;
+    args.header.retain(|header| {
+        header.split_once(':').is_some_and(|(key, _)| {
+            key.trim()
+                .to_ascii_lowercase()
+                .starts_with(KEPT_HEADER_PREFIX)
+        })
+    });
+    if s3 {
+        // S3 returns the checksums only when they are asked for.
+        args.header.push("x-amz-checksum-mode: ENABLED".to_string());
+    }
+
+    let req = param.sendable(param.build_request().await?)?;
+    if param.args.verbose {
+        print_request_verbose(&req, &param.args);
+    }
+    let res = retry::send(&param, client, req).await?;
+    if param.args.verbose {
+        print_response_verbose(&res, &param.args);
+    }
+    if !res.status().is_success() {
+        bail!("Upload verification failed: HEAD returned {}", res.status());
+    }
+    let headers = res.headers();
+    let length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok()?.parse::<u64>().ok())
+        .context("Upload verification failed: HEAD returned no Content-Length")?;
+    if length != len {
+        bail!(
+            "Upload verification failed: Content-Length mismatch: expected {}, actual {}",
+            len,
+            length
+        );
+    }
+    let verification =
+        checksum::verify_read(headers, body, s3).context("Upload verification failed")?;
+    if param.args.verbose {
+        let mut checks = vec!["content-length"];
+        checks.extend(&verification.verified);
+        logging::note(format_args!(
+            "Verified the upload with {}",
+            checks.join(", ")
+        ));
+        for reason in &verification.skipped {
+            logging::note(format_args!("Not verified: {}", reason));
+        }
+    }
+    Ok(())
+}